@@ -0,0 +1,248 @@
+use crate::instructions::*;
+
+/// Value of an operand, as stored in an `Instruction` field
+trait Field: Copy {
+    fn from_u16(value: u16) -> Self;
+    fn to_u16(self) -> u16;
+}
+
+impl Field for u8 {
+    fn from_u16(value: u16) -> Self {
+        (value & 0xFF) as u8
+    }
+
+    fn to_u16(self) -> u16 {
+        self as u16
+    }
+}
+
+impl Field for u16 {
+    fn from_u16(value: u16) -> Self {
+        value
+    }
+
+    fn to_u16(self) -> u16 {
+        self
+    }
+}
+
+macro_rules! instructions {
+    ($($name:ident $(($($arg:ident: $ty:ty),*))? = $code:ident,)*) => {
+        /// Typed representation of one instruction and its operands.
+        /// Registers are stored as their id in `arch::registers`
+        #[derive(Clone, Copy, Debug, PartialEq, Eq)]
+        pub enum Instruction {
+            $($name $(($($ty),*))?,)*
+        }
+
+        impl Instruction {
+            /// Opcode used to encode the instruction
+            pub fn opcode(&self) -> u8 {
+                match self {
+                    $(Instruction::$name { .. } => $code,)*
+                }
+            }
+
+            /// Operands in encoding order, widened to 16 bits
+            pub fn operands(&self) -> Vec<u16> {
+                match *self {
+                    $(Instruction::$name $(($($arg),*))? => vec![$($($arg.to_u16()),*)?],)*
+                }
+            }
+
+            /// Build the instruction from its opcode and its operands.
+            /// `values` must hold one value per operand of the opcode
+            fn from_parts(code: u8, values: &[u16]) -> Option<Self> {
+                #[allow(unused_variables, unused_mut)]
+                let mut values = values.iter().copied();
+
+                match code {
+                    $($code => Some(Instruction::$name $(($(<$ty>::from_u16(values.next()?)),*))?),)*
+                    _ => None,
+                }
+            }
+        }
+    };
+}
+
+instructions! {
+    MovLitReg(lit: u16, reg: u8)                = MOV_LIT_REG,
+    MovLitMem8(lit: u16, mem: u16)              = MOV_LIT_MEM8,
+    MovLitMem16(lit: u16, mem: u16)             = MOV_LIT_MEM16,
+    MovRegReg(from: u8, to: u8)                 = MOV_REG_REG,
+    MovRegMem(reg: u8, mem: u16)                = MOV_REG_MEM,
+    MovMemReg(mem: u16, reg: u8)                = MOV_MEM_REG,
+    MovMemMem8(from: u16, to: u16)              = MOV_MEM_MEM_8,
+    MovMemMem16(from: u16, to: u16)             = MOV_MEM_MEM_16,
+    MovPtrRegReg(ptr: u8, reg: u8)              = MOV_PTRREG_REG,
+    MovRegPtrReg(reg: u8, ptr: u8)              = MOV_REG_PTRREG,
+    MovLitOffReg(lit: u16, off: u8, reg: u8)    = MOV_LITOFF_REG,
+    MovRegLitOff(reg: u8, lit: u16, off: u8)    = MOV_REG_LITOFF,
+
+    AddRegReg(r1: u8, r2: u8)                   = ADD_REG_REG,
+    AddRegLit(reg: u8, lit: u16)                = ADD_REG_LIT,
+    SubRegLit(reg: u8, lit: u16)                = SUB_REG_LIT,
+    SubLitReg(lit: u16, reg: u8)                = SUB_LIT_REG,
+    SubRegReg(r1: u8, r2: u8)                   = SUB_REG_REG,
+    MulRegReg(r1: u8, r2: u8)                   = MUL_REG_REG,
+    MulRegLit(reg: u8, lit: u16)                = MUL_REG_LIT,
+    CmpRegReg(r1: u8, r2: u8)                   = CMP_REG_REG,
+    CmpRegLit(reg: u8, lit: u16)                = CMP_REG_LIT,
+    IncReg(reg: u8)                             = INC_REG,
+    DecReg(reg: u8)                             = DEC_REG,
+
+    JmpLit(add: u16)                            = JMP_LIT,
+    JmpReg(reg: u8)                             = JMP_REG,
+    JeqLit(add: u16)                            = JEQ_LIT,
+    JeqReg(reg: u8)                             = JEQ_REG,
+    JneLit(add: u16)                            = JNE_LIT,
+    JneReg(reg: u8)                             = JNE_REG,
+    JgtLit(add: u16)                            = JGT_LIT,
+    JgtReg(reg: u8)                             = JGT_REG,
+    JgeLit(add: u16)                            = JGE_LIT,
+    JgeReg(reg: u8)                             = JGE_REG,
+    JltLit(add: u16)                            = JLT_LIT,
+    JltReg(reg: u8)                             = JLT_REG,
+    JleLit(add: u16)                            = JLE_LIT,
+    JleReg(reg: u8)                             = JLE_REG,
+
+    PshLit(lit: u16)                            = PSH_LIT,
+    PshReg(reg: u8)                             = PSH_REG,
+    PshMem8(mem: u16)                           = PSH_MEM8,
+    PshMem16(mem: u16)                          = PSH_MEM16,
+    PshPtrReg8(ptr: u8)                         = PSH_PTRREG8,
+    PshPtrReg16(ptr: u8)                        = PSH_PTRREG16,
+    PopReg(reg: u8)                             = POP_REG,
+    PopMem8(mem: u16)                           = POP_MEM8,
+    PopMem16(mem: u16)                          = POP_MEM16,
+    PopPtrReg8(ptr: u8)                         = POP_PTRREG8,
+    PopPtrReg16(ptr: u8)                        = POP_PTRREG16,
+    Pushf                                       = PUSHF,
+    Popf                                        = POPF,
+
+    CallLit(add: u16)                           = CALL_LIT,
+    CallReg(reg: u8)                            = CALL_REG,
+    Ret                                         = RET,
+    CallLitArgs(add: u16, args: u16)            = CALL_LIT_ARGS,
+    CallRegArgs(reg: u8, args: u16)             = CALL_REG_ARGS,
+    RetLit(args: u16)                           = RET_LIT,
+    JsrLit(add: u16)                            = JSR_LIT,
+    JsrReg(reg: u8)                             = JSR_REG,
+    Rts                                         = RTS,
+
+    LsfRegReg(r1: u8, r2: u8)                   = LSF_REG_REG,
+    LsfRegLit(reg: u8, lit: u16)                = LSF_REG_LIT,
+    RsfRegReg(r1: u8, r2: u8)                   = RSF_REG_REG,
+    RsfRegLit(reg: u8, lit: u16)                = RSF_REG_LIT,
+    AndRegReg(r1: u8, r2: u8)                   = AND_REG_REG,
+    AndRegLit(reg: u8, lit: u16)                = AND_REG_LIT,
+    OrRegReg(r1: u8, r2: u8)                    = OR_REG_REG,
+    OrRegLit(reg: u8, lit: u16)                 = OR_REG_LIT,
+    XorRegReg(r1: u8, r2: u8)                   = XOR_REG_REG,
+    XorRegLit(reg: u8, lit: u16)                = XOR_REG_LIT,
+    Not(reg: u8)                                = NOT,
+
+    IntLit(vector: u16)                         = INT_LIT,
+    Iret                                        = IRET,
+    Cli                                         = CLI,
+    Sti                                         = STI,
+    SysLit(syscall: u16)                        = SYS_LIT,
+    HostcallLit(id: u16)                        = HOSTCALL_LIT,
+
+    JaLit(add: u16)                             = JA_LIT,
+    JaReg(reg: u8)                              = JA_REG,
+    JaeLit(add: u16)                            = JAE_LIT,
+    JaeReg(reg: u8)                             = JAE_REG,
+    JbLit(add: u16)                             = JB_LIT,
+    JbReg(reg: u8)                              = JB_REG,
+    JbeLit(add: u16)                            = JBE_LIT,
+    JbeReg(reg: u8)                             = JBE_REG,
+
+    DivRegReg(r1: u8, r2: u8)                   = DIV_REG_REG,
+    DivRegLit(reg: u8, lit: u16)                = DIV_REG_LIT,
+    ModRegReg(r1: u8, r2: u8)                   = MOD_REG_REG,
+    ModRegLit(reg: u8, lit: u16)                = MOD_REG_LIT,
+    SdivRegReg(r1: u8, r2: u8)                  = SDIV_REG_REG,
+    SdivRegLit(reg: u8, lit: u16)               = SDIV_REG_LIT,
+    SmodRegReg(r1: u8, r2: u8)                  = SMOD_REG_REG,
+    SmodRegLit(reg: u8, lit: u16)               = SMOD_REG_LIT,
+
+    Brk                                         = BRK,
+    End                                         = END,
+}
+
+impl Instruction {
+    /// Description of the opcode in the `arch` table
+    pub fn info(&self) -> &'static OpcodeInfo {
+        opcode_info(self.opcode()).unwrap()
+    }
+
+    /// Number of bytes used to encode the instruction
+    pub fn size(&self) -> usize {
+        self.info().size()
+    }
+}
+
+/// Enumeration of every error that can happen while decoding bytes
+pub enum DecodeError {
+    Empty,
+    UnknownOpcode(u8),
+    Truncated { opcode: u8, expected: usize, found: usize },
+}
+
+impl std::fmt::Debug for DecodeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let error = match self {
+            DecodeError::Empty => "Expected an instruction, found nothing".to_owned(),
+            DecodeError::UnknownOpcode(code) => format!("Opcode {:#04X} doesn't exist", code),
+            DecodeError::Truncated { opcode, expected, found } => format!(
+                "Instruction {:#04X} needs {} bytes, only {} available", opcode, expected, found
+            ),
+        };
+
+        write!(f, "{}", error)
+    }
+}
+
+/// Decode the instruction at the start of `bytes`.
+/// On success, return the instruction and the number of bytes read
+pub fn decode(bytes: &[u8]) -> Result<(Instruction, usize), DecodeError> {
+    let code = *bytes.first().ok_or(DecodeError::Empty)?;
+    let info = opcode_info(code).ok_or(DecodeError::UnknownOpcode(code))?;
+
+    let size = info.size();
+    if bytes.len() < size {
+        return Err(DecodeError::Truncated { opcode: code, expected: size, found: bytes.len() });
+    }
+
+    let mut ptr = 1;
+    let mut values = Vec::with_capacity(info.operands.len());
+    for operand in info.operands {
+        let value = match operand.size() {
+            1 => bytes[ptr] as u16,
+            _ => ((bytes[ptr] as u16) << 8) + bytes[ptr + 1] as u16,
+        };
+
+        values.push(value);
+        ptr += operand.size();
+    }
+
+    let ins = Instruction::from_parts(code, &values).ok_or(DecodeError::UnknownOpcode(code))?;
+    Ok((ins, size))
+}
+
+/// Encode `ins` in the bytes expected by the VM
+pub fn encode(ins: &Instruction) -> Vec<u8> {
+    let info = ins.info();
+    let mut bytes = Vec::with_capacity(info.size());
+    bytes.push(info.code);
+
+    for (operand, value) in info.operands.iter().zip(ins.operands()) {
+        match operand.size() {
+            1 => bytes.push(value as u8),
+            _ => bytes.extend_from_slice(&[(value >> 8) as u8, (value & 0xFF) as u8]),
+        }
+    }
+
+    bytes
+}
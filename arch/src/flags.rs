@@ -0,0 +1,6 @@
+pub const ZERO     : u8 = 1; // bit0
+pub const NEGATIF  : u8 = 2; // bit1
+pub const CARRY    : u8 = 4; // bit2
+pub const INTERRUPT: u8 = 8; // bit3, interrupts are enabled
+pub const OVERFLOW : u8 = 16; // bit4, signed result doesn't fit
+pub const SUPERVISOR: u8 = 32; // bit5, supervisor mode, cleared in user mode
@@ -0,0 +1,272 @@
+//! Files written by the compiler and read by the VM.
+//!
+//! Intel HEX and Motorola S-record files only hold the loaded sections
+//! (code and data) and the entry point. Zeroed memory isn't written,
+//! the memory of the VM is already cleared when it starts.
+
+use std::convert::TryFrom;
+
+use crate::object::{Object, Section, SectionKind};
+
+/// Number of bytes written on each data record
+const RECORD_LEN: usize = 16;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Format {
+    /// object with header and sections, see `arch::object`
+    Vmo,
+    /// legacy image without address, copied at the base given to the VM
+    Raw,
+    /// Intel HEX
+    Ihex,
+    /// Motorola S-record
+    Srec,
+}
+
+impl Format {
+    /// Extension of the files written in this format
+    pub fn extension(self) -> &'static str {
+        match self {
+            Format::Vmo | Format::Raw => "vmo",
+            Format::Ihex => "hex",
+            Format::Srec => "srec",
+        }
+    }
+}
+
+impl std::str::FromStr for Format {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "vmo" => Ok(Format::Vmo),
+            "raw" => Ok(Format::Raw),
+            "ihex" | "hex" => Ok(Format::Ihex),
+            "srec" => Ok(Format::Srec),
+            _ => Err(format!("Unknown format {}, expected vmo, raw, ihex or srec", s)),
+        }
+    }
+}
+
+/// Loaded sections of an object: their address and their content
+fn segments(object: &Object) -> impl Iterator<Item = (u16, &[u8])> {
+    object.sections.iter()
+        .filter(|sec| matches!(sec.kind, SectionKind::Code | SectionKind::Data))
+        .flat_map(|sec| sec.data.chunks(RECORD_LEN)
+            .enumerate()
+            .map(move |(id, chunk)| (sec.address.wrapping_add((id * RECORD_LEN) as u16), chunk)))
+}
+
+/// Build an object from the records read in a file, each record becomes a code section.
+/// Without entry point record, execution starts on the lowest address
+fn build_object(records: Vec<(u16, Vec<u8>)>, entry: Option<u16>) -> Object {
+    let entry = entry.or_else(|| records.iter().map(|(add, _)| *add).min()).unwrap_or(0);
+    let mut object = Object::new(entry);
+
+    for (address, data) in records {
+        // records following each other are merged
+        match object.sections.last_mut() {
+            Some(sec) if sec.address as usize + sec.data.len() == address as usize => {
+                sec.data.extend_from_slice(&data);
+                sec.size = sec.data.len() as u16;
+            }
+            _ => object.sections.push(Section::new(SectionKind::Code, address, data)),
+        }
+    }
+
+    object
+}
+
+/// Decode the hexadecimal digits of a record
+fn hex_bytes(digits: &str, line: usize) -> Result<Vec<u8>, HexError> {
+    if !digits.len().is_multiple_of(2) || !digits.is_ascii() {
+        return Err(HexError::BadRecord(line));
+    }
+
+    (0..digits.len()).step_by(2)
+        .map(|id| u8::from_str_radix(&digits[id..id + 2], 16).map_err(|_| HexError::BadRecord(line)))
+        .collect()
+}
+
+/// Write the loaded sections and the entry point of `object` in Intel HEX
+pub fn to_ihex(object: &Object) -> String {
+    let record = |kind: u8, address: u16, data: &[u8]| {
+        let mut bytes = vec![data.len() as u8, (address >> 8) as u8, address as u8, kind];
+        bytes.extend_from_slice(data);
+
+        let sum = bytes.iter().fold(0u8, |sum, b| sum.wrapping_add(*b));
+        bytes.push(sum.wrapping_neg());
+
+        let digits: String = bytes.iter().map(|b| format!("{:02X}", b)).collect();
+        format!(":{}\n", digits)
+    };
+
+    let mut out = String::new();
+    for (address, data) in segments(object) {
+        out.push_str(&record(0x00, address, data));
+    }
+
+    // start linear address
+    out.push_str(&record(0x05, 0, &(object.entry as u32).to_be_bytes()));
+    out.push_str(&record(0x01, 0, &[]));
+    out
+}
+
+/// Read an Intel HEX file, addresses must fit in the 16 bits of the VM
+pub fn from_ihex(text: &str) -> Result<Object, HexError> {
+    let mut records = vec![];
+    let mut entry = None;
+    let mut upper = 0u32;
+
+    for (id, line) in text.lines().enumerate().filter(|(_, line)| !line.trim().is_empty()) {
+        let line_nb = id + 1;
+        let digits = line.trim().strip_prefix(':').ok_or(HexError::BadRecord(line_nb))?;
+        let bytes = hex_bytes(digits, line_nb)?;
+
+        if bytes.len() < 5 || bytes.len() != bytes[0] as usize + 5 {
+            return Err(HexError::BadRecord(line_nb));
+        }
+        if bytes.iter().fold(0u8, |sum, b| sum.wrapping_add(*b)) != 0 {
+            return Err(HexError::BadChecksum(line_nb));
+        }
+
+        let address = u16::from_be_bytes([bytes[1], bytes[2]]) as u32;
+        let data = &bytes[4..bytes.len() - 1];
+
+        match bytes[3] {
+            0x00 => {
+                let address = upper + address;
+                if address as usize + data.len() > 0x10000 {
+                    return Err(HexError::AddressOverflow(line_nb));
+                }
+                records.push((address as u16, data.to_vec()));
+            }
+            0x01 => return Ok(build_object(records, entry)),
+            // extended segment and linear address
+            0x02 if data.len() == 2 => upper = (u16::from_be_bytes([data[0], data[1]]) as u32) << 4,
+            0x04 if data.len() == 2 => upper = (u16::from_be_bytes([data[0], data[1]]) as u32) << 16,
+            // start segment address, CS:IP
+            0x03 if data.len() == 4 => {
+                let cs = u16::from_be_bytes([data[0], data[1]]) as u32;
+                let ip = u16::from_be_bytes([data[2], data[3]]) as u32;
+                entry = Some(u16::try_from((cs << 4) + ip).map_err(|_| HexError::AddressOverflow(line_nb))?);
+            }
+            // start linear address
+            0x05 if data.len() == 4 => {
+                let add = u32::from_be_bytes([data[0], data[1], data[2], data[3]]);
+                entry = Some(u16::try_from(add).map_err(|_| HexError::AddressOverflow(line_nb))?);
+            }
+            kind => return Err(HexError::UnsupportedRecord(line_nb, format!("{:02X}", kind))),
+        }
+    }
+
+    Err(HexError::MissingEnd)
+}
+
+/// Write the loaded sections and the entry point of `object` in Motorola S-records.
+/// `name` is written in the header record
+pub fn to_srec(object: &Object, name: &str) -> String {
+    let record = |kind: char, address: u16, data: &[u8]| {
+        let mut bytes = vec![data.len() as u8 + 3, (address >> 8) as u8, address as u8];
+        bytes.extend_from_slice(data);
+
+        let sum = bytes.iter().fold(0u8, |sum, b| sum.wrapping_add(*b));
+        bytes.push(!sum);
+
+        let digits: String = bytes.iter().map(|b| format!("{:02X}", b)).collect();
+        format!("S{}{}\n", kind, digits)
+    };
+
+    let name = name.as_bytes();
+    let mut out = record('0', 0, &name[..name.len().min(64)]);
+    let mut count = 0;
+
+    for (address, data) in segments(object) {
+        out.push_str(&record('1', address, data));
+        count += 1;
+    }
+
+    if count <= 0xFFFF {
+        out.push_str(&record('5', count as u16, &[]));
+    }
+    out.push_str(&record('9', object.entry, &[]));
+    out
+}
+
+/// Read a Motorola S-record file, addresses must fit in the 16 bits of the VM
+pub fn from_srec(text: &str) -> Result<Object, HexError> {
+    let mut records = vec![];
+
+    for (id, line) in text.lines().enumerate().filter(|(_, line)| !line.trim().is_empty()) {
+        let line_nb = id + 1;
+        let line = line.trim();
+
+        let kind = match line.strip_prefix('S').and_then(|rest| rest.chars().next()) {
+            Some(kind) if line.is_ascii() => kind,
+            _ => return Err(HexError::BadRecord(line_nb)),
+        };
+        let bytes = hex_bytes(&line[2..], line_nb)?;
+
+        if bytes.is_empty() || bytes.len() != bytes[0] as usize + 1 {
+            return Err(HexError::BadRecord(line_nb));
+        }
+        if bytes.iter().fold(0u8, |sum, b| sum.wrapping_add(*b)) != 0xFF {
+            return Err(HexError::BadChecksum(line_nb));
+        }
+
+        // size of the address field
+        let add_len = match kind {
+            '0' | '1' | '5' | '9' => 2,
+            '2' | '6' | '8' => 3,
+            '3' | '7' => 4,
+            _ => return Err(HexError::UnsupportedRecord(line_nb, format!("S{}", kind))),
+        };
+        if bytes.len() < add_len + 2 {
+            return Err(HexError::BadRecord(line_nb));
+        }
+
+        let address = bytes[1..=add_len].iter().fold(0u32, |add, b| (add << 8) + *b as u32);
+        let data = &bytes[add_len + 1..bytes.len() - 1];
+
+        match kind {
+            '1' | '2' | '3' => {
+                if address as usize + data.len() > 0x10000 {
+                    return Err(HexError::AddressOverflow(line_nb));
+                }
+                records.push((address as u16, data.to_vec()));
+            }
+            '7' | '8' | '9' => {
+                let entry = u16::try_from(address).map_err(|_| HexError::AddressOverflow(line_nb))?;
+                return Ok(build_object(records, Some(entry)));
+            }
+            // header and record count
+            _ => (),
+        }
+    }
+
+    Err(HexError::MissingEnd)
+}
+
+/// Enumeration of every error found while reading an Intel HEX or S-record file.
+/// Records are identified by their line
+pub enum HexError {
+    BadRecord(usize),
+    BadChecksum(usize),
+    UnsupportedRecord(usize, String),
+    AddressOverflow(usize),
+    MissingEnd,
+}
+
+impl std::fmt::Debug for HexError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let error = match self {
+            HexError::BadRecord(line) => format!("Malformed record on line {}", line),
+            HexError::BadChecksum(line) => format!("Wrong checksum on line {}", line),
+            HexError::UnsupportedRecord(line, kind) => format!("Record type {} on line {} is not supported", kind, line),
+            HexError::AddressOverflow(line) => format!("Address on line {} doesn't fit in 16 bits", line),
+            HexError::MissingEnd => "File ends without end of file record".to_owned(),
+        };
+
+        write!(f, "{}", error)
+    }
+}
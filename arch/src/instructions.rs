@@ -1,3 +1,10 @@
+use crate::flags::{ZERO, NEGATIF, CARRY, INTERRUPT, OVERFLOW, SUPERVISOR};
+use Operand::*;
+
+/// Version of the instruction set, raised each time opcodes are added or changed.
+/// An object built for a version can run on every VM implementing this one or a later one
+pub const ISA_VERSION: u16 = 10;
+
 pub const MOV_LIT_REG   : u8 = 0x10;
 pub const MOV_LIT_MEM8  : u8 = 0x11;
 pub const MOV_LIT_MEM16 : u8 = 0x12;
@@ -9,6 +16,7 @@ pub const MOV_MEM_MEM_16: u8 = 0x17;
 pub const MOV_PTRREG_REG: u8 = 0x18;
 pub const MOV_REG_PTRREG: u8 = 0x19;
 pub const MOV_LITOFF_REG: u8 = 0x1A;
+pub const MOV_REG_LITOFF: u8 = 0x1B; // write at lit + reg, like `*fp-2`
 
 pub const ADD_REG_REG   : u8 = 0x20;
 pub const ADD_REG_LIT   : u8 = 0x21;
@@ -28,13 +36,13 @@ pub const JEQ_LIT       : u8 = 0x32; // jump to second lit if first lit == acc
 pub const JEQ_REG       : u8 = 0x33;
 pub const JNE_LIT       : u8 = 0x34; // jump to second lit if first lit != acc
 pub const JNE_REG       : u8 = 0x35;
-pub const JGT_LIT       : u8 = 0x36; // jump to second lit if first lit >  acc
+pub const JGT_LIT       : u8 = 0x36; // jump if greater, signed comparison
 pub const JGT_REG       : u8 = 0x37;
-pub const JGE_LIT       : u8 = 0x38; // jump to second lit if first lit >= acc
+pub const JGE_LIT       : u8 = 0x38; // jump if greater or equal, signed
 pub const JGE_REG       : u8 = 0x39;
-pub const JLT_LIT       : u8 = 0x3A; // jump to second lit if first lit <  acc
+pub const JLT_LIT       : u8 = 0x3A; // jump if lower, signed
 pub const JLT_REG       : u8 = 0x3B;
-pub const JLE_LIT       : u8 = 0x3C; // jump to second lit if first lit <= acc
+pub const JLE_LIT       : u8 = 0x3C; // jump if lower or equal, signed
 pub const JLE_REG       : u8 = 0x3D;
 
 pub const PSH_LIT       : u8 = 0x40;
@@ -48,10 +56,18 @@ pub const POP_MEM8      : u8 = 0x47;
 pub const POP_MEM16     : u8 = 0x48;
 pub const POP_PTRREG8   : u8 = 0x49;
 pub const POP_PTRREG16  : u8 = 0x4A;
+pub const PUSHF         : u8 = 0x4B; // push the flags
+pub const POPF          : u8 = 0x4C;
 
 pub const CALL_LIT      : u8 = 0x50;
 pub const CALL_REG      : u8 = 0x51;
 pub const RET           : u8 = 0x52;
+pub const CALL_LIT_ARGS : u8 = 0x53; // call, recording the number of argument words
+pub const CALL_REG_ARGS : u8 = 0x54;
+pub const RET_LIT       : u8 = 0x55; // return, discarding lit argument words
+pub const JSR_LIT       : u8 = 0x56; // call, only saving the return address
+pub const JSR_REG       : u8 = 0x57;
+pub const RTS           : u8 = 0x58;
 
 pub const LSF_REG_REG   : u8 = 0x60;
 pub const LSF_REG_LIT   : u8 = 0x61;
@@ -65,4 +81,200 @@ pub const XOR_REG_REG   : u8 = 0x68;
 pub const XOR_REG_LIT   : u8 = 0x69;
 pub const NOT           : u8 = 0x6A;
 
+pub const INT_LIT       : u8 = 0x70; // call the handler of vector lit
+pub const IRET          : u8 = 0x71;
+pub const CLI           : u8 = 0x72;
+pub const STI           : u8 = 0x73;
+pub const SYS_LIT       : u8 = 0x74; // call the handler of syscall lit in supervisor mode
+pub const HOSTCALL_LIT  : u8 = 0x75; // call the host function registered as lit
+
+pub const JA_LIT        : u8 = 0x80; // jump if above, unsigned comparison
+pub const JA_REG        : u8 = 0x81;
+pub const JAE_LIT       : u8 = 0x82; // jump if above or equal, unsigned
+pub const JAE_REG       : u8 = 0x83;
+pub const JB_LIT        : u8 = 0x84; // jump if below, unsigned
+pub const JB_REG        : u8 = 0x85;
+pub const JBE_LIT       : u8 = 0x86; // jump if below or equal, unsigned
+pub const JBE_REG       : u8 = 0x87;
+
+pub const DIV_REG_REG   : u8 = 0x90; // acc = r1 / r2, r1 = r1 % r2
+pub const DIV_REG_LIT   : u8 = 0x91;
+pub const MOD_REG_REG   : u8 = 0x92; // acc = r1 % r2, r1 = r1 / r2
+pub const MOD_REG_LIT   : u8 = 0x93;
+pub const SDIV_REG_REG  : u8 = 0x94; // signed div
+pub const SDIV_REG_LIT  : u8 = 0x95;
+pub const SMOD_REG_REG  : u8 = 0x96; // signed mod
+pub const SMOD_REG_LIT  : u8 = 0x97;
+
+pub const BRK           : u8 = 0xFE; // breakpoint, the VM stops and can go on
 pub const END           : u8 = 0xFF;
+
+
+/// Kind of an operand encoded after an opcode
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Operand {
+    /// register id (1 byte)
+    Reg,
+    /// register id used as a memory pointer (1 byte)
+    PtrReg,
+    /// 16 bits literal value
+    Lit16,
+    /// 16 bits memory address
+    Mem16,
+    /// 16 bits code address, target of a jump or a call
+    Addr16,
+}
+
+impl Operand {
+    /// number of bytes used to encode this operand
+    pub const fn size(self) -> usize {
+        match self {
+            Operand::Reg | Operand::PtrReg => 1,
+            Operand::Lit16 | Operand::Mem16 | Operand::Addr16 => 2,
+        }
+    }
+}
+
+/// Description of one opcode, shared by the VM and the assembler
+#[derive(Debug)]
+pub struct OpcodeInfo {
+    pub code: u8,
+    pub mnemonic: &'static str,
+    pub operands: &'static [Operand],
+    /// cycles taken by the instruction, see `OPCODES`
+    pub cycles: u8,
+    /// flags updated by the instruction, see `arch::flags`
+    pub flags: u8,
+}
+
+impl OpcodeInfo {
+    const fn new(code: u8, mnemonic: &'static str, operands: &'static [Operand], cycles: u8, flags: u8) -> Self {
+        Self { code, mnemonic, operands, cycles, flags }
+    }
+
+    /// number of bytes used to encode the instruction, opcode included
+    pub fn size(&self) -> usize {
+        self.operands.iter().fold(1, |len, op| len + op.size())
+    }
+}
+
+const ZN  : u8 = ZERO | NEGATIF;
+const ZNC : u8 = ZERO | NEGATIF | CARRY;
+const ZNCO: u8 = ZNC | OVERFLOW;
+
+/// Every opcode known by the architecture, sorted by `code`. An instruction takes one cycle,
+/// plus one for each memory access and each word moved on the stack. Multiplications take 4
+/// cycles, divisions 8 and `hostcall` 4, without the time spent by the host
+pub const OPCODES: &[OpcodeInfo] = &[
+    OpcodeInfo::new(MOV_LIT_REG,    "mov",  &[Lit16, Reg],          1,  ZN),
+    OpcodeInfo::new(MOV_LIT_MEM8,   "mov8", &[Lit16, Mem16],        2,  ZN),
+    OpcodeInfo::new(MOV_LIT_MEM16,  "mov",  &[Lit16, Mem16],        2,  ZN),
+    OpcodeInfo::new(MOV_REG_REG,    "mov",  &[Reg, Reg],            1,  ZN),
+    OpcodeInfo::new(MOV_REG_MEM,    "mov",  &[Reg, Mem16],          2,  ZN),
+    OpcodeInfo::new(MOV_MEM_REG,    "mov",  &[Mem16, Reg],          2,  ZN),
+    OpcodeInfo::new(MOV_MEM_MEM_8,  "mov8", &[Mem16, Mem16],        3,  ZN),
+    OpcodeInfo::new(MOV_MEM_MEM_16, "mov",  &[Mem16, Mem16],        3,  ZN),
+    OpcodeInfo::new(MOV_PTRREG_REG, "mov",  &[PtrReg, Reg],         2,  ZN),
+    OpcodeInfo::new(MOV_REG_PTRREG, "mov",  &[Reg, PtrReg],         2,  ZN),
+    OpcodeInfo::new(MOV_LITOFF_REG, "mov",  &[Lit16, Reg, Reg],     2,  ZN),
+    OpcodeInfo::new(MOV_REG_LITOFF, "mov",  &[Reg, Lit16, Reg],     2,  ZN),
+
+    OpcodeInfo::new(ADD_REG_REG,    "add",  &[Reg, Reg],            1,  ZNCO),
+    OpcodeInfo::new(ADD_REG_LIT,    "add",  &[Reg, Lit16],          1,  ZNCO),
+    OpcodeInfo::new(SUB_REG_LIT,    "sub",  &[Reg, Lit16],          1,  ZNCO),
+    OpcodeInfo::new(SUB_LIT_REG,    "sub",  &[Lit16, Reg],          1,  ZNCO),
+    OpcodeInfo::new(SUB_REG_REG,    "sub",  &[Reg, Reg],            1,  ZNCO),
+    OpcodeInfo::new(MUL_REG_REG,    "mult", &[Reg, Reg],            4,  ZNCO),
+    OpcodeInfo::new(MUL_REG_LIT,    "mult", &[Reg, Lit16],          4,  ZNCO),
+    OpcodeInfo::new(CMP_REG_REG,    "cmp",  &[Reg, Reg],            1,  ZNCO),
+    OpcodeInfo::new(CMP_REG_LIT,    "cmp",  &[Reg, Lit16],          1,  ZNCO),
+    OpcodeInfo::new(INC_REG,        "inc",  &[Reg],                 1,  ZNCO),
+    OpcodeInfo::new(DEC_REG,        "dec",  &[Reg],                 1,  ZNCO),
+
+    OpcodeInfo::new(JMP_LIT,        "jmp",  &[Addr16],              1,  0),
+    OpcodeInfo::new(JMP_REG,        "jmp",  &[Reg],                 1,  0),
+    OpcodeInfo::new(JEQ_LIT,        "jeq",  &[Addr16],              1,  0),
+    OpcodeInfo::new(JEQ_REG,        "jeq",  &[Reg],                 1,  0),
+    OpcodeInfo::new(JNE_LIT,        "jne",  &[Addr16],              1,  0),
+    OpcodeInfo::new(JNE_REG,        "jne",  &[Reg],                 1,  0),
+    OpcodeInfo::new(JGT_LIT,        "jgt",  &[Addr16],              1,  0),
+    OpcodeInfo::new(JGT_REG,        "jgt",  &[Reg],                 1,  0),
+    OpcodeInfo::new(JGE_LIT,        "jge",  &[Addr16],              1,  0),
+    OpcodeInfo::new(JGE_REG,        "jge",  &[Reg],                 1,  0),
+    OpcodeInfo::new(JLT_LIT,        "jlt",  &[Addr16],              1,  0),
+    OpcodeInfo::new(JLT_REG,        "jlt",  &[Reg],                 1,  0),
+    OpcodeInfo::new(JLE_LIT,        "jle",  &[Addr16],              1,  0),
+    OpcodeInfo::new(JLE_REG,        "jle",  &[Reg],                 1,  0),
+
+    OpcodeInfo::new(PSH_LIT,        "psh",  &[Lit16],               2,  ZN),
+    OpcodeInfo::new(PSH_REG,        "psh",  &[Reg],                 2,  ZN),
+    OpcodeInfo::new(PSH_MEM8,       "psh8", &[Mem16],               3,  ZN),
+    OpcodeInfo::new(PSH_MEM16,      "psh",  &[Mem16],               3,  ZN),
+    OpcodeInfo::new(PSH_PTRREG8,    "psh8", &[PtrReg],              3,  ZN),
+    OpcodeInfo::new(PSH_PTRREG16,   "psh",  &[PtrReg],              3,  ZN),
+    OpcodeInfo::new(POP_REG,        "pop",  &[Reg],                 2,  ZN),
+    OpcodeInfo::new(POP_MEM8,       "pop8", &[Mem16],               3,  ZN),
+    OpcodeInfo::new(POP_MEM16,      "pop",  &[Mem16],               3,  ZN),
+    OpcodeInfo::new(POP_PTRREG8,    "pop8", &[PtrReg],              3,  ZN),
+    OpcodeInfo::new(POP_PTRREG16,   "pop",  &[PtrReg],              3,  ZN),
+    OpcodeInfo::new(PUSHF,          "pushf", &[],                   2,  0),
+    OpcodeInfo::new(POPF,           "popf", &[],                    2,  ZNCO | INTERRUPT | SUPERVISOR),
+
+    OpcodeInfo::new(CALL_LIT,       "cal",  &[Addr16],              12, 0),
+    OpcodeInfo::new(CALL_REG,       "cal",  &[Reg],                 12, 0),
+    OpcodeInfo::new(RET,            "ret",  &[],                    12, 0),
+    OpcodeInfo::new(CALL_LIT_ARGS,  "cal",  &[Addr16, Lit16],       12, 0),
+    OpcodeInfo::new(CALL_REG_ARGS,  "cal",  &[Reg, Lit16],          12, 0),
+    OpcodeInfo::new(RET_LIT,        "ret",  &[Lit16],               12, 0),
+    OpcodeInfo::new(JSR_LIT,        "jsr",  &[Addr16],              2,  0),
+    OpcodeInfo::new(JSR_REG,        "jsr",  &[Reg],                 2,  0),
+    OpcodeInfo::new(RTS,            "rts",  &[],                    2,  0),
+
+    OpcodeInfo::new(LSF_REG_REG,    "lsf",  &[Reg, Reg],            1,  ZN),
+    OpcodeInfo::new(LSF_REG_LIT,    "lsf",  &[Reg, Lit16],          1,  ZN),
+    OpcodeInfo::new(RSF_REG_REG,    "rsf",  &[Reg, Reg],            1,  ZN),
+    OpcodeInfo::new(RSF_REG_LIT,    "rsf",  &[Reg, Lit16],          1,  ZN),
+    OpcodeInfo::new(AND_REG_REG,    "and",  &[Reg, Reg],            1,  ZN),
+    OpcodeInfo::new(AND_REG_LIT,    "and",  &[Reg, Lit16],          1,  ZN),
+    OpcodeInfo::new(OR_REG_REG,     "or",   &[Reg, Reg],            1,  ZN),
+    OpcodeInfo::new(OR_REG_LIT,     "or",   &[Reg, Lit16],          1,  ZN),
+    OpcodeInfo::new(XOR_REG_REG,    "xor",  &[Reg, Reg],            1,  ZN),
+    OpcodeInfo::new(XOR_REG_LIT,    "xor",  &[Reg, Lit16],          1,  ZN),
+    OpcodeInfo::new(NOT,            "not",  &[Reg],                 1,  ZN),
+
+    OpcodeInfo::new(INT_LIT,        "int",  &[Lit16],               13, INTERRUPT | SUPERVISOR),
+    OpcodeInfo::new(IRET,           "iret", &[],                    13, ZNCO | INTERRUPT | SUPERVISOR),
+    OpcodeInfo::new(CLI,            "cli",  &[],                    1,  INTERRUPT),
+    OpcodeInfo::new(STI,            "sti",  &[],                    1,  INTERRUPT),
+    OpcodeInfo::new(SYS_LIT,        "sys",  &[Lit16],               13, SUPERVISOR),
+    OpcodeInfo::new(HOSTCALL_LIT,   "hostcall", &[Lit16],           4,  0),
+
+    OpcodeInfo::new(JA_LIT,         "ja",   &[Addr16],              1,  0),
+    OpcodeInfo::new(JA_REG,         "ja",   &[Reg],                 1,  0),
+    OpcodeInfo::new(JAE_LIT,        "jae",  &[Addr16],              1,  0),
+    OpcodeInfo::new(JAE_REG,        "jae",  &[Reg],                 1,  0),
+    OpcodeInfo::new(JB_LIT,         "jb",   &[Addr16],              1,  0),
+    OpcodeInfo::new(JB_REG,         "jb",   &[Reg],                 1,  0),
+    OpcodeInfo::new(JBE_LIT,        "jbe",  &[Addr16],              1,  0),
+    OpcodeInfo::new(JBE_REG,        "jbe",  &[Reg],                 1,  0),
+
+    OpcodeInfo::new(DIV_REG_REG,    "div",  &[Reg, Reg],            8,  ZNCO),
+    OpcodeInfo::new(DIV_REG_LIT,    "div",  &[Reg, Lit16],          8,  ZNCO),
+    OpcodeInfo::new(MOD_REG_REG,    "mod",  &[Reg, Reg],            8,  ZNCO),
+    OpcodeInfo::new(MOD_REG_LIT,    "mod",  &[Reg, Lit16],          8,  ZNCO),
+    OpcodeInfo::new(SDIV_REG_REG,   "sdiv", &[Reg, Reg],            8,  ZNCO),
+    OpcodeInfo::new(SDIV_REG_LIT,   "sdiv", &[Reg, Lit16],          8,  ZNCO),
+    OpcodeInfo::new(SMOD_REG_REG,   "smod", &[Reg, Reg],            8,  ZNCO),
+    OpcodeInfo::new(SMOD_REG_LIT,   "smod", &[Reg, Lit16],          8,  ZNCO),
+
+    OpcodeInfo::new(BRK,            "brk",  &[],                    1,  0),
+    OpcodeInfo::new(END,            "end",  &[],                    1,  0),
+];
+
+/// Find the description of the opcode `code`, `None` if the opcode doesn't exist
+pub fn opcode_info(code: u8) -> Option<&'static OpcodeInfo> {
+    OPCODES
+        .binary_search_by_key(&code, |info| info.code)
+        .ok()
+        .map(|id| &OPCODES[id])
+}
@@ -0,0 +1,30 @@
+//! Interrupt vectors. The vector table holds the address of the handler of each vector,
+//! a null address means the vector has no handler. `int n` and the lines raised by the
+//! devices share the same vectors. The table is placed after the first bytes of memory,
+//! so a boot stub can still jump to the program from address 0.
+
+/// Address of the vector table in memory
+pub const VECTOR_TABLE: u16 = 0x0010;
+
+/// Number of vectors, each one is a 16 bits address
+pub const VECTOR_COUNT: u16 = 16;
+
+/// Address of the entry of `vector` in the vector table
+pub const fn vector_address(vector: u16) -> u16 {
+    VECTOR_TABLE + vector * 2
+}
+
+/// Address of the syscall table, after the vector table. `sys n` calls the handler of
+/// the syscall `n` in supervisor mode, a null address means the syscall doesn't exist
+pub const SYSCALL_TABLE: u16 = VECTOR_TABLE + VECTOR_COUNT * 2;
+
+/// Number of syscalls, each one is a 16 bits address
+pub const SYSCALL_COUNT: u16 = 16;
+
+/// Address of the entry of `syscall` in the syscall table
+pub const fn syscall_address(syscall: u16) -> u16 {
+    SYSCALL_TABLE + syscall * 2
+}
+
+/// First address after the syscall table, programs should be loaded from there
+pub const TABLES_END: u16 = SYSCALL_TABLE + SYSCALL_COUNT * 2;
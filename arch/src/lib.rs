@@ -1,2 +1,10 @@
 pub mod instructions;
 pub mod registers;
+pub mod flags;
+pub mod interrupts;
+pub mod codec;
+pub mod object;
+pub mod format;
+pub mod reader;
+
+mod test;
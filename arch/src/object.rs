@@ -0,0 +1,431 @@
+//! Container format of the `.vmo` files written by the compiler.
+//!
+//! Every value is stored big endian, like in the VM memory.
+//!
+//! | bytes | content                                   |
+//! |-------|-------------------------------------------|
+//! | 4     | magic, `VMO\0`                            |
+//! | 2     | format version                            |
+//! | 2     | ISA version, see `arch::instructions`     |
+//! | 2     | entry point address                       |
+//! | 2     | number of sections                        |
+//! | 10 x n| section table                             |
+//! | ...   | content of every section, in table order  |
+//!
+//! Each entry of the section table is made of the kind (1 byte), a reserved
+//! byte, the load address (2 bytes), the size in memory (2 bytes) and the
+//! offset of the content in the file (4 bytes).
+
+use crate::reader::{Reader, Truncated};
+
+pub const MAGIC: &[u8; 4] = b"VMO\0";
+pub const FORMAT_VERSION: u16 = 3;
+
+const HEADER_LEN: usize = 12;
+const SECTION_LEN: usize = 10;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum SectionKind {
+    /// executable code, loaded at `address`
+    Code,
+    /// initialized data, loaded at `address`
+    Data,
+    /// zeroed memory, no content in the file
+    Bss,
+    /// labels, variables and imported names, not loaded
+    Symbols,
+    /// fields of the code holding the address of a symbol, not loaded
+    Relocations,
+    /// source file and line of every instruction, not loaded
+    Debug,
+}
+
+impl SectionKind {
+    fn code(self) -> u8 {
+        match self {
+            SectionKind::Code => 1,
+            SectionKind::Data => 2,
+            SectionKind::Bss => 3,
+            SectionKind::Symbols => 4,
+            SectionKind::Relocations => 5,
+            SectionKind::Debug => 6,
+        }
+    }
+
+    fn from_code(code: u8) -> Option<Self> {
+        match code {
+            1 => Some(SectionKind::Code),
+            2 => Some(SectionKind::Data),
+            3 => Some(SectionKind::Bss),
+            4 => Some(SectionKind::Symbols),
+            5 => Some(SectionKind::Relocations),
+            6 => Some(SectionKind::Debug),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Section {
+    pub kind: SectionKind,
+    pub address: u16,
+    /// size in memory, equal to `data.len()` except for `Bss`
+    pub size: u16,
+    pub data: Vec<u8>,
+}
+
+impl Section {
+    pub fn new(kind: SectionKind, address: u16, data: Vec<u8>) -> Self {
+        Self { kind, address, size: data.len() as u16, data }
+    }
+
+    pub fn bss(address: u16, size: u16) -> Self {
+        Self { kind: SectionKind::Bss, address, size, data: vec![] }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SymbolKind {
+    Label,
+    Variable,
+    /// declared with `.extern`, defined by another object
+    Import,
+}
+
+/// set on the kind byte of a symbol exported with `.global`
+const GLOBAL: u8 = 0x80;
+
+/// Entry of the `Symbols` section
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Symbol {
+    pub name: String,
+    pub kind: SymbolKind,
+    pub address: u16,
+    /// number of bytes of a variable, 0 for a label
+    pub size: u16,
+    /// size of one element of a variable (1 for u8, 2 for u16), 0 for a label
+    pub type_len: u8,
+    /// source line of the definition, or of the `.extern` directive
+    pub line: u16,
+    /// visible from the other objects, set by `.global`
+    pub global: bool,
+}
+
+impl Symbol {
+    pub fn label(name: String, address: u16, line: u16) -> Self {
+        Self { name, kind: SymbolKind::Label, address, size: 0, type_len: 0, line, global: false }
+    }
+
+    pub fn variable(name: String, address: u16, size: u16, type_len: u8, line: u16) -> Self {
+        Self { name, kind: SymbolKind::Variable, address, size, type_len, line, global: false }
+    }
+
+    pub fn import(name: String, line: u16) -> Self {
+        Self { name, kind: SymbolKind::Import, address: 0, size: 0, type_len: 0, line, global: false }
+    }
+
+    /// Encode a list of symbols as the content of a `Symbols` section
+    pub fn to_section(symbols: &[Symbol]) -> Section {
+        let mut data = vec![];
+
+        for sym in symbols {
+            let kind = match sym.kind {
+                SymbolKind::Label => 0,
+                SymbolKind::Variable => 1,
+                SymbolKind::Import => 2,
+            };
+            let global = if sym.global { GLOBAL } else { 0 };
+
+            data.extend_from_slice(&[kind | global, sym.type_len]);
+            data.extend_from_slice(&sym.address.to_be_bytes());
+            data.extend_from_slice(&sym.size.to_be_bytes());
+            data.extend_from_slice(&sym.line.to_be_bytes());
+            data.push(sym.name.len() as u8);
+            data.extend_from_slice(sym.name.as_bytes());
+        }
+
+        Section::new(SectionKind::Symbols, 0, data)
+    }
+
+    /// Decode the content of a `Symbols` section
+    pub fn from_section(section: &Section) -> Result<Vec<Symbol>, ObjectError> {
+        let mut reader = Reader::new(&section.data);
+        let mut symbols = vec![];
+
+        while !reader.is_empty() {
+            let flags = reader.u8()?;
+            let kind = match flags & !GLOBAL {
+                0 => SymbolKind::Label,
+                1 => SymbolKind::Variable,
+                2 => SymbolKind::Import,
+                _ => return Err(ObjectError::BadSymbol),
+            };
+            let type_len = reader.u8()?;
+            let address = reader.u16()?;
+            let size = reader.u16()?;
+            let line = reader.u16()?;
+            let len = reader.u8()? as usize;
+            let name = String::from_utf8(reader.bytes(len)?.to_vec()).map_err(|_| ObjectError::BadSymbol)?;
+
+            symbols.push(Symbol { name, kind, address, size, type_len, line, global: flags & GLOBAL != 0 });
+        }
+
+        Ok(symbols)
+    }
+}
+
+/// Entry of the `Relocations` section: the 16 bits at `offset` in the code
+/// hold the address of `symbol`. They must be rewritten when the object is linked
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Relocation {
+    /// address of the field, code is considered loaded at its section address
+    pub offset: u16,
+    /// index of the symbol in the `Symbols` section
+    pub symbol: u16,
+    /// source line of the instruction
+    pub line: u16,
+}
+
+impl Relocation {
+    /// Encode a list of relocations as the content of a `Relocations` section
+    pub fn to_section(relocations: &[Relocation]) -> Section {
+        let mut data = Vec::with_capacity(relocations.len() * 6);
+
+        for rel in relocations {
+            data.extend_from_slice(&rel.offset.to_be_bytes());
+            data.extend_from_slice(&rel.symbol.to_be_bytes());
+            data.extend_from_slice(&rel.line.to_be_bytes());
+        }
+
+        Section::new(SectionKind::Relocations, 0, data)
+    }
+
+    /// Decode the content of a `Relocations` section
+    pub fn from_section(section: &Section) -> Result<Vec<Relocation>, ObjectError> {
+        let mut reader = Reader::new(&section.data);
+        let mut relocations = vec![];
+
+        while !reader.is_empty() {
+            let offset = reader.u16()?;
+            let symbol = reader.u16()?;
+            let line = reader.u16()?;
+
+            relocations.push(Relocation { offset, symbol, line });
+        }
+
+        Ok(relocations)
+    }
+}
+
+/// Source position of the instruction starting at `address`
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct LineEntry {
+    pub address: u16,
+    /// index in `DebugInfo::files`
+    pub file: u8,
+    pub line: u16,
+}
+
+/// Content of the `Debug` section: the source files, then one
+/// entry per instruction sorted by address
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct DebugInfo {
+    pub files: Vec<String>,
+    pub lines: Vec<LineEntry>,
+}
+
+impl DebugInfo {
+    /// File name and line of the instruction containing `address`
+    pub fn line_at(&self, address: u16) -> Option<(&str, u16)> {
+        let id = match self.lines.binary_search_by_key(&address, |entry| entry.address) {
+            Ok(id) => id,
+            Err(0) => return None,
+            Err(id) => id - 1,
+        };
+
+        let entry = &self.lines[id];
+        self.files.get(entry.file as usize).map(|file| (file.as_str(), entry.line))
+    }
+
+    pub fn to_section(&self) -> Section {
+        let mut data = vec![self.files.len() as u8];
+
+        for file in self.files.iter() {
+            data.push(file.len() as u8);
+            data.extend_from_slice(file.as_bytes());
+        }
+
+        for entry in self.lines.iter() {
+            data.extend_from_slice(&entry.address.to_be_bytes());
+            data.push(entry.file);
+            data.extend_from_slice(&entry.line.to_be_bytes());
+        }
+
+        Section::new(SectionKind::Debug, 0, data)
+    }
+
+    pub fn from_section(section: &Section) -> Result<Self, ObjectError> {
+        let mut reader = Reader::new(&section.data);
+        let mut info = DebugInfo::default();
+
+        for _ in 0..reader.u8()? {
+            let len = reader.u8()? as usize;
+            let file = String::from_utf8(reader.bytes(len)?.to_vec()).map_err(|_| ObjectError::BadDebugInfo)?;
+            info.files.push(file);
+        }
+
+        while !reader.is_empty() {
+            let address = reader.u16()?;
+            let file = reader.u8()?;
+            let line = reader.u16()?;
+
+            info.lines.push(LineEntry { address, file, line });
+        }
+
+        Ok(info)
+    }
+}
+
+/// Content of a `.vmo` file
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Object {
+    pub isa_version: u16,
+    pub entry: u16,
+    pub sections: Vec<Section>,
+}
+
+impl Object {
+    pub fn new(entry: u16) -> Self {
+        Self { isa_version: crate::instructions::ISA_VERSION, entry, sections: vec![] }
+    }
+
+    /// return true if `bytes` starts like an object file
+    pub fn is_object(bytes: &[u8]) -> bool {
+        bytes.starts_with(MAGIC)
+    }
+
+    /// First section of the given kind
+    pub fn section(&self, kind: SectionKind) -> Option<&Section> {
+        self.sections.iter().find(|sec| sec.kind == kind)
+    }
+
+    /// Symbols of the object, empty if there is no `Symbols` section
+    pub fn symbols(&self) -> Result<Vec<Symbol>, ObjectError> {
+        match self.section(SectionKind::Symbols) {
+            Some(section) => Symbol::from_section(section),
+            None => Ok(vec![]),
+        }
+    }
+
+    /// Relocations of the object, empty if there is no `Relocations` section
+    pub fn relocations(&self) -> Result<Vec<Relocation>, ObjectError> {
+        match self.section(SectionKind::Relocations) {
+            Some(section) => Relocation::from_section(section),
+            None => Ok(vec![]),
+        }
+    }
+
+    /// Line table of the object, if it has a `Debug` section
+    pub fn debug_info(&self) -> Result<Option<DebugInfo>, ObjectError> {
+        self.section(SectionKind::Debug).map(DebugInfo::from_section).transpose()
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(HEADER_LEN + SECTION_LEN * self.sections.len());
+        bytes.extend_from_slice(MAGIC);
+        bytes.extend_from_slice(&FORMAT_VERSION.to_be_bytes());
+        bytes.extend_from_slice(&self.isa_version.to_be_bytes());
+        bytes.extend_from_slice(&self.entry.to_be_bytes());
+        bytes.extend_from_slice(&(self.sections.len() as u16).to_be_bytes());
+
+        let mut offset = (HEADER_LEN + SECTION_LEN * self.sections.len()) as u32;
+        for sec in self.sections.iter() {
+            bytes.extend_from_slice(&[sec.kind.code(), 0]);
+            bytes.extend_from_slice(&sec.address.to_be_bytes());
+            bytes.extend_from_slice(&sec.size.to_be_bytes());
+            bytes.extend_from_slice(&offset.to_be_bytes());
+            offset += sec.data.len() as u32;
+        }
+
+        for sec in self.sections.iter() {
+            bytes.extend_from_slice(&sec.data);
+        }
+
+        bytes
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, ObjectError> {
+        if !Object::is_object(bytes) {
+            return Err(ObjectError::BadMagic);
+        }
+
+        let mut reader = Reader::new(&bytes[MAGIC.len()..]);
+        let version = reader.u16()?;
+        if version != FORMAT_VERSION {
+            return Err(ObjectError::UnsupportedVersion(version));
+        }
+
+        let isa_version = reader.u16()?;
+        let entry = reader.u16()?;
+        let count = reader.u16()?;
+
+        let mut sections = Vec::with_capacity(count as usize);
+        for _ in 0..count {
+            let code = reader.u8()?;
+            let kind = SectionKind::from_code(code).ok_or(ObjectError::UnknownSection(code))?;
+            let _reserved = reader.u8()?;
+            let address = reader.u16()?;
+            let size = reader.u16()?;
+            let offset = reader.u32()? as usize;
+
+            let len = match kind {
+                SectionKind::Bss => 0,
+                _ => size as usize,
+            };
+            let data = bytes.get(offset..offset + len).ok_or(ObjectError::Truncated)?.to_vec();
+
+            sections.push(Section { kind, address, size, data });
+        }
+
+        Ok(Self { isa_version, entry, sections })
+    }
+}
+
+/// Enumeration of every error found while reading an object file
+pub enum ObjectError {
+    BadMagic,
+    UnsupportedVersion(u16),
+    IncompatibleIsa(u16),
+    UnknownSection(u8),
+    BadSymbol,
+    BadRelocation(u16),
+    BadDebugInfo,
+    Truncated,
+}
+
+impl From<Truncated> for ObjectError {
+    fn from(_: Truncated) -> Self {
+        ObjectError::Truncated
+    }
+}
+
+impl std::fmt::Debug for ObjectError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let error = match self {
+            ObjectError::BadMagic => "Missing magic number, this may be a legacy raw image".to_owned(),
+            ObjectError::UnsupportedVersion(v) => format!(
+                "Object format version {} is not supported, expected version {}", v, FORMAT_VERSION
+            ),
+            ObjectError::IncompatibleIsa(v) => format!(
+                "Object needs ISA version {}, this VM implements version {}", v, crate::instructions::ISA_VERSION
+            ),
+            ObjectError::UnknownSection(kind) => format!("Unknown section kind {}", kind),
+            ObjectError::BadSymbol => "Malformed symbol table".to_owned(),
+            ObjectError::BadRelocation(offset) => format!("Relocation at {:#06X} references a missing symbol", offset),
+            ObjectError::BadDebugInfo => "Malformed debug information".to_owned(),
+            ObjectError::Truncated => "Object file is truncated".to_owned(),
+        };
+
+        write!(f, "{}", error)
+    }
+}
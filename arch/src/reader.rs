@@ -0,0 +1,50 @@
+//! Reader of the binary files of the toolchain: objects, save-states and input recordings.
+//! Every value is stored big endian, like in the VM memory.
+
+/// The file ends before the value being read. Every file format converts it to its own
+/// error with `From`
+#[derive(Debug)]
+pub struct Truncated;
+
+/// Read big endian values from a slice of bytes
+pub struct Reader<'a> {
+    bytes: &'a [u8],
+    ptr: usize,
+}
+
+impl<'a> Reader<'a> {
+    pub fn new(bytes: &'a [u8]) -> Self {
+        Self { bytes, ptr: 0 }
+    }
+
+    /// Every byte was read
+    pub fn is_empty(&self) -> bool {
+        self.ptr >= self.bytes.len()
+    }
+
+    pub fn bytes(&mut self, len: usize) -> Result<&'a [u8], Truncated> {
+        let bytes = self.bytes.get(self.ptr..self.ptr + len).ok_or(Truncated)?;
+        self.ptr += len;
+        Ok(bytes)
+    }
+
+    pub fn u8(&mut self) -> Result<u8, Truncated> {
+        Ok(self.bytes(1)?[0])
+    }
+
+    pub fn u16(&mut self) -> Result<u16, Truncated> {
+        let b = self.bytes(2)?;
+        Ok(u16::from_be_bytes([b[0], b[1]]))
+    }
+
+    pub fn u32(&mut self) -> Result<u32, Truncated> {
+        let b = self.bytes(4)?;
+        Ok(u32::from_be_bytes([b[0], b[1], b[2], b[3]]))
+    }
+
+    pub fn u64(&mut self) -> Result<u64, Truncated> {
+        let mut b = [0; 8];
+        b.copy_from_slice(self.bytes(8)?);
+        Ok(u64::from_be_bytes(b))
+    }
+}
@@ -6,6 +6,7 @@ pub const REGISTER_NAMES: &[&str] = &[
     "dh", "dl", "dx",
     "ex", "fx", "gx", "hx",
     "sp", "fp",
+    "fl",
 ];
 
 pub const SIZE_OF: &[u8] = &[
@@ -16,6 +17,7 @@ pub const SIZE_OF: &[u8] = &[
     1, 1, 2,
     2, 2, 2, 2,
     2, 2,
+    2,
 ];
 
 pub const ADDRESS_OF: &[usize] = &[
@@ -26,9 +28,10 @@ pub const ADDRESS_OF: &[usize] = &[
     10, 11, 10,
     12, 14, 16, 18,
     20, 22,
+    24,
 ];
 
-pub const REGISTER_LEN: usize = 24;
+pub const REGISTER_LEN: usize = 26;
 
 pub const IP : u8 = 0;
 pub const ACC: u8 = 1;
@@ -50,3 +53,4 @@ pub const GX : u8 = 16;
 pub const HX : u8 = 17;
 pub const SP : u8 = 18;
 pub const FP : u8 = 19;
+pub const FL : u8 = 20; // flags, see `arch::flags`
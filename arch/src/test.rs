@@ -0,0 +1,209 @@
+#[cfg(test)]
+mod tests {
+    use crate::instructions::*;
+    use crate::codec::*;
+    use crate::object::*;
+    use crate::format::*;
+    use crate::reader::Reader;
+
+    #[test]
+    fn opcodes_sorted_and_unique() {
+        for pair in OPCODES.windows(2) {
+            assert!(pair[0].code < pair[1].code, "{:#04X} is not sorted", pair[1].code);
+        }
+    }
+
+    #[test]
+    fn opcode_lookup() {
+        let mov = opcode_info(MOV_LITOFF_REG).unwrap();
+        assert_eq!(mov.mnemonic, "mov");
+        assert_eq!(mov.operands, &[Operand::Lit16, Operand::Reg, Operand::Reg]);
+        assert_eq!(mov.size(), 5);
+        assert_eq!(mov.cycles, 2);
+
+        assert_eq!(opcode_info(PSH_PTRREG8).unwrap().size(), 2);
+        assert_eq!(opcode_info(RET).unwrap().size(), 1);
+        assert_eq!(opcode_info(END).unwrap().size(), 1);
+        assert!(opcode_info(0x00).is_none());
+    }
+
+    #[test]
+    fn decode_encode_round_trip() {
+        let patterns: [&[u8]; 4] = [
+            &[0x00, 0x00, 0x00, 0x00],
+            &[0x04, 0x12, 0x34, 0x07],
+            &[0xFF, 0xFF, 0xFF, 0xFF],
+            &[0x13, 0x00, 0xFE, 0x01],
+        ];
+
+        for info in OPCODES {
+            for pattern in patterns.iter() {
+                let mut bytes = vec![info.code];
+                bytes.extend_from_slice(&pattern[..info.size() - 1]);
+
+                let (ins, len) = decode(&bytes).unwrap();
+                assert_eq!(ins.opcode(), info.code);
+                assert_eq!(len, info.size());
+                assert_eq!(encode(&ins), bytes);
+                assert_eq!(decode(&encode(&ins)).unwrap().0, ins);
+            }
+        }
+    }
+
+    #[test]
+    fn decode_errors() {
+        assert!(matches!(decode(&[]), Err(DecodeError::Empty)));
+        assert!(matches!(decode(&[0x00]), Err(DecodeError::UnknownOpcode(0x00))));
+        assert!(matches!(
+            decode(&[MOV_LIT_REG, 0x00, 0x01]),
+            Err(DecodeError::Truncated { opcode: MOV_LIT_REG, expected: 4, found: 3 })
+        ));
+
+        let (ins, len) = decode(&[MOV_LITOFF_REG, 0x14, 0x00, 0x04, 0x07, END]).unwrap();
+        assert_eq!(ins, Instruction::MovLitOffReg(0x1400, 0x04, 0x07));
+        assert_eq!(len, 5);
+    }
+
+    #[test]
+    fn object_round_trip() {
+        let mut object = Object::new(0x0004);
+        object.sections.push(Section::new(SectionKind::Code, 0, vec![MOV_LIT_REG, 0x00, 0x01, 0x04, END]));
+        object.sections.push(Section::new(SectionKind::Data, 0x0005, vec![0x48, 0x69]));
+        object.sections.push(Section::bss(0x0007, 0x10));
+        let mut hi = Symbol::variable("hi".to_owned(), 0x0005, 2, 1, 2);
+        hi.global = true;
+        object.sections.push(Symbol::to_section(&[
+            Symbol::label("start".to_owned(), 0x0000, 5),
+            hi.clone(),
+            Symbol::import("print".to_owned(), 1),
+        ]));
+        object.sections.push(Relocation::to_section(&[
+            Relocation { offset: 0x0001, symbol: 1, line: 6 },
+        ]));
+
+        let bytes = object.to_bytes();
+        assert!(Object::is_object(&bytes));
+
+        let read = Object::from_bytes(&bytes).unwrap();
+        assert_eq!(read, object);
+        assert_eq!(read.section(SectionKind::Bss).unwrap().size, 0x10);
+        assert_eq!(read.symbols().unwrap()[1], hi);
+        assert_eq!(read.symbols().unwrap()[2].kind, SymbolKind::Import);
+        assert_eq!(read.relocations().unwrap(), [Relocation { offset: 0x0001, symbol: 1, line: 6 }]);
+    }
+
+    #[test]
+    fn object_errors() {
+        let bytes = Object::new(0).to_bytes();
+
+        assert!(matches!(Object::from_bytes(&[MOV_LIT_REG, 0x00]), Err(ObjectError::BadMagic)));
+        assert!(matches!(Object::from_bytes(&bytes[..6]), Err(ObjectError::Truncated)));
+
+        let mut newer = bytes.clone();
+        newer[5] = 9;
+        assert!(matches!(Object::from_bytes(&newer), Err(ObjectError::UnsupportedVersion(9))));
+
+        let mut object = Object::new(0);
+        object.sections.push(Section::new(SectionKind::Code, 0, vec![END]));
+        let mut unknown = object.to_bytes();
+        unknown[12] = 0x7F;
+        assert!(matches!(Object::from_bytes(&unknown), Err(ObjectError::UnknownSection(0x7F))));
+
+        let truncated = object.to_bytes();
+        assert!(matches!(Object::from_bytes(&truncated[..truncated.len() - 1]), Err(ObjectError::Truncated)));
+    }
+
+    #[test]
+    fn reader_values() {
+        let bytes = [0x01, 0x02, 0x03, 0x00, 0x00, 0x00, 0x04, 0, 0, 0, 0, 0, 0, 0x01, 0x00, 0xAA];
+        let mut reader = Reader::new(&bytes);
+
+        assert_eq!(reader.u8().unwrap(), 0x01);
+        assert_eq!(reader.u16().unwrap(), 0x0203);
+        assert_eq!(reader.u32().unwrap(), 0x0000_0004);
+        assert_eq!(reader.u64().unwrap(), 0x0000_0000_0000_0100);
+        assert!(!reader.is_empty());
+
+        // nothing is consumed by a value going past the end
+        assert!(reader.u16().is_err());
+        assert_eq!(reader.bytes(1).unwrap(), [0xAA]);
+        assert!(reader.is_empty());
+        assert!(reader.u8().is_err());
+    }
+
+    #[test]
+    fn debug_info() {
+        let info = DebugInfo {
+            files: vec!["main.vms".to_owned(), "lib.vms".to_owned()],
+            lines: vec![
+                LineEntry { address: 0x0000, file: 0, line: 3 },
+                LineEntry { address: 0x0004, file: 0, line: 4 },
+                LineEntry { address: 0x0007, file: 1, line: 9 },
+            ],
+        };
+
+        let mut object = Object::new(0);
+        object.sections.push(info.to_section());
+        let read = Object::from_bytes(&object.to_bytes()).unwrap().debug_info().unwrap().unwrap();
+        assert_eq!(read, info);
+
+        assert_eq!(info.line_at(0x0000), Some(("main.vms", 3)));
+        assert_eq!(info.line_at(0x0005), Some(("main.vms", 4)));
+        assert_eq!(info.line_at(0x0007), Some(("lib.vms", 9)));
+        assert_eq!(DebugInfo::default().line_at(0x0007), None);
+        assert_eq!(Object::new(0).debug_info().unwrap(), None);
+    }
+
+    fn loaded_object() -> Object {
+        let mut object = Object::new(0x8004);
+        object.sections.push(Section::new(SectionKind::Code, 0x8000, (0..20).collect()));
+        object.sections.push(Section::new(SectionKind::Data, 0x8014, vec![0x48, 0x69]));
+        object.sections.push(Symbol::to_section(&[Symbol::label("start".to_owned(), 0x8004, 2)]));
+        object
+    }
+
+    #[test]
+    fn ihex_round_trip() {
+        let text = to_ihex(&loaded_object());
+        let lines: Vec<&str> = text.lines().collect();
+
+        assert_eq!(lines[0], ":10800000000102030405060708090A0B0C0D0E0FF8");
+        assert_eq!(lines[lines.len() - 2], ":040000050000800473");
+        assert_eq!(lines[lines.len() - 1], ":00000001FF");
+
+        let read = from_ihex(&text).unwrap();
+        assert_eq!(read.entry, 0x8004);
+        assert_eq!(read.sections.len(), 1);
+        assert_eq!(read.sections[0].address, 0x8000);
+        assert_eq!(read.sections[0].data, [(0..20).collect::<Vec<u8>>(), vec![0x48, 0x69]].concat());
+    }
+
+    #[test]
+    fn srec_round_trip() {
+        let text = to_srec(&loaded_object(), "prog");
+        let lines: Vec<&str> = text.lines().collect();
+
+        assert_eq!(lines[0], "S007000070726F6740");
+        assert_eq!(lines[1], "S1138000000102030405060708090A0B0C0D0E0FF4");
+        assert_eq!(lines[lines.len() - 1], "S903800478");
+
+        let read = from_srec(&text).unwrap();
+        assert_eq!(read.entry, 0x8004);
+        assert_eq!(read.sections[0].address, 0x8000);
+        assert_eq!(read.sections[0].data.len(), 22);
+    }
+
+    #[test]
+    fn hex_errors() {
+        // no entry point record, execution starts on the lowest address
+        assert_eq!(from_ihex(":020100001234B7\n:00000001FF\n").unwrap().entry, 0x0100);
+        assert!(matches!(from_ihex(":020100001234B8\n"), Err(HexError::BadChecksum(1))));
+        assert!(matches!(from_ihex(":020100001234B7\n"), Err(HexError::MissingEnd)));
+        assert!(matches!(from_ihex("\n020100001234B7\n"), Err(HexError::BadRecord(2))));
+        assert!(matches!(from_ihex(":020000040001F9\n:020100001234B7\n"), Err(HexError::AddressOverflow(2))));
+        assert!(matches!(from_srec("S10501001234B4\nS9030000FC\n"), Err(HexError::BadChecksum(1))));
+        assert!(matches!(from_srec("S4030000FC\n"), Err(HexError::UnsupportedRecord(1, _))));
+        assert_eq!("ihex".parse::<Format>().unwrap(), Format::Ihex);
+        assert!("elf".parse::<Format>().is_err());
+    }
+}
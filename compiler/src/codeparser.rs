@@ -1,18 +1,30 @@
 use std::fmt::{Display, Formatter};
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 
 use crate::dataparser::DataParser;
 use crate::instructions::Ins;
 use crate::chunk::Chunk;
+use arch::object::{DebugInfo, LineEntry, Object, Relocation, Section, SectionKind, Symbol, SymbolKind};
+
+/// Names given to the `.global` and `.extern` directives,
+/// with the line of the directive
+#[derive(Default)]
+pub struct Linkage {
+    pub exports: Vec<(String, usize)>,
+    pub imports: Vec<(String, usize)>,
+}
 
 pub struct CodeParser {
     start_address: usize,
+    origin: u16,
     cmds: Vec<(Ins, usize)>,
     jumps_pts: HashMap<String, u16>,
 }
 
 impl CodeParser {
-    pub fn new(chunk: Chunk) -> Result<Self, String> {
+    /// `start` can be omitted by a `library`, its code is only reached from other objects.
+    /// The code is loaded at `origin`, set with the `.org` directive
+    pub fn new(chunk: Chunk, library: bool, origin: u16) -> Result<Self, String> {
         let mut cmds = Vec::with_capacity(10);
         let mut start_address = None;
 
@@ -20,7 +32,7 @@ impl CodeParser {
             match Ins::build_with_line(line) {
                 Ok(cmd) => {
                     if let Ins::Flag(flag) = &cmd {
-                        if start_address == None && flag == "start" {
+                        if start_address.is_none() && flag == "start" {
                             start_address = Some(cmds.len());
                         }
                     }
@@ -32,10 +44,11 @@ impl CodeParser {
 
         let start_address = match start_address {
             Some(add) => add,
-            None => return Err("Flag start is required to start execution".to_owned()),
+            None if library => 0,
+            None => return Err("Flag start is required to start execution, or export symbols with .global".to_owned()),
         };
 
-        let mut ptr = 0;
+        let mut ptr = origin as usize;
         let cmds_len = cmds.len();
         let mut jumps_pts = HashMap::new();
 
@@ -53,7 +66,11 @@ impl CodeParser {
             ptr += cmd.ins_len();
         }
 
-        Ok(Self{ start_address, cmds, jumps_pts })
+        if ptr > 0x10000 {
+            return Err(format!("Code loaded at {:#06X} doesn't fit in memory", origin));
+        }
+
+        Ok(Self{ start_address, origin, cmds, jumps_pts })
     }
 
     pub fn ins_len(&self) -> usize {
@@ -66,14 +83,59 @@ impl CodeParser {
         len
     }
 
-    pub fn get_vec(self, data: Option<DataParser>) -> Result<Vec<u8>, String> {
+    /// Symbols of every flag, sorted by address. `start` comes first
+    /// so a disassembled listing keeps it at the head of the code
+    pub fn symbols(&self) -> Vec<Symbol> {
+        let mut labels: Vec<_> = self.cmds.iter()
+            .filter_map(|(ins, line)| match ins {
+                Ins::Flag(name) => Some((name, self.jumps_pts[name], *line)),
+                _ => None,
+            })
+            .collect();
+        labels.sort_by_key(|(name, add, _)| (*add, name.as_str() != "start", name.as_str()));
+
+        labels.into_iter()
+            .map(|(name, add, line)| Symbol::label(name.to_owned(), add, line as u16))
+            .collect()
+    }
+
+    /// Build the object: code at its origin, data right after it, the symbols, the relocations
+    /// and the lines of `source`. `start` is moved on the first instruction, so the entry point is the origin
+    pub fn get_object(self, data: Option<DataParser>, linkage: &Linkage, source: &str) -> Result<Object, String> {
         let ins_len = self.ins_len();
-        let (data_len, vars) = match &data {
-            Some(data) => (data.data_len(), Some(data.vars())),
-            None => (0, None),
-        };
+        let origin = self.origin as usize;
+        let data_add = origin + ins_len;
+
+        let data_len = data.as_ref().map_or(0, |data| data.data_len());
+        if data_add + data_len > 0x10000 {
+            return Err(format!("Data loaded at {:#06X} doesn't fit in memory", data_add));
+        }
+        let vars = data.as_ref().map(|data| data.vars());
+        let imports: HashSet<String> = linkage.imports.iter().map(|(name, _)| name.to_owned()).collect();
+
+        let mut symbols = self.symbols();
+        if let Some(data) = &data {
+            symbols.append(&mut data.symbols(data_add as u16));
+        }
+        for (name, line) in linkage.imports.iter() {
+            symbols.push(Symbol::import(name.to_owned(), *line as u16));
+        }
+
+        for (name, line) in linkage.exports.iter() {
+            let mut found = false;
+            for sym in symbols.iter_mut().filter(|sym| &sym.name == name && sym.kind != SymbolKind::Import) {
+                sym.global = true;
+                found = true;
+            }
 
-        let mut vec = Vec::with_capacity(ins_len + data_len);
+            if !found {
+                return Err(format!("Error on line {} : can't export {}, it is not defined", line, name));
+            }
+        }
+
+        let mut code = Vec::with_capacity(ins_len);
+        let mut relocations = vec![];
+        let mut debug = DebugInfo { files: vec![source.to_owned()], lines: vec![] };
         let mut reg_ptr = HashMap::new();
         let cmds_len = self.cmds.len();
 
@@ -81,17 +143,47 @@ impl CodeParser {
             let id = (self.start_address + id) % cmds_len;
             let (ins, line) = &self.cmds[id];
 
-            match ins.get_code(&self.jumps_pts, &mut reg_ptr, vars, ins_len as u16) {
-                Ok(mut v) => vec.append(&mut v),
+            match ins.get_code(&self.jumps_pts, &mut reg_ptr, vars, data_add as u16, &imports) {
+                Ok((mut v, reference)) => {
+                    if let Some(reference) = reference {
+                        let symbol = symbols.iter()
+                            .position(|sym| sym.kind == reference.kind && sym.name == reference.name)
+                            .unwrap();
+
+                        relocations.push(Relocation {
+                            offset: (origin + code.len() + reference.offset) as u16,
+                            symbol: symbol as u16,
+                            line: *line as u16,
+                        });
+                    }
+
+                    if !v.is_empty() {
+                        debug.lines.push(LineEntry { address: (origin + code.len()) as u16, file: 0, line: *line as u16 });
+                    }
+
+                    code.append(&mut v);
+                }
                 Err(s) => return Err(format!("Error while compiling on line {} : {}", line, s)),
             }
         }
 
+        let mut object = Object::new(self.origin);
+        object.sections.push(Section::new(SectionKind::Code, self.origin, code));
+
         if let Some(data) = data {
-            vec.append(&mut data.get_vec());
+            let data = data.get_vec();
+            if !data.is_empty() {
+                object.sections.push(Section::new(SectionKind::Data, data_add as u16, data));
+            }
+        }
+
+        object.sections.push(Symbol::to_section(&symbols));
+        if !relocations.is_empty() {
+            object.sections.push(Relocation::to_section(&relocations));
         }
+        object.sections.push(debug.to_section());
 
-        Ok(vec)
+        Ok(object)
     }
 }
 
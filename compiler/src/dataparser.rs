@@ -4,6 +4,7 @@ use regex::Regex;
 
 use crate::{chunk::Chunk, variable::Type};
 use crate::variable::Var;
+use arch::object::Symbol;
 
 enum Expect {
     Name,
@@ -35,7 +36,8 @@ impl Display for Expect {
 }
 
 pub struct DataParser {
-    order: Vec<String>,
+    /// name and source line of every variable, in declaration order
+    order: Vec<(String, usize)>,
     vars: HashMap<String, Var>,
 }
 
@@ -161,7 +163,7 @@ impl DataParser {
             var.set_location(location);
             location += vlen as u16;
 
-            order.push(name.to_owned());
+            order.push((name.to_owned(), id));
             vars.insert(name.to_owned(), var);
         }
 
@@ -170,7 +172,7 @@ impl DataParser {
 
     pub fn data_len(&self) -> usize {
         match self.order.last() {
-            Some(key) => {
+            Some((key, _)) => {
                 let v = self.vars.get(key).unwrap();
                 *v.get_location() as usize + v.data_len()
             }
@@ -182,11 +184,22 @@ impl DataParser {
         &self.vars
     }
 
+    /// Symbols of every variable, in declaration order, for data loaded at `base`
+    pub fn symbols(&self, base: u16) -> Vec<Symbol> {
+        self.order.iter()
+            .map(|(name, line)| {
+                let var = self.vars.get(name).unwrap();
+                let size = var.data_len() as u16;
+                Symbol::variable(name.to_owned(), base + var.get_location(), size, var.type_len() as u8, *line as u16)
+            })
+            .collect()
+    }
+
     pub fn get_vec(mut self) -> Vec<u8> {
         let data_len = self.data_len();
         let mut vec = Vec::with_capacity(data_len);
 
-        for key in self.order {
+        for (key, _) in self.order {
             let var = self.vars.get_mut(&key).unwrap();
             vec.append(var.get_data_mut());
         }
@@ -199,7 +212,7 @@ impl Display for DataParser {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
         writeln!(f, ".data")?;
 
-        for var in &self.order {
+        for (var, _) in &self.order {
             let data = self.vars.get(var).unwrap();
             writeln!(f, "{} @mem:{} => {:?}", var, data.get_location(), data.get_data())?;
         }
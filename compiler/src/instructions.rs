@@ -1,23 +1,74 @@
 use arch::{instructions::*, registers::*};
-use std::{collections::HashMap, u16, u8};
+use arch::codec::{encode, Instruction};
+use arch::object::SymbolKind;
+use std::collections::{HashMap, HashSet};
+use std::cell::Cell;
 
 use crate::variable::Var;
 
+/// Symbol used by a compiled instruction, its address
+/// must be rewritten when the object is linked
+pub struct Reference {
+    pub kind: SymbolKind,
+    pub name: String,
+    /// position of the address in the compiled instruction
+    pub offset: usize,
+}
+
+/// Kind and name of the symbol resolved while compiling an instruction
+type Used = (SymbolKind, String);
+
 #[derive(Debug)]
 pub enum Ins {
     Flag(String),
     Mov(Param, Param),
+    Mov8(Param, Param),
     Add(Param, Param),
+    Sub(Param, Param),
+    Mult(Param, Param),
+    Div(Param, Param),
+    Mod(Param, Param),
+    Sdiv(Param, Param),
+    Smod(Param, Param),
     Cmp(Param, Param),
     Inc(Param),
     Dec(Param),
     Jmp(Param),
+    Jeq(Param),
     Jne(Param),
+    Jgt(Param),
+    Jge(Param),
+    Jlt(Param),
+    Jle(Param),
+    Ja(Param),
+    Jae(Param),
+    Jb(Param),
+    Jbe(Param),
     Psh(Param),
+    Psh8(Param),
     Pop(Param),
+    Pop8(Param),
+    Pushf,
+    Popf,
     Cal(Param),
+    CalArgs(Param, Param),
     Ret,
+    RetArgs(Param),
+    Jsr(Param),
+    Rts,
+    Lsf(Param, Param),
+    Rsf(Param, Param),
+    And(Param, Param),
+    Or(Param, Param),
     Xor(Param, Param),
+    Not(Param),
+    Int(Param),
+    Iret,
+    Cli,
+    Sti,
+    Sys(Param),
+    Hostcall(Param),
+    Brk,
     End,
 }
 
@@ -28,68 +79,67 @@ impl Ins {
             Some(ins) => {
                 let lower = ins.to_lowercase();
                 let ins = lower.as_str();
+                let mut param = || match seg.next() {
+                    Some(val) => Ok(Param::build_with_value(val)),
+                    None => Err(format!("missing parameter for '{}'", ins)),
+                };
 
                 match ins {
-                    "mov" => {
-                        let p1 = Param::build_with_value(seg.next().unwrap());
-                        let p2 = Param::build_with_value(seg.next().unwrap());
-
-                        Ok(Ins::Mov(p1, p2))
-                    }
-                    "add" => {
-                        let p1 = Param::build_with_value(seg.next().unwrap());
-                        let p2 = Param::build_with_value(seg.next().unwrap());
-
-                        Ok(Ins::Add(p1, p2))
-                    }
-                    "cmp" => {
-                        let p1 = Param::build_with_value(seg.next().unwrap());
-                        let p2 = Param::build_with_value(seg.next().unwrap());
-
-                        Ok(Ins::Cmp(p1, p2))
-                    }
-                    "inc" => {
-                        let p1 = Param::build_with_value(seg.next().unwrap());
-
-                        Ok(Ins::Inc(p1))
-                    }
-                    "dec" => {
-                        let p1 = Param::build_with_value(seg.next().unwrap());
-
-                        Ok(Ins::Dec(p1))
-                    }
-                    "jmp" => {
-                        let add = Param::build_with_value(seg.next().unwrap());
-
-                        Ok(Ins::Jmp(add))
-                    }
-                    "jne" => {
-                        let add = Param::build_with_value(seg.next().unwrap());
-
-                        Ok(Ins::Jne(add))
-                    }
-                    "psh" => {
-                        let val = Param::build_with_value(seg.next().unwrap());
-
-                        Ok(Ins::Psh(val))
-                    }
-                    "pop" => {
-                        let val = Param::build_with_value(seg.next().unwrap());
-
-                        Ok(Ins::Pop(val))
-                    }
+                    "mov" => Ok(Ins::Mov(param()?, param()?)),
+                    "mov8" => Ok(Ins::Mov8(param()?, param()?)),
+                    "add" => Ok(Ins::Add(param()?, param()?)),
+                    "sub" => Ok(Ins::Sub(param()?, param()?)),
+                    "mult" => Ok(Ins::Mult(param()?, param()?)),
+                    "div" => Ok(Ins::Div(param()?, param()?)),
+                    "mod" => Ok(Ins::Mod(param()?, param()?)),
+                    "sdiv" => Ok(Ins::Sdiv(param()?, param()?)),
+                    "smod" => Ok(Ins::Smod(param()?, param()?)),
+                    "cmp" => Ok(Ins::Cmp(param()?, param()?)),
+                    "inc" => Ok(Ins::Inc(param()?)),
+                    "dec" => Ok(Ins::Dec(param()?)),
+                    "jmp" => Ok(Ins::Jmp(param()?)),
+                    "jeq" => Ok(Ins::Jeq(param()?)),
+                    "jne" => Ok(Ins::Jne(param()?)),
+                    "jgt" | "jg" => Ok(Ins::Jgt(param()?)),
+                    "jge" => Ok(Ins::Jge(param()?)),
+                    "jlt" | "jl" => Ok(Ins::Jlt(param()?)),
+                    "jle" => Ok(Ins::Jle(param()?)),
+                    "ja" => Ok(Ins::Ja(param()?)),
+                    "jae" => Ok(Ins::Jae(param()?)),
+                    "jb" => Ok(Ins::Jb(param()?)),
+                    "jbe" => Ok(Ins::Jbe(param()?)),
+                    "psh" => Ok(Ins::Psh(param()?)),
+                    "psh8" => Ok(Ins::Psh8(param()?)),
+                    "pop" => Ok(Ins::Pop(param()?)),
+                    "pop8" => Ok(Ins::Pop8(param()?)),
+                    "pushf" => Ok(Ins::Pushf),
+                    "popf" => Ok(Ins::Popf),
                     "cal" => {
-                        let val = Param::build_with_value(seg.next().unwrap());
-
-                        Ok(Ins::Cal(val))
-                    }
-                    "ret" => Ok(Ins::Ret),
-                    "xor" => {
-                        let p1 = Param::build_with_value(seg.next().unwrap());
-                        let p2 = Param::build_with_value(seg.next().unwrap());
-
-                        Ok(Ins::Xor(p1, p2))
+                        let add = param()?;
+                        match param() {
+                            Ok(args) => Ok(Ins::CalArgs(add, args)),
+                            Err(_) => Ok(Ins::Cal(add)),
+                        }
                     }
+                    "ret" => match param() {
+                        Ok(args) => Ok(Ins::RetArgs(args)),
+                        Err(_) => Ok(Ins::Ret),
+                    },
+                    "jsr" => Ok(Ins::Jsr(param()?)),
+                    "rts" => Ok(Ins::Rts),
+                    "lsf" => Ok(Ins::Lsf(param()?, param()?)),
+                    "rsf" => Ok(Ins::Rsf(param()?, param()?)),
+                    "and" => Ok(Ins::And(param()?, param()?)),
+                    "or" => Ok(Ins::Or(param()?, param()?)),
+                    "xor" => Ok(Ins::Xor(param()?, param()?)),
+                    "not" => Ok(Ins::Not(param()?)),
+                    "int" => Ok(Ins::Int(param()?)),
+                    "iret" => Ok(Ins::Iret),
+                    "cli" => Ok(Ins::Cli),
+                    "sti" => Ok(Ins::Sti),
+                    "sys" => Ok(Ins::Sys(param()?)),
+                    "hostcall" => Ok(Ins::Hostcall(param()?)),
+                    "brk" => Ok(Ins::Brk),
                     "end" => Ok(Ins::End),
                     _ => {
                         let ins_l = ins.len() - 1;
@@ -107,200 +157,478 @@ impl Ins {
         }
     }
 
+    /// Compile the instruction, with the symbol it uses if any.
+    /// Names declared in `imports` are compiled with the address 0
     pub fn get_code(
         &self,
         jmps: &HashMap<String, u16>,
         reg_ptr: &mut HashMap<u8, u8>,
         vars: Option<&HashMap<String, Var>>,
         vars_add: u16,
-    ) -> Result<Vec<u8>, String> {
-        match self {
+        imports: &HashSet<String>,
+    ) -> Result<(Vec<u8>, Option<Reference>), String> {
+        Ok(match self.get_instruction(jmps, reg_ptr, vars, vars_add, imports)? {
+            Some((ins, symbol)) => {
+                let reference = symbol.map(|(kind, name)| Reference { kind, name, offset: Self::address_offset(&ins) });
+                (encode(&ins), reference)
+            }
+            None => (vec![], None),
+        })
+    }
+
+    /// Every instruction using a flag or a variable stores its address
+    /// in its jump target or else in its last 16 bits operand
+    fn address_offset(ins: &Instruction) -> usize {
+        let mut offset = 1;
+        let mut address = 1;
+
+        for operand in ins.info().operands {
+            if *operand == Operand::Addr16 {
+                return offset;
+            }
+            if operand.size() == 2 {
+                address = offset;
+            }
+            offset += operand.size();
+        }
+
+        address
+    }
+
+    /// Resolve flags and variables to build the `arch` instruction.
+    /// `Ins::Flag` isn't compiled and return `None`
+    fn get_instruction(
+        &self,
+        jmps: &HashMap<String, u16>,
+        reg_ptr: &mut HashMap<u8, u8>,
+        vars: Option<&HashMap<String, Var>>,
+        vars_add: u16,
+        imports: &HashSet<String>,
+    ) -> Result<Option<(Instruction, Option<Used>)>, String> {
+        let symbol = Cell::new(None);
+
+        // address and type size of a variable, imported ones are 16 bits
+        let var = |flag: &String| match vars.and_then(|vars| vars.get(flag)) {
+            Some(var) => {
+                symbol.set(Some((SymbolKind::Variable, flag.to_owned())));
+                Ok((vars_add + *var.get_location(), var.type_len()))
+            }
+            None if imports.contains(flag) => {
+                symbol.set(Some((SymbolKind::Import, flag.to_owned())));
+                Ok((0, 2))
+            }
+            None => Err(format!("No variable with name {}", flag)),
+        };
+        // address of a jump or a call, given by a flag or a literal
+        let jmp_add = |add: &Param| match add {
+            Param::Flag(flag) => match jmps.get(flag) {
+                Some(add) => {
+                    symbol.set(Some((SymbolKind::Label, flag.to_owned())));
+                    Ok(*add)
+                }
+                None if imports.contains(flag) => {
+                    symbol.set(Some((SymbolKind::Import, flag.to_owned())));
+                    Ok(0)
+                }
+                None => Err(format!("The flag {} dosen't exist", flag)),
+            },
+            Param::Lit(add) => Ok(*add),
+            p => Err(format!("Found an unknow instructions : {}_{}", self.name(), p)),
+        };
+
+        let ins = match self {
             // MOV_LIT_REG
-            Ins::Mov(Param::Lit(lit), Param::Reg(reg)) => Ok(vec![
-                MOV_LIT_REG, (lit >> 8) as u8, (lit & 0xFF) as u8, *reg
-            ]),
+            Ins::Mov(Param::Lit(lit), Param::Reg(reg)) => Instruction::MovLitReg(*lit, *reg),
             // MOV_LIT_MEM
-            Ins::Mov(Param::Lit(lit), Param::Mem(mem)) => Ok(vec![
-                MOV_LIT_MEM16, (lit >> 8) as u8, (lit & 0xFF) as u8, (mem >> 8) as u8, (mem & 0xFF) as u8
-            ]),
+            Ins::Mov(Param::Lit(lit), Param::Mem(mem)) => Instruction::MovLitMem16(*lit, *mem),
+            Ins::Mov8(Param::Lit(lit), Param::Mem(mem)) => Instruction::MovLitMem8(*lit, *mem),
             // MOV_LIT_PTR{}
             Ins::Mov(Param::Lit(lit), Param::Ptr(ptr)) => match ptr.as_ref() {
                 // MOV_LIT_PTRflag => MOV_LIT_MEM
-                Param::Flag(flag) => match vars {
-                    Some(vars) => match vars.get(flag) {
-                        Some(var) => {
-                            let mov_lit_mem = match var.type_len() {
-                                1 => MOV_LIT_MEM8,
-                                _ => MOV_LIT_MEM16,
-                            };
-                            let var_add = vars_add + *var.get_location();
-                            Ok(vec![mov_lit_mem, (lit >> 8) as u8, (lit & 0xFF) as u8, (var_add >> 8) as u8, (var_add & 0xFF) as u8])
-                        }
-                        None => Err(format!("No variable with name {}", flag)),
-                    },
-                    None => Err(format!("No variable with name {}", flag)),
-                },
-                p => Err(format!("Found an unknow instructions : MOV_LIT_PTR{}", p)),
+                Param::Flag(flag) => {
+                    let (var_add, type_len) = var(flag)?;
+                    match type_len {
+                        1 => Instruction::MovLitMem8(*lit, var_add),
+                        _ => Instruction::MovLitMem16(*lit, var_add),
+                    }
+                }
+                p => return Err(format!("Found an unknow instructions : MOV_LIT_PTR{}", p)),
             },
             // MOV_REG_REG
-            Ins::Mov(Param::Reg(r1), Param::Reg(r2)) => Ok(vec![MOV_REG_REG, *r1, *r2]),
+            Ins::Mov(Param::Reg(r1), Param::Reg(r2)) => Instruction::MovRegReg(*r1, *r2),
             // MOV_REG_MEM
-            Ins::Mov(Param::Reg(reg), Param::Mem(mem)) => Ok(vec![
-                MOV_REG_MEM, *reg, (mem >> 8) as u8, (mem & 0xFF) as u8
-            ]),
+            Ins::Mov(Param::Reg(reg), Param::Mem(mem)) => Instruction::MovRegMem(*reg, *mem),
             // MOV_MEM_REG
-            Ins::Mov(Param::Mem(mem), Param::Reg(reg)) => Ok(vec![
-                MOV_MEM_REG, (mem >> 8) as u8, (mem & 0xFF) as u8, *reg
-            ]),
+            Ins::Mov(Param::Mem(mem), Param::Reg(reg)) => Instruction::MovMemReg(*mem, *reg),
+            // MOV_MEM_MEM
+            Ins::Mov(Param::Mem(m1), Param::Mem(m2)) => Instruction::MovMemMem16(*m1, *m2),
+            Ins::Mov8(Param::Mem(m1), Param::Mem(m2)) => Instruction::MovMemMem8(*m1, *m2),
+            // MOV_flag_REG with the address of a label, used to install interrupt handlers
+            Ins::Mov(label @ Param::Flag(flag), Param::Reg(reg))
+                if jmps.contains_key(flag) && !vars.is_some_and(|vars| vars.contains_key(flag)) => {
+                Instruction::MovLitReg(jmp_add(label)?, *reg)
+            }
             // MOV_flag_REG
-            Ins::Mov(Param::Flag(flag), Param::Reg(reg)) => match vars {
-                Some(vars) => match vars.get(flag) {
-                    Some(var) => {
-                        let var_add = vars_add + *var.get_location();
-                        let size = var.type_len() as u8;
-                        reg_ptr.insert(*reg, size);
-
-                        Ok(vec![MOV_LIT_REG, (var_add >> 8) as u8, (var_add & 0xFF) as u8, *reg])
-                    }
-                    None => Err(format!("No variable with name {}", flag)),
-                },
-                None => Err(format!("No variable with name {}", flag)),
-            },
+            Ins::Mov(Param::Flag(flag), Param::Reg(reg)) => {
+                let (var_add, type_len) = var(flag)?;
+                reg_ptr.insert(*reg, type_len as u8);
+
+                Instruction::MovLitReg(var_add, *reg)
+            }
             // MOV_PTR{}_REG
             Ins::Mov(Param::Ptr(ptr), Param::Reg(r2)) => match ptr.as_ref() {
                 // MOV_PTRREG_REG
-                Param::Reg(r1) => Ok(vec![MOV_PTRREG_REG, *r1, *r2]),
+                Param::Reg(r1) => Instruction::MovPtrRegReg(*r1, *r2),
                 // MOV_PTR{var}_REG => MOV_MEM_REG
-                Param::Flag(flag) => match vars {
-                    Some(vars) => match vars.get(flag) {
-                        Some(var) => {
-                            let var_add = vars_add + *var.get_location();
-                            Ok(vec![
-                                MOV_MEM_REG, (var_add >> 8) as u8, (var_add & 0xFF) as u8, *r2
-                            ])
-                        }
-                        None => Err(format!("No variable with name {}", flag)),
-                    },
-                    None => Err(format!("No variable with name {}", flag)),
-                },
-                p => Err(format!("Found an unknow instructions : MOV_PTR{}_REG", p)),
+                Param::Flag(flag) => Instruction::MovMemReg(var(flag)?.0, *r2),
+                p => return Err(format!("Found an unknow instructions : MOV_PTR{}_REG", p)),
             },
             // MOV_REG_PTR{}
             Ins::Mov(Param::Reg(r1), Param::Ptr(ptr)) => match ptr.as_ref() {
                 // MOV_REG_PTRREG
-                Param::Reg(r2) => Ok(vec![MOV_REG_PTRREG, *r1, *r2]),
-                p => Err(format!("Found an unknow instructions : MOV_REG_PTR{}", p)),
+                Param::Reg(r2) => Instruction::MovRegPtrReg(*r1, *r2),
+                p => return Err(format!("Found an unknow instructions : MOV_REG_PTR{}", p)),
+            },
+            // MOV_LITOFF_REG
+            Ins::Mov(Param::Off(base, off), Param::Reg(reg)) => match base.as_ref() {
+                Param::Lit(lit) => Instruction::MovLitOffReg(*lit, *off, *reg),
+                // MOV_{var}OFF_REG => MOV_LITOFF_REG
+                Param::Flag(flag) => Instruction::MovLitOffReg(var(flag)?.0, *off, *reg),
+                p => return Err(format!("Found an unknow instructions : MOV_{}OFF_REG", p)),
+            },
+            // MOV_REG_LITOFF
+            Ins::Mov(Param::Reg(reg), Param::Off(base, off)) => match base.as_ref() {
+                Param::Lit(lit) => Instruction::MovRegLitOff(*reg, *lit, *off),
+                // MOV_REG_{var}OFF => MOV_REG_LITOFF
+                Param::Flag(flag) => Instruction::MovRegLitOff(*reg, var(flag)?.0, *off),
+                p => return Err(format!("Found an unknow instructions : MOV_REG_{}OFF", p)),
             },
 
             // ADD_REG_REG
-            Ins::Add(Param::Reg(r1), Param::Reg(r2)) => Ok(vec![ADD_REG_REG, *r1, *r2]),
+            Ins::Add(Param::Reg(r1), Param::Reg(r2)) => Instruction::AddRegReg(*r1, *r2),
             // ADD_REG_LIT
-            Ins::Add(Param::Reg(reg), Param::Lit(lit)) => Ok(vec![ADD_REG_LIT, *reg, (lit >> 8) as u8, (lit & 0xFF) as u8]),
+            Ins::Add(Param::Reg(reg), Param::Lit(lit)) => Instruction::AddRegLit(*reg, *lit),
+            // SUB_REG_REG
+            Ins::Sub(Param::Reg(r1), Param::Reg(r2)) => Instruction::SubRegReg(*r1, *r2),
+            // SUB_REG_LIT
+            Ins::Sub(Param::Reg(reg), Param::Lit(lit)) => Instruction::SubRegLit(*reg, *lit),
+            // SUB_LIT_REG
+            Ins::Sub(Param::Lit(lit), Param::Reg(reg)) => Instruction::SubLitReg(*lit, *reg),
+            // MUL_REG_REG
+            Ins::Mult(Param::Reg(r1), Param::Reg(r2)) => Instruction::MulRegReg(*r1, *r2),
+            // MUL_REG_LIT
+            Ins::Mult(Param::Reg(reg), Param::Lit(lit)) => Instruction::MulRegLit(*reg, *lit),
+            // DIV_REG_REG
+            Ins::Div(Param::Reg(r1), Param::Reg(r2)) => Instruction::DivRegReg(*r1, *r2),
+            // DIV_REG_LIT
+            Ins::Div(Param::Reg(reg), Param::Lit(lit)) => Instruction::DivRegLit(*reg, *lit),
+            // MOD_REG_REG
+            Ins::Mod(Param::Reg(r1), Param::Reg(r2)) => Instruction::ModRegReg(*r1, *r2),
+            // MOD_REG_LIT
+            Ins::Mod(Param::Reg(reg), Param::Lit(lit)) => Instruction::ModRegLit(*reg, *lit),
+            // SDIV_REG_REG
+            Ins::Sdiv(Param::Reg(r1), Param::Reg(r2)) => Instruction::SdivRegReg(*r1, *r2),
+            // SDIV_REG_LIT
+            Ins::Sdiv(Param::Reg(reg), Param::Lit(lit)) => Instruction::SdivRegLit(*reg, *lit),
+            // SMOD_REG_REG
+            Ins::Smod(Param::Reg(r1), Param::Reg(r2)) => Instruction::SmodRegReg(*r1, *r2),
+            // SMOD_REG_LIT
+            Ins::Smod(Param::Reg(reg), Param::Lit(lit)) => Instruction::SmodRegLit(*reg, *lit),
             // CMP_REG_REG
-            Ins::Cmp(Param::Reg(r1), Param::Reg(r2)) => Ok(vec![CMP_REG_REG, *r1, *r2]),
+            Ins::Cmp(Param::Reg(r1), Param::Reg(r2)) => Instruction::CmpRegReg(*r1, *r2),
             // CMP_REG_LIT
-            Ins::Cmp(Param::Reg(reg), Param::Lit(lit)) => Ok(vec![CMP_REG_LIT, *reg, (lit >> 8) as u8, (lit & 0xFF) as u8]),
+            Ins::Cmp(Param::Reg(reg), Param::Lit(lit)) => Instruction::CmpRegLit(*reg, *lit),
             // INC_REG
-            Ins::Inc(Param::Reg(reg)) => Ok(vec![INC_REG, *reg]),
+            Ins::Inc(Param::Reg(reg)) => Instruction::IncReg(*reg),
             // DEC_REG
-            Ins::Dec(Param::Reg(reg)) => Ok(vec![DEC_REG, *reg]),
-
-            // JMP_flag
-            Ins::Jmp(Param::Flag(flag)) => match jmps.get(flag) {
-                Some(add) => Ok(vec![JMP_LIT, (add >> 8) as u8, (add & 0xFF) as u8]),
-                None => Err(format!("The flag {} dosen't exist", flag)),
-            },
-            // JMP_LIT
-            Ins::Jmp(Param::Lit(add)) => Ok(vec![JMP_LIT, (add >> 8) as u8, (add & 0xFF) as u8]),
-
-            // JNE_LIT_flag
-            Ins::Jne(Param::Flag(flag)) => match jmps.get(flag) {
-                Some(add) => Ok(vec![
-                    JNE_LIT, (add >> 8) as u8, (add & 0xFF) as u8
-                ]),
-                None => Err(format!("The flag {} dosen't exist", flag)),
-            },
-            // JNE_LIT_LIT
-            Ins::Jne(Param::Lit(add)) => Ok(vec![
-                JNE_LIT, (add >> 8) as u8, (add & 0xFF) as u8
-            ]),
+            Ins::Dec(Param::Reg(reg)) => Instruction::DecReg(*reg),
+
+            // J{}_REG
+            Ins::Jmp(Param::Reg(reg)) => Instruction::JmpReg(*reg),
+            Ins::Jeq(Param::Reg(reg)) => Instruction::JeqReg(*reg),
+            Ins::Jne(Param::Reg(reg)) => Instruction::JneReg(*reg),
+            Ins::Jgt(Param::Reg(reg)) => Instruction::JgtReg(*reg),
+            Ins::Jge(Param::Reg(reg)) => Instruction::JgeReg(*reg),
+            Ins::Jlt(Param::Reg(reg)) => Instruction::JltReg(*reg),
+            Ins::Jle(Param::Reg(reg)) => Instruction::JleReg(*reg),
+            Ins::Ja(Param::Reg(reg)) => Instruction::JaReg(*reg),
+            Ins::Jae(Param::Reg(reg)) => Instruction::JaeReg(*reg),
+            Ins::Jb(Param::Reg(reg)) => Instruction::JbReg(*reg),
+            Ins::Jbe(Param::Reg(reg)) => Instruction::JbeReg(*reg),
+            // J{}_LIT, with a literal or a flag
+            Ins::Jmp(add) => Instruction::JmpLit(jmp_add(add)?),
+            Ins::Jeq(add) => Instruction::JeqLit(jmp_add(add)?),
+            Ins::Jne(add) => Instruction::JneLit(jmp_add(add)?),
+            Ins::Jgt(add) => Instruction::JgtLit(jmp_add(add)?),
+            Ins::Jge(add) => Instruction::JgeLit(jmp_add(add)?),
+            Ins::Jlt(add) => Instruction::JltLit(jmp_add(add)?),
+            Ins::Jle(add) => Instruction::JleLit(jmp_add(add)?),
+            Ins::Ja(add) => Instruction::JaLit(jmp_add(add)?),
+            Ins::Jae(add) => Instruction::JaeLit(jmp_add(add)?),
+            Ins::Jb(add) => Instruction::JbLit(jmp_add(add)?),
+            Ins::Jbe(add) => Instruction::JbeLit(jmp_add(add)?),
 
             // PSH_LIT
-            Ins::Psh(Param::Lit(lit)) => Ok(vec![PSH_LIT, (lit >> 8) as u8, (lit & 0xFF) as u8]),
+            Ins::Psh(Param::Lit(lit)) => Instruction::PshLit(*lit),
             // PSH_REG
-            Ins::Psh(Param::Reg(reg)) => Ok(vec![PSH_REG, *reg]),
+            Ins::Psh(Param::Reg(reg)) => Instruction::PshReg(*reg),
             // PSH_MEM
-            Ins::Psh(Param::Mem(mem)) => Ok(vec![PSH_LIT, (mem >> 8) as u8, (mem & 0xFF) as u8]),
+            Ins::Psh(Param::Mem(mem)) => Instruction::PshMem16(*mem),
+            Ins::Psh8(Param::Mem(mem)) => Instruction::PshMem8(*mem),
             // PSH_PTR{}
             Ins::Psh(Param::Ptr(ptr)) => match ptr.as_ref() {
-                // PSH_PTRREG
+                // PSH_PTRREG, 16 bits unless the register points to a u8 variable
                 Param::Reg(reg) => match reg_ptr.get(reg) {
-                    None => Err("PSH_PTRREG but reg isn't a ptr".to_owned()),
-                    Some(1) => Ok(vec![PSH_PTRREG8, *reg]),
-                    _ => Ok(vec![PSH_PTRREG16, *reg]),
+                    Some(1) => Instruction::PshPtrReg8(*reg),
+                    _ => Instruction::PshPtrReg16(*reg),
                 }
-                p => Err(format!("Found an unknow instructions : PSH_PTR{}", p)),
+                p => return Err(format!("Found an unknow instructions : PSH_PTR{}", p)),
+            },
+            Ins::Psh8(Param::Ptr(ptr)) => match ptr.as_ref() {
+                Param::Reg(reg) => Instruction::PshPtrReg8(*reg),
+                p => return Err(format!("Found an unknow instructions : PSH8_PTR{}", p)),
             },
 
             // POP_REG
-            Ins::Pop(Param::Reg(reg)) => Ok(vec![POP_REG, *reg]),
+            Ins::Pop(Param::Reg(reg)) => Instruction::PopReg(*reg),
+            // POP_MEM
+            Ins::Pop(Param::Mem(mem)) => Instruction::PopMem16(*mem),
+            Ins::Pop8(Param::Mem(mem)) => Instruction::PopMem8(*mem),
             // POP_PTR{}
             Ins::Pop(Param::Ptr(ptr)) => match ptr.as_ref() {
-                // PSH_PTRREG
+                // POP_PTRREG, 16 bits unless the register points to a u8 variable
                 Param::Reg(reg) => match reg_ptr.get(reg) {
-                    None => Err("POP_PTRREG but reg isn't a ptr".to_owned()),
-                    Some(1) => Ok(vec![POP_PTRREG8, *reg]),
-                    _ => Ok(vec![POP_PTRREG16, *reg]),
+                    Some(1) => Instruction::PopPtrReg8(*reg),
+                    _ => Instruction::PopPtrReg16(*reg),
                 }
-                p => Err(format!("Found an unknow instructions : POP_PTR{}", p)),
+                p => return Err(format!("Found an unknow instructions : POP_PTR{}", p)),
             },
-
-            // CAL_flag
-            Ins::Cal(Param::Flag(flag)) => match jmps.get(flag) {
-                Some(add) => Ok(vec![CALL_LIT, (add >> 8) as u8, (add & 0xFF) as u8]),
-                None => Err(format!("The flag {} dosen't exist", flag)),
+            Ins::Pop8(Param::Ptr(ptr)) => match ptr.as_ref() {
+                Param::Reg(reg) => Instruction::PopPtrReg8(*reg),
+                p => return Err(format!("Found an unknow instructions : POP8_PTR{}", p)),
             },
-            // CAL_LIT
-            Ins::Cal(Param::Lit(lit)) => Ok(vec![CALL_LIT, (lit >> 8) as u8, (lit & 0xFF) as u8]),
+            // PUSHF, POPF
+            Ins::Pushf => Instruction::Pushf,
+            Ins::Popf => Instruction::Popf,
+
             // CAL_REG
-            Ins::Cal(Param::Reg(reg)) => Ok(vec![CALL_REG, *reg]),
+            Ins::Cal(Param::Reg(reg)) => Instruction::CallReg(*reg),
+            // CAL_LIT, with a literal or a flag
+            Ins::Cal(add) => Instruction::CallLit(jmp_add(add)?),
+            // CAL_REG_ARGS
+            Ins::CalArgs(Param::Reg(reg), Param::Lit(args)) => Instruction::CallRegArgs(*reg, *args),
+            // CAL_LIT_ARGS, with a literal or a flag
+            Ins::CalArgs(add, Param::Lit(args)) => Instruction::CallLitArgs(jmp_add(add)?, *args),
 
             // RET
-            Ins::Ret => Ok(vec![RET]),
-
+            Ins::Ret => Instruction::Ret,
+            // RET_LIT
+            Ins::RetArgs(Param::Lit(args)) => Instruction::RetLit(*args),
+
+            // JSR_REG
+            Ins::Jsr(Param::Reg(reg)) => Instruction::JsrReg(*reg),
+            // JSR_LIT, with a literal or a flag
+            Ins::Jsr(add) => Instruction::JsrLit(jmp_add(add)?),
+            // RTS
+            Ins::Rts => Instruction::Rts,
+
+            // LSF_REG_REG
+            Ins::Lsf(Param::Reg(r1), Param::Reg(r2)) => Instruction::LsfRegReg(*r1, *r2),
+            // LSF_REG_LIT
+            Ins::Lsf(Param::Reg(reg), Param::Lit(lit)) => Instruction::LsfRegLit(*reg, *lit),
+            // RSF_REG_REG
+            Ins::Rsf(Param::Reg(r1), Param::Reg(r2)) => Instruction::RsfRegReg(*r1, *r2),
+            // RSF_REG_LIT
+            Ins::Rsf(Param::Reg(reg), Param::Lit(lit)) => Instruction::RsfRegLit(*reg, *lit),
+            // AND_REG_REG
+            Ins::And(Param::Reg(r1), Param::Reg(r2)) => Instruction::AndRegReg(*r1, *r2),
+            // AND_REG_LIT
+            Ins::And(Param::Reg(reg), Param::Lit(lit)) => Instruction::AndRegLit(*reg, *lit),
+            // OR_REG_REG
+            Ins::Or(Param::Reg(r1), Param::Reg(r2)) => Instruction::OrRegReg(*r1, *r2),
+            // OR_REG_LIT
+            Ins::Or(Param::Reg(reg), Param::Lit(lit)) => Instruction::OrRegLit(*reg, *lit),
             // XOR_REG_REG
-            Ins::Xor(Param::Reg(r1), Param::Reg(r2)) => Ok(vec![XOR_REG_REG, *r1, *r2]),
+            Ins::Xor(Param::Reg(r1), Param::Reg(r2)) => Instruction::XorRegReg(*r1, *r2),
             // XOR_REG_LIT
-            Ins::Xor(Param::Reg(reg), Param::Lit(lit)) => Ok(vec![
-                XOR_REG_LIT,
-                *reg,
-                (lit >> 8) as u8,
-                (lit & 0xFF) as u8,
-            ]),
-
+            Ins::Xor(Param::Reg(reg), Param::Lit(lit)) => Instruction::XorRegLit(*reg, *lit),
+            // NOT
+            Ins::Not(Param::Reg(reg)) => Instruction::Not(*reg),
+
+            // INT_LIT
+            Ins::Int(Param::Lit(vector)) => Instruction::IntLit(*vector),
+            // SYS_LIT
+            Ins::Sys(Param::Lit(syscall)) => Instruction::SysLit(*syscall),
+            // HOSTCALL_LIT
+            Ins::Hostcall(Param::Lit(id)) => Instruction::HostcallLit(*id),
+            // IRET
+            Ins::Iret => Instruction::Iret,
+            // CLI
+            Ins::Cli => Instruction::Cli,
+            // STI
+            Ins::Sti => Instruction::Sti,
+
+            // BRK
+            Ins::Brk => Instruction::Brk,
             // END
-            Ins::End => Ok(vec![END]),
-            Ins::Flag(_) => Ok(vec![]),
-            ins => Err(format!("Found an unknow instructions : {}", ins)),
-        }
+            Ins::End => Instruction::End,
+            Ins::Flag(_) => return Ok(None),
+            ins => return Err(format!("Found an unknow instructions : {}", ins)),
+        };
+
+        Ok(Some((ins, symbol.into_inner())))
+    }
+
+    /// Opcode selected by the shape of the parameters. Labels and variables
+    /// don't need to be resolved, so it can be used to compute addresses.
+    /// 8/16 bits variants have the same size, the 16 bits one is returned.
+    fn opcode(&self) -> Option<u8> {
+        use Param::*;
+
+        let code = match self {
+            Ins::Mov(Lit(_), Reg(_)) | Ins::Mov(Flag(_), Reg(_)) => MOV_LIT_REG,
+            Ins::Mov(Lit(_), Mem(_)) | Ins::Mov8(Lit(_), Mem(_)) => MOV_LIT_MEM16,
+            Ins::Mov(Lit(_), Ptr(ptr)) if matches!(**ptr, Flag(_)) => MOV_LIT_MEM16,
+            Ins::Mov(Reg(_), Reg(_)) => MOV_REG_REG,
+            Ins::Mov(Reg(_), Mem(_)) => MOV_REG_MEM,
+            Ins::Mov(Mem(_), Reg(_)) => MOV_MEM_REG,
+            Ins::Mov(Mem(_), Mem(_)) | Ins::Mov8(Mem(_), Mem(_)) => MOV_MEM_MEM_16,
+            Ins::Mov(Ptr(ptr), Reg(_)) => match **ptr {
+                Reg(_) => MOV_PTRREG_REG,
+                Flag(_) => MOV_MEM_REG,
+                _ => return None,
+            },
+            Ins::Mov(Reg(_), Ptr(ptr)) if matches!(**ptr, Reg(_)) => MOV_REG_PTRREG,
+            Ins::Mov(Off(..), Reg(_)) => MOV_LITOFF_REG,
+            Ins::Mov(Reg(_), Off(..)) => MOV_REG_LITOFF,
+            Ins::Add(Reg(_), Reg(_)) => ADD_REG_REG,
+            Ins::Add(Reg(_), Lit(_)) => ADD_REG_LIT,
+            Ins::Sub(Reg(_), Reg(_)) => SUB_REG_REG,
+            Ins::Sub(Reg(_), Lit(_)) => SUB_REG_LIT,
+            Ins::Sub(Lit(_), Reg(_)) => SUB_LIT_REG,
+            Ins::Mult(Reg(_), Reg(_)) => MUL_REG_REG,
+            Ins::Mult(Reg(_), Lit(_)) => MUL_REG_LIT,
+            Ins::Div(Reg(_), Reg(_)) => DIV_REG_REG,
+            Ins::Div(Reg(_), Lit(_)) => DIV_REG_LIT,
+            Ins::Mod(Reg(_), Reg(_)) => MOD_REG_REG,
+            Ins::Mod(Reg(_), Lit(_)) => MOD_REG_LIT,
+            Ins::Sdiv(Reg(_), Reg(_)) => SDIV_REG_REG,
+            Ins::Sdiv(Reg(_), Lit(_)) => SDIV_REG_LIT,
+            Ins::Smod(Reg(_), Reg(_)) => SMOD_REG_REG,
+            Ins::Smod(Reg(_), Lit(_)) => SMOD_REG_LIT,
+            Ins::Cmp(Reg(_), Reg(_)) => CMP_REG_REG,
+            Ins::Cmp(Reg(_), Lit(_)) => CMP_REG_LIT,
+            Ins::Inc(Reg(_)) => INC_REG,
+            Ins::Dec(Reg(_)) => DEC_REG,
+            Ins::Jmp(Reg(_)) | Ins::Jeq(Reg(_)) | Ins::Jne(Reg(_)) | Ins::Jgt(Reg(_))
+            | Ins::Jge(Reg(_)) | Ins::Jlt(Reg(_)) | Ins::Jle(Reg(_)) | Ins::Ja(Reg(_))
+            | Ins::Jae(Reg(_)) | Ins::Jb(Reg(_)) | Ins::Jbe(Reg(_)) => JMP_REG,
+            Ins::Jmp(Flag(_) | Lit(_)) | Ins::Jeq(Flag(_) | Lit(_)) | Ins::Jne(Flag(_) | Lit(_))
+            | Ins::Jgt(Flag(_) | Lit(_)) | Ins::Jge(Flag(_) | Lit(_)) | Ins::Jlt(Flag(_) | Lit(_))
+            | Ins::Jle(Flag(_) | Lit(_)) | Ins::Ja(Flag(_) | Lit(_)) | Ins::Jae(Flag(_) | Lit(_))
+            | Ins::Jb(Flag(_) | Lit(_)) | Ins::Jbe(Flag(_) | Lit(_)) => JMP_LIT,
+            Ins::Psh(Lit(_)) => PSH_LIT,
+            Ins::Psh(Reg(_)) => PSH_REG,
+            Ins::Psh(Mem(_)) | Ins::Psh8(Mem(_)) => PSH_MEM16,
+            Ins::Psh(Ptr(ptr)) | Ins::Psh8(Ptr(ptr)) if matches!(**ptr, Reg(_)) => PSH_PTRREG16,
+            Ins::Pop(Reg(_)) => POP_REG,
+            Ins::Pop(Mem(_)) | Ins::Pop8(Mem(_)) => POP_MEM16,
+            Ins::Pop(Ptr(ptr)) | Ins::Pop8(Ptr(ptr)) if matches!(**ptr, Reg(_)) => POP_PTRREG16,
+            Ins::Pushf => PUSHF,
+            Ins::Popf => POPF,
+            Ins::Cal(Flag(_) | Lit(_)) => CALL_LIT,
+            Ins::Cal(Reg(_)) => CALL_REG,
+            Ins::CalArgs(Flag(_) | Lit(_), Lit(_)) => CALL_LIT_ARGS,
+            Ins::CalArgs(Reg(_), Lit(_)) => CALL_REG_ARGS,
+            Ins::Ret => RET,
+            Ins::RetArgs(Lit(_)) => RET_LIT,
+            Ins::Jsr(Flag(_) | Lit(_)) => JSR_LIT,
+            Ins::Jsr(Reg(_)) => JSR_REG,
+            Ins::Rts => RTS,
+            Ins::Lsf(Reg(_), Reg(_)) => LSF_REG_REG,
+            Ins::Lsf(Reg(_), Lit(_)) => LSF_REG_LIT,
+            Ins::Rsf(Reg(_), Reg(_)) => RSF_REG_REG,
+            Ins::Rsf(Reg(_), Lit(_)) => RSF_REG_LIT,
+            Ins::And(Reg(_), Reg(_)) => AND_REG_REG,
+            Ins::And(Reg(_), Lit(_)) => AND_REG_LIT,
+            Ins::Or(Reg(_), Reg(_)) => OR_REG_REG,
+            Ins::Or(Reg(_), Lit(_)) => OR_REG_LIT,
+            Ins::Xor(Reg(_), Reg(_)) => XOR_REG_REG,
+            Ins::Xor(Reg(_), Lit(_)) => XOR_REG_LIT,
+            Ins::Not(Reg(_)) => NOT,
+            Ins::Int(Lit(_)) => INT_LIT,
+            Ins::Iret => IRET,
+            Ins::Cli => CLI,
+            Ins::Sti => STI,
+            Ins::Sys(Lit(_)) => SYS_LIT,
+            Ins::Hostcall(Lit(_)) => HOSTCALL_LIT,
+            Ins::Brk => BRK,
+            Ins::End => END,
+            _ => return None,
+        };
+
+        Some(code)
     }
 
+    /// Size of the instruction once compiled, taken from the `arch` opcode table.
+    /// Flags and malformed instructions have no size.
     pub fn ins_len(&self) -> usize {
+        self.opcode()
+            .and_then(opcode_info)
+            .map_or(0, |info| info.size())
+    }
+
+    /// Keyword of the instruction, as written in the source
+    fn name(&self) -> &'static str {
         match self {
-            Ins::Flag(_) => 0,
-            Ins::Mov(p1, p2) => 1 + p1.param_len() + p2.param_len(),
-            Ins::Add(p1, p2) => 1 + p1.param_len() + p2.param_len(),
-            Ins::Cmp(p1, p2) => 1 + p1.param_len() + p2.param_len(),
-            Ins::Inc(p1) => 1 + p1.param_len(),
-            Ins::Dec(p1) => 1 + p1.param_len(),
-            Ins::Jmp(p1) => 1 + p1.param_len(),
-            Ins::Jne(p1) => 1 + p1.param_len(),
-            Ins::Psh(p1) => 1 + p1.param_len(),
-            Ins::Pop(p1) => 1 + p1.param_len(),
-            Ins::Cal(p1) => 1 + p1.param_len(),
-            Ins::Ret => 1,
-            Ins::Xor(p1, p2) => 1 + p1.param_len() + p2.param_len(),
-            Ins::End => 1,
+            Ins::Flag(_) => "FLAG",
+            Ins::Mov(..) => "MOV",
+            Ins::Mov8(..) => "MOV8",
+            Ins::Add(..) => "ADD",
+            Ins::Sub(..) => "SUB",
+            Ins::Mult(..) => "MULT",
+            Ins::Div(..) => "DIV",
+            Ins::Mod(..) => "MOD",
+            Ins::Sdiv(..) => "SDIV",
+            Ins::Smod(..) => "SMOD",
+            Ins::Cmp(..) => "CMP",
+            Ins::Inc(_) => "INC",
+            Ins::Dec(_) => "DEC",
+            Ins::Jmp(_) => "JMP",
+            Ins::Jeq(_) => "JEQ",
+            Ins::Jne(_) => "JNE",
+            Ins::Jgt(_) => "JGT",
+            Ins::Jge(_) => "JGE",
+            Ins::Jlt(_) => "JLT",
+            Ins::Jle(_) => "JLE",
+            Ins::Ja(_) => "JA",
+            Ins::Jae(_) => "JAE",
+            Ins::Jb(_) => "JB",
+            Ins::Jbe(_) => "JBE",
+            Ins::Psh(_) => "PSH",
+            Ins::Psh8(_) => "PSH8",
+            Ins::Pop(_) => "POP",
+            Ins::Pop8(_) => "POP8",
+            Ins::Pushf => "PUSHF",
+            Ins::Popf => "POPF",
+            Ins::Cal(_) | Ins::CalArgs(..) => "CAL",
+            Ins::Ret | Ins::RetArgs(_) => "RET",
+            Ins::Jsr(_) => "JSR",
+            Ins::Rts => "RTS",
+            Ins::Lsf(..) => "LSF",
+            Ins::Rsf(..) => "RSF",
+            Ins::And(..) => "AND",
+            Ins::Or(..) => "OR",
+            Ins::Xor(..) => "XOR",
+            Ins::Not(_) => "NOT",
+            Ins::Int(_) => "INT",
+            Ins::Iret => "IRET",
+            Ins::Cli => "CLI",
+            Ins::Sti => "STI",
+            Ins::Sys(_) => "SYS",
+            Ins::Hostcall(_) => "HOSTCALL",
+            Ins::Brk => "BRK",
+            Ins::End => "END",
         }
     }
 }
@@ -309,19 +637,22 @@ impl std::fmt::Display for Ins {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
             Ins::Flag(name) => write!(f, "FLAG{{{}}}", name),
-            Ins::Mov(p1, p2) => write!(f, "MOV_{}_{}", p1, p2),
-            Ins::Add(p1, p2) => write!(f, "ADD_{}_{}", p1, p2),
-            Ins::Cmp(p1, p2) => write!(f, "CMP_{}_{}", p1, p2),
-            Ins::Inc(p1) => write!(f, "INC_{}", p1),
-            Ins::Dec(p1) => write!(f, "DEC_{}", p1),
-            Ins::Jmp(p1) => write!(f, "JMP_{}", p1),
-            Ins::Jne(p1) => write!(f, "JNE_{}", p1),
-            Ins::Psh(p1) => write!(f, "PSH_{}", p1),
-            Ins::Pop(p1) => write!(f, "POP_{}", p1),
-            Ins::Cal(p1) => write!(f, "CAL_{}", p1),
-            Ins::Ret => write!(f, "RET"),
-            Ins::Xor(p1, p2) => write!(f, "XOR_{}_{}", p1, p2),
-            Ins::End => write!(f, "END"),
+            Ins::Mov(p1, p2) | Ins::Mov8(p1, p2) | Ins::Add(p1, p2) | Ins::Sub(p1, p2)
+            | Ins::Mult(p1, p2) | Ins::Div(p1, p2) | Ins::Mod(p1, p2) | Ins::Sdiv(p1, p2)
+            | Ins::Smod(p1, p2) | Ins::Cmp(p1, p2) | Ins::Lsf(p1, p2) | Ins::Rsf(p1, p2)
+            | Ins::And(p1, p2) | Ins::Or(p1, p2) | Ins::Xor(p1, p2) | Ins::CalArgs(p1, p2) => {
+                write!(f, "{}_{}_{}", self.name(), p1, p2)
+            }
+            Ins::Inc(p1) | Ins::Dec(p1) | Ins::Jmp(p1) | Ins::Jeq(p1) | Ins::Jne(p1)
+            | Ins::Jgt(p1) | Ins::Jge(p1) | Ins::Jlt(p1) | Ins::Jle(p1) | Ins::Ja(p1)
+            | Ins::Jae(p1) | Ins::Jb(p1) | Ins::Jbe(p1) | Ins::Psh(p1) | Ins::Psh8(p1)
+            | Ins::Pop(p1) | Ins::Pop8(p1) | Ins::Cal(p1) | Ins::RetArgs(p1) | Ins::Jsr(p1)
+            | Ins::Not(p1) | Ins::Int(p1) | Ins::Sys(p1) | Ins::Hostcall(p1) => {
+                write!(f, "{}_{}", self.name(), p1)
+            }
+            Ins::Ret | Ins::Rts | Ins::Pushf | Ins::Popf | Ins::Iret | Ins::Cli | Ins::Sti | Ins::Brk | Ins::End => {
+                write!(f, "{}", self.name())
+            }
         }
     }
 }
@@ -330,6 +661,7 @@ impl std::fmt::Display for Ins {
 pub enum Param {
     Flag(String),
     Ptr(Box<Param>),
+    Off(Box<Param>, u8),
     Lit(u16),
     Mem(u16),
     Reg(u8),
@@ -343,7 +675,25 @@ impl Param {
         let memory = v0 == "#";
 
         if v0 == "*" {
-            return Param::Ptr(Box::from(Param::build_with_value(val.get(1..).unwrap())));
+            let ptr = val.get(1..).unwrap();
+
+            // register with a literal offset, like `*fp+4` or `*fp-2`
+            if let Some(id) = ptr.find(['+', '-']).filter(|id| *id > 0 && *id + 1 < ptr.len()) {
+                let (reg, off) = (Param::build_with_value(&ptr[..id]), Param::build_with_value(&ptr[id + 1..]));
+                if let (Param::Reg(reg), Param::Lit(off)) = (reg, off) {
+                    let off = if &ptr[id..=id] == "-" { off.wrapping_neg() } else { off };
+                    return Param::Off(Box::from(Param::Lit(off)), reg);
+                }
+            }
+
+            return Param::Ptr(Box::from(Param::build_with_value(ptr)));
+        }
+
+        // literal or variable with a register as offset, like `0x1400+ax`
+        if let Some((base, offset)) = val.split_once('+') {
+            if let Param::Reg(reg) = Param::build_with_value(offset) {
+                return Param::Off(Box::from(Param::build_with_value(base)), reg);
+            }
         }
 
         // if val has only one char, it's a base10 literal or flag. for sure
@@ -393,15 +743,6 @@ impl Param {
             },
         }
     }
-
-    pub fn param_len(&self) -> usize {
-        match self {
-            Param::Flag(_) => 2,
-            Param::Reg(_) => 1,
-            Param::Ptr(p) => p.param_len(),
-            Param::Lit(_) | Param::Mem(_) => 2,
-        }
-    }
 }
 
 impl std::fmt::Display for Param {
@@ -409,6 +750,7 @@ impl std::fmt::Display for Param {
         let param = match self {
             Param::Flag(_) => "FLAG".to_owned(),
             Param::Ptr(p) => format!("PTR{}", p),
+            Param::Off(p, _) => format!("{}OFF", p),
             Param::Lit(_) => "LIT".to_owned(),
             Param::Mem(_) => "MEM".to_owned(),
             Param::Reg(_) => "REG".to_owned(),
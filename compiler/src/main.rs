@@ -2,21 +2,69 @@ use std::io::{self, BufRead, Write};
 use structopt::StructOpt;
 use std::fs::File;
 
-use codeparser::CodeParser;
+use codeparser::{CodeParser, Linkage};
+use instructions::Param;
 use dataparser::DataParser;
 use chunk::Chunk;
+use arch::object::{Object, SectionKind, SymbolKind};
+use arch::format::{self, Format};
+use arch::interrupts::TABLES_END;
 
 pub mod instructions;
 pub mod codeparser;
 pub mod dataparser;
 pub mod variable;
 pub mod chunk;
+mod test;
 
 #[derive(StructOpt)]
 pub struct Args {
     pub input: String,
 
     pub out: Option<String>,
+
+    /// format of the output: vmo, raw (legacy image without header), ihex or srec
+    #[structopt(short, long, default_value = "vmo")]
+    pub format: Format,
+}
+
+/// remove the comment at the end of the line,
+/// `;` inside a string doesn't start a comment
+fn strip_comment(line: &str) -> &str {
+    let mut in_string = false;
+
+    for (id, c) in line.char_indices() {
+        match c {
+            '"' => in_string = !in_string,
+            ';' if !in_string => return &line[..id],
+            _ => (),
+        }
+    }
+
+    line
+}
+
+/// Content of the output file. Raw and hex images have no symbols, an object
+/// using symbols of other objects is only written as `.vmo`, to be linked
+fn write_object(object: &Object, format: Format, name: &str) -> Result<Vec<u8>, String> {
+    if format != Format::Vmo {
+        let symbols = object.symbols().map_err(|e| format!("Error on compilation: {:?}", e))?;
+        if let Some(import) = symbols.into_iter().find(|sym| sym.kind == SymbolKind::Import) {
+            return Err(format!(
+                "Error on compilation: symbol {} is defined in another object, link them first", import.name
+            ));
+        }
+    }
+
+    Ok(match format {
+        Format::Vmo => object.to_bytes(),
+        Format::Raw => object.sections.iter()
+            .filter(|sec| matches!(sec.kind, SectionKind::Code | SectionKind::Data))
+            .flat_map(|sec| sec.data.iter().copied())
+            .collect(),
+        Format::Ihex => format::to_ihex(object).into_bytes(),
+        Format::Srec => format::to_srec(object, name).into_bytes(),
+    })
 }
 
 fn main() {
@@ -24,6 +72,8 @@ fn main() {
     let input_dir = "data/scripts/";
     let out_dir = "data/output/";
     let mut chunks = vec![];
+    let mut linkage = Linkage::default();
+    let mut origin = None;
 
     let file = match File::open(format!("{}{}.vms", input_dir, args.input)) {
         Ok(file) => io::BufReader::new(file).lines(),
@@ -35,15 +85,39 @@ fn main() {
 
     for (id, line) in file.enumerate() {
         if let Ok(line) = line {
-            let line = line.trim().to_owned();
+            let line = strip_comment(&line).trim().to_owned();
 
-            if line.starts_with(';') || line.is_empty() {
+            if line.is_empty() {
                 continue;
             }
 
-            if line.starts_with('.') {
-                let chunk = Chunk::new(line.get(1..).unwrap().to_owned());
-                chunks.push(chunk);
+            if let Some(directive) = line.strip_prefix('.') {
+                let mut words = directive.split(|c: char| c.is_whitespace() || c == ',').filter(|w| !w.is_empty());
+                let names = match words.next() {
+                    Some("global") => &mut linkage.exports,
+                    Some("extern") => &mut linkage.imports,
+                    // address where the code is loaded, `.org 0x8000`
+                    Some("org") => {
+                        origin = match (words.next().map(Param::build_with_value), words.next(), origin) {
+                            (_, _, Some(_)) => {
+                                eprintln!("Error on line {} : .org is already set", id + 1);
+                                return;
+                            }
+                            (Some(Param::Lit(add)), None, None) => Some(add),
+                            _ => {
+                                eprintln!("Error on line {} : .org expects an address", id + 1);
+                                return;
+                            }
+                        };
+                        continue;
+                    }
+                    _ => {
+                        chunks.push(Chunk::new(directive.to_owned()));
+                        continue;
+                    }
+                };
+
+                names.extend(words.map(|name| (name.to_owned(), id + 1)));
             } else if let Some(chunk) = chunks.last_mut() {
                 chunk.insert_line(line, id + 1);
             }
@@ -55,7 +129,7 @@ fn main() {
 
     for chunk in chunks {
         match chunk.name().as_str() {
-            "code" => code = match CodeParser::new(chunk) {
+            "code" => code = match CodeParser::new(chunk, !linkage.exports.is_empty(), origin.unwrap_or(TABLES_END)) {
                 Ok(code) => Some(code),
                 Err(e) => {
                     eprintln!("{}", e);
@@ -76,8 +150,8 @@ fn main() {
         }
     }
 
-    let res = match code {
-        Some(main) => match main.get_vec(data) {
+    let object = match code {
+        Some(main) => match main.get_object(data, &linkage, &format!("{}.vms", args.input)) {
             Ok(ok) => ok,
             Err(s) => {
                 eprintln!("{}", s);
@@ -91,9 +165,17 @@ fn main() {
     };
 
     let out_file = args.out.unwrap_or(args.input);
+    let res = match write_object(&object, args.format, &out_file) {
+        Ok(res) => res,
+        Err(e) => {
+            eprintln!("{}", e);
+            return;
+        }
+    };
+
     match std::fs::create_dir_all(out_dir) {
         Ok(_) => {
-            let mut out_file = File::create(format!("{}{}.vmo", out_dir, out_file)).unwrap();
+            let mut out_file = File::create(format!("{}{}.{}", out_dir, out_file, args.format.extension())).unwrap();
             out_file.write_all(&res).unwrap();
         },
         Err(e) => eprintln!("Can't create dir '{}': {}", out_dir, e),
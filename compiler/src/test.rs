@@ -0,0 +1,331 @@
+#[cfg(test)]
+mod tests {
+    use std::collections::{HashMap, HashSet};
+
+    use crate::chunk::Chunk;
+    use crate::codeparser::{CodeParser, Linkage};
+    use crate::dataparser::DataParser;
+    use crate::instructions::{Ins, Param, Reference};
+    use crate::write_object;
+    use arch::format::Format;
+    use arch::codec::decode;
+    use arch::object::{Relocation, SectionKind, Symbol, SymbolKind};
+    use arch::{instructions::*, registers::*};
+
+    /// `count u16` at 0x0800 and `byte u8` at 0x0802
+    fn data() -> DataParser {
+        let mut chunk = Chunk::new("data".to_owned());
+        chunk.insert_line("count u16 0x2A".to_owned(), 1);
+        chunk.insert_line("byte u8 7".to_owned(), 2);
+        DataParser::new(chunk).unwrap()
+    }
+
+    /// Compile `line` with the label `target` at 0x1234, the variables of `data` and the import `ext`
+    fn compile(line: &str) -> (Ins, Vec<u8>, Option<Reference>) {
+        let ins = Ins::build_with_line(line.to_owned()).unwrap();
+        let jmps: HashMap<_, _> = [("target".to_owned(), 0x1234)].iter().cloned().collect();
+        let imports: HashSet<_> = ["ext".to_owned()].iter().cloned().collect();
+        let data = data();
+
+        let (code, reference) = ins.get_code(&jmps, &mut HashMap::new(), Some(data.vars()), 0x0800, &imports)
+            .unwrap_or_else(|e| panic!("{}: {}", line, e));
+        (ins, code, reference)
+    }
+
+    /// Chunk made of `lines`, numbered from 1
+    fn source(name: &str, lines: &[&str]) -> Chunk {
+        let mut chunk = Chunk::new(name.to_owned());
+        for (id, line) in lines.iter().enumerate() {
+            chunk.insert_line(line.to_string(), id + 1);
+        }
+        chunk
+    }
+
+    #[test]
+    fn parse_params() {
+        assert!(matches!(Param::build_with_value("7"), Param::Lit(7)));
+        assert!(matches!(Param::build_with_value("0x1F"), Param::Lit(0x1F)));
+        assert!(matches!(Param::build_with_value("0b101"), Param::Lit(5)));
+        assert!(matches!(Param::build_with_value("300"), Param::Lit(300)));
+        assert!(matches!(Param::build_with_value("#0x0400"), Param::Mem(0x0400)));
+        assert!(matches!(Param::build_with_value("#1024"), Param::Mem(1024)));
+        assert!(matches!(Param::build_with_value("AX"), Param::Reg(r) if r == AX));
+        assert!(matches!(Param::build_with_value("fl"), Param::Reg(r) if r == FL));
+        assert!(matches!(Param::build_with_value("x"), Param::Flag(ref f) if f == "x"));
+        assert!(matches!(Param::build_with_value("loop"), Param::Flag(ref f) if f == "loop"));
+
+        match Param::build_with_value("*ax") {
+            Param::Ptr(ptr) => assert!(matches!(*ptr, Param::Reg(r) if r == AX)),
+            p => panic!("{:?}", p),
+        }
+        match Param::build_with_value("*count") {
+            Param::Ptr(ptr) => assert!(matches!(*ptr, Param::Flag(ref f) if f == "count")),
+            p => panic!("{:?}", p),
+        }
+
+        assert!(Ins::build_with_line("mov ax".to_owned()).is_err());
+        assert!(Ins::build_with_line("move ax bx".to_owned()).is_err());
+        assert!(matches!(Ins::build_with_line("loop:".to_owned()), Ok(Ins::Flag(ref f)) if f == "loop"));
+    }
+
+    #[test]
+    fn ins_len_matches_code() {
+        let lines = [
+            "mov 0x10 ax", "mov 0x10 #0x0400", "mov8 0x10 #0x0400", "mov 0x10 *count", "mov 0x10 *byte",
+            "mov ax bx", "mov ax #0x0400", "mov #0x0400 ax", "mov #0x0400 #0x0402", "mov8 #0x0400 #0x0402",
+            "mov target ax", "mov count ax", "mov *ax bx", "mov *count bx", "mov *ext bx", "mov ax *bx",
+            "mov 0x1400+ax bx", "mov count+ax bx", "mov ax 0x1400+bx", "mov ax count+bx",
+            "add ax bx", "add ax 1", "sub ax bx", "sub ax 1", "sub 1 ax", "mult ax bx", "mult ax 2",
+            "cmp ax bx", "cmp ax 1", "inc ax", "dec ax", "jmp ax", "jmp target", "jmp 0x0100", "jmp ext",
+            "jeq target", "jne ax", "psh 1", "psh ax", "psh #0x0400", "psh8 #0x0400", "psh *ax", "psh8 *ax",
+            "pop ax", "pop #0x0400", "pop8 #0x0400", "pop *ax", "pop8 *ax", "pushf", "popf",
+            "cal target", "cal ext", "cal ax", "ret", "lsf ax bx", "lsf ax 1", "rsf ax bx", "rsf ax 1",
+            "and ax bx", "and ax 1", "or ax bx", "or ax 1", "xor ax bx", "xor ax 1", "not ax",
+            "int 1", "iret", "cli", "sti", "sys 3", "hostcall 1", "brk", "end",
+        ];
+
+        for line in lines.iter() {
+            let (ins, code, _) = compile(line);
+            assert_eq!(ins.ins_len(), code.len(), "{}", line);
+
+            let (_, len) = decode(&code).unwrap_or_else(|e| panic!("{}: {:?}", line, e));
+            assert_eq!(len, code.len(), "{}", line);
+        }
+
+        assert_eq!(Ins::build_with_line("loop:".to_owned()).unwrap().ins_len(), 0);
+        assert_eq!(Ins::build_with_line("mov ax 1".to_owned()).unwrap().ins_len(), 0);
+    }
+
+    #[test]
+    fn relocation_offsets() {
+        // every instruction using a symbol, with the address it is compiled with
+        let lines = [
+            ("jmp target", SymbolKind::Label, 0x1234),
+            ("jeq target", SymbolKind::Label, 0x1234),
+            ("cal target", SymbolKind::Label, 0x1234),
+            ("mov target ax", SymbolKind::Label, 0x1234),
+            ("mov count ax", SymbolKind::Variable, 0x0800),
+            ("mov 0x10 *count", SymbolKind::Variable, 0x0800),
+            ("mov 0x10 *byte", SymbolKind::Variable, 0x0802),
+            ("mov *count bx", SymbolKind::Variable, 0x0800),
+            ("mov count+ax bx", SymbolKind::Variable, 0x0800),
+            ("mov ax count+bx", SymbolKind::Variable, 0x0800),
+            ("jmp ext", SymbolKind::Import, 0x0000),
+            ("cal ext", SymbolKind::Import, 0x0000),
+            ("mov *ext bx", SymbolKind::Import, 0x0000),
+        ];
+
+        for (line, kind, address) in lines.iter() {
+            let (_, code, reference) = compile(line);
+            let reference = reference.unwrap_or_else(|| panic!("{} uses no symbol", line));
+
+            assert_eq!(reference.kind, *kind, "{}", line);
+            assert_eq!(code[reference.offset..reference.offset + 2], (*address as u16).to_be_bytes(), "{}", line);
+        }
+
+        // literals aren't relocated
+        assert!(compile("jmp 0x0100").2.is_none());
+        assert!(compile("mov 0x10 #0x0400").2.is_none());
+    }
+
+    #[test]
+    fn linkage() {
+        let chunk = source("code", &["print:", "mov *count ax", "cal ext", "jmp print"]);
+        let code = CodeParser::new(chunk, true, 0).unwrap();
+        let linkage = Linkage {
+            exports: vec![("print".to_owned(), 1)],
+            imports: vec![("ext".to_owned(), 2)],
+        };
+
+        let object = code.get_object(Some(data()), &linkage, "lib.vms").unwrap();
+        let symbols = object.sections.iter().find(|sec| sec.kind == SectionKind::Symbols).unwrap();
+        let symbols = Symbol::from_section(symbols).unwrap();
+        let relocations = object.sections.iter().find(|sec| sec.kind == SectionKind::Relocations).unwrap();
+        let relocations = Relocation::from_section(relocations).unwrap();
+
+        let print = symbols.iter().find(|sym| sym.name == "print").unwrap();
+        assert!(print.global);
+        assert!(symbols.iter().any(|sym| sym.name == "ext" && sym.kind == SymbolKind::Import));
+
+        // `mov *count ax` at 0, `cal ext` at 4 and `jmp print` at 7
+        let targets: Vec<_> = relocations.iter()
+            .map(|rel| (rel.offset, symbols[rel.symbol as usize].name.as_str()))
+            .collect();
+        assert_eq!(targets, vec![(0x0001, "count"), (0x0005, "ext"), (0x0008, "print")]);
+
+        let linkage = Linkage { exports: vec![("missing".to_owned(), 1)], imports: vec![] };
+        let code = CodeParser::new(source("code", &["start:", "end"]), true, 0).unwrap();
+        assert!(code.get_object(None, &linkage, "lib.vms").is_err());
+
+        assert!(CodeParser::new(source("code", &["loop:", "end"]), false, 0).is_err());
+        assert!(CodeParser::new(source("code", &["start:", "start:", "end"]), false, 0).is_err());
+    }
+
+    #[test]
+    fn unlinked_images() {
+        // imported fields are only filled by the linker, images without symbols can't hold them
+        let chunk = source("code", &["start:", "cal ext", "end"]);
+        let code = CodeParser::new(chunk, true, 0).unwrap();
+        let linkage = Linkage {
+            exports: vec![("start".to_owned(), 1)],
+            imports: vec![("ext".to_owned(), 2)],
+        };
+        let object = code.get_object(None, &linkage, "main.vms").unwrap();
+
+        assert!(write_object(&object, Format::Vmo, "main").is_ok());
+        for &format in [Format::Raw, Format::Ihex, Format::Srec].iter() {
+            let error = write_object(&object, format, "main").unwrap_err();
+            assert!(error.contains("symbol ext"), "{}", error);
+        }
+
+        let code = CodeParser::new(source("code", &["start:", "end"]), false, 0).unwrap();
+        let object = code.get_object(None, &Linkage::default(), "main.vms").unwrap();
+        assert!(write_object(&object, Format::Ihex, "main").is_ok());
+    }
+
+    #[test]
+    fn origin() {
+        // `.org 0x8000`: labels, sections, entry point and relocations are placed at the origin
+        let chunk = source("code", &["start:", "mov *count ax", "loop:", "jmp loop", "end"]);
+        let code = CodeParser::new(chunk, false, 0x8000).unwrap();
+        let object = code.get_object(Some(data()), &Linkage::default(), "org.vms").unwrap();
+        assert_eq!(object.entry, 0x8000);
+
+        let code = object.sections.iter().find(|sec| sec.kind == SectionKind::Code).unwrap();
+        assert_eq!(code.address, 0x8000);
+        assert_eq!(code.data, vec![MOV_MEM_REG, 0x80, 0x08, AX, JMP_LIT, 0x80, 0x04, END]);
+
+        let data = object.sections.iter().find(|sec| sec.kind == SectionKind::Data).unwrap();
+        assert_eq!(data.address, 0x8008);
+
+        let relocations = object.sections.iter().find(|sec| sec.kind == SectionKind::Relocations).unwrap();
+        let offsets: Vec<_> = Relocation::from_section(relocations).unwrap().iter().map(|rel| rel.offset).collect();
+        assert_eq!(offsets, vec![0x8001, 0x8005]);
+
+        // the code must fit under 0x10000
+        let chunk = source("code", &["start:", "mov 0x10 ax", "end"]);
+        assert!(CodeParser::new(chunk, false, 0xFFFE).is_err());
+    }
+
+    #[test]
+    fn conditional_jumps() {
+        // signed jumps, with their short names, and unsigned jumps
+        let jumps = [
+            ("jeq", JEQ_LIT, JEQ_REG), ("jne", JNE_LIT, JNE_REG),
+            ("jgt", JGT_LIT, JGT_REG), ("jg", JGT_LIT, JGT_REG), ("jge", JGE_LIT, JGE_REG),
+            ("jlt", JLT_LIT, JLT_REG), ("jl", JLT_LIT, JLT_REG), ("jle", JLE_LIT, JLE_REG),
+            ("ja", JA_LIT, JA_REG), ("jae", JAE_LIT, JAE_REG),
+            ("jb", JB_LIT, JB_REG), ("jbe", JBE_LIT, JBE_REG),
+        ];
+
+        for (name, lit, reg) in jumps.iter() {
+            let (ins, code, reference) = compile(&format!("{} target", name));
+            assert_eq!(code, vec![*lit, 0x12, 0x34], "{}", name);
+            assert_eq!(ins.ins_len(), 3, "{}", name);
+            assert_eq!(reference.unwrap().offset, 1, "{}", name);
+
+            let (ins, code, _) = compile(&format!("{} bx", name));
+            assert_eq!(code, vec![*reg, BX], "{}", name);
+            assert_eq!(ins.ins_len(), 2, "{}", name);
+        }
+    }
+
+    #[test]
+    fn division() {
+        let divisions = [
+            ("div", DIV_REG_REG, DIV_REG_LIT), ("mod", MOD_REG_REG, MOD_REG_LIT),
+            ("sdiv", SDIV_REG_REG, SDIV_REG_LIT), ("smod", SMOD_REG_REG, SMOD_REG_LIT),
+        ];
+
+        for (name, reg, lit) in divisions.iter() {
+            let (ins, code, _) = compile(&format!("{} ax bx", name));
+            assert_eq!(code, vec![*reg, AX, BX], "{}", name);
+            assert_eq!(ins.ins_len(), 3, "{}", name);
+
+            let (ins, code, _) = compile(&format!("{} cl 0x0A", name));
+            assert_eq!(code, vec![*lit, CL, 0x00, 0x0A], "{}", name);
+            assert_eq!(ins.ins_len(), 4, "{}", name);
+
+            // the dividend is a register
+            let ins = Ins::build_with_line(format!("{} 10 ax", name)).unwrap();
+            assert_eq!(ins.ins_len(), 0, "{}", name);
+            assert!(ins.get_code(&HashMap::new(), &mut HashMap::new(), None, 0, &HashSet::new()).is_err());
+        }
+    }
+
+    #[test]
+    fn stack_arguments() {
+        // register with a literal offset, negative offsets wrap
+        match Param::build_with_value("*fp+4") {
+            Param::Off(base, reg) => {
+                assert!(matches!(*base, Param::Lit(4)));
+                assert_eq!(reg, FP);
+            }
+            p => panic!("{:?}", p),
+        }
+        match Param::build_with_value("*fp-0x10") {
+            Param::Off(base, reg) => {
+                assert!(matches!(*base, Param::Lit(0xFFF0)));
+                assert_eq!(reg, FP);
+            }
+            p => panic!("{:?}", p),
+        }
+        match Param::build_with_value("0x1400+ax") {
+            Param::Off(base, reg) => {
+                assert!(matches!(*base, Param::Lit(0x1400)));
+                assert_eq!(reg, AX);
+            }
+            p => panic!("{:?}", p),
+        }
+        // a sign without a value after it is not an offset
+        assert!(matches!(Param::build_with_value("*fp-"), Param::Ptr(_)));
+
+        assert_eq!(compile("mov *fp+4 ax").1, vec![MOV_LITOFF_REG, 0x00, 0x04, FP, AX]);
+        assert_eq!(compile("mov ax *fp-2").1, vec![MOV_REG_LITOFF, AX, 0xFF, 0xFE, FP]);
+
+        let (ins, code, reference) = compile("cal target 2");
+        assert_eq!(code, vec![CALL_LIT_ARGS, 0x12, 0x34, 0x00, 0x02]);
+        assert_eq!(ins.ins_len(), 5);
+        assert_eq!(reference.unwrap().offset, 1);
+
+        let (_, code, reference) = compile("cal ext 3");
+        assert_eq!(code, vec![CALL_LIT_ARGS, 0x00, 0x00, 0x00, 0x03]);
+        assert_eq!(reference.unwrap().offset, 1);
+
+        let (ins, code, _) = compile("cal ax 2");
+        assert_eq!(code, vec![CALL_REG_ARGS, AX, 0x00, 0x02]);
+        assert_eq!(ins.ins_len(), 4);
+
+        let (ins, code, _) = compile("ret 2");
+        assert_eq!(code, vec![RET_LIT, 0x00, 0x02]);
+        assert_eq!(ins.ins_len(), 3);
+        assert_eq!(compile("ret").1, vec![RET]);
+    }
+
+    #[test]
+    fn light_calls() {
+        let (ins, code, reference) = compile("jsr target");
+        assert_eq!(code, vec![JSR_LIT, 0x12, 0x34]);
+        assert_eq!(ins.ins_len(), 3);
+        assert_eq!(reference.unwrap().kind, SymbolKind::Label);
+
+        let (_, code, reference) = compile("jsr ext");
+        assert_eq!(code, vec![JSR_LIT, 0x00, 0x00]);
+        assert_eq!(reference.unwrap().kind, SymbolKind::Import);
+
+        let (ins, code, _) = compile("jsr dx");
+        assert_eq!(code, vec![JSR_REG, DX]);
+        assert_eq!(ins.ins_len(), 2);
+
+        let (ins, code, _) = compile("rts");
+        assert_eq!(code, vec![RTS]);
+        assert_eq!(ins.ins_len(), 1);
+
+        // labels after a `jsr` are placed with its size
+        let chunk = source("code", &["start:", "jsr print", "end", "print:", "rts"]);
+        let object = CodeParser::new(chunk, false, 0).unwrap()
+            .get_object(None, &Linkage::default(), "jsr.vms").unwrap();
+        let code = object.sections.iter().find(|sec| sec.kind == SectionKind::Code).unwrap();
+        assert_eq!(code.data, vec![JSR_LIT, 0x00, 0x04, END, RTS]);
+    }
+}
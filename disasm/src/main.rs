@@ -0,0 +1,347 @@
+mod test;
+
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::io::{self, prelude::*};
+use std::fs::File;
+use structopt::StructOpt;
+
+use arch::codec::{decode, Instruction};
+use arch::instructions::{Operand, MOV_LIT_MEM8, MOV_LITOFF_REG, MOV_REG_LITOFF, JMP_LIT, JMP_REG, RET, RET_LIT, RTS, IRET, END};
+use arch::interrupts::TABLES_END;
+use arch::registers::REGISTER_NAMES;
+use arch::object::{Object, ObjectError, SectionKind, SymbolKind};
+
+#[derive(StructOpt)]
+pub struct Args {
+    pub source: String,
+
+    pub out: Option<String>,
+}
+
+/// Number of bytes written on each line of the `.data` segment
+const DATA_PER_LINE: usize = 8;
+
+/// Decode the image loaded at `base` from the first byte, until something
+/// that can't be written in assembly is found
+fn decode_all(image: &[u8], base: usize) -> Vec<(usize, Instruction)> {
+    let mut code = vec![];
+    let mut ptr = 0;
+
+    while let Ok((ins, len)) = decode(&image[ptr..]) {
+        let valid_regs = ins.info().operands.iter()
+            .zip(ins.operands())
+            .all(|(op, val)| !matches!(op, Operand::Reg | Operand::PtrReg) || (val as usize) < REGISTER_NAMES.len());
+
+        if !valid_regs {
+            break;
+        }
+
+        code.push((base + ptr, ins));
+        ptr += len;
+    }
+
+    code
+}
+
+/// Address targeted by a jump or a call with a literal address
+fn target(ins: &Instruction) -> Option<u16> {
+    ins.info().operands.iter()
+        .zip(ins.operands())
+        .find(|(op, _)| **op == Operand::Addr16)
+        .map(|(_, add)| add)
+}
+
+/// Number of instructions reachable from the entry point, following jumps
+/// and calls. Everything after the last reachable instruction is data.
+fn code_len(code: &[(usize, Instruction)]) -> usize {
+    let index: BTreeMap<usize, usize> = code.iter()
+        .enumerate()
+        .map(|(id, (add, _))| (*add, id))
+        .collect();
+
+    let mut seen = HashSet::new();
+    let mut todo = vec![0];
+    let mut last = None;
+
+    while let Some(id) = todo.pop() {
+        if id >= code.len() || !seen.insert(id) {
+            continue;
+        }
+
+        last = last.max(Some(id));
+        let ins = &code[id].1;
+
+        if let Some(next) = target(ins).and_then(|add| index.get(&(add as usize))) {
+            todo.push(*next);
+        }
+
+        // these instructions never continue on the next one
+        match ins.opcode() {
+            JMP_LIT | JMP_REG | RET | RET_LIT | RTS | IRET | END => (),
+            _ => todo.push(id + 1),
+        }
+    }
+
+    last.map_or(0, |id| id + 1)
+}
+
+/// Write one instruction at `add` with the assembler syntax.
+/// `relocations` gives the symbol held by a field, from its address
+fn format_ins(
+    ins: &Instruction,
+    add: usize,
+    labels: &BTreeMap<u16, Vec<String>>,
+    relocations: &HashMap<usize, String>,
+) -> String {
+    let info = ins.info();
+    let values = ins.operands();
+
+    let mut field = add + 1;
+    let mut symbol = None;
+    let params: Vec<String> = info.operands.iter().zip(values.iter()).map(|(op, val)| {
+        let name = relocations.get(&field);
+        field += op.size();
+        symbol = symbol.or(name);
+
+        match (op, name) {
+            (Operand::Reg, _) => REGISTER_NAMES[*val as usize].to_owned(),
+            (Operand::PtrReg, _) => format!("*{}", REGISTER_NAMES[*val as usize]),
+            (Operand::Lit16 | Operand::Addr16, Some(name)) => name.to_owned(),
+            (Operand::Mem16, Some(name)) => format!("*{}", name),
+            (Operand::Lit16, None) => format!("{:#06X}", val),
+            (Operand::Mem16, None) => format!("#{:#06X}", val),
+            (Operand::Addr16, None) => match labels.get(val) {
+                Some(names) => names[0].to_owned(),
+                None => format!("{:#06X}", val),
+            },
+        }
+    }).collect();
+
+    let (mnemonic, params) = match info.code {
+        // literal and offset are written as one parameter: `0x1400+ax`
+        MOV_LITOFF_REG => (info.mnemonic, vec![format!("{}+{}", params[0], params[1]), params[2].to_owned()]),
+        MOV_REG_LITOFF => (info.mnemonic, vec![params[0].to_owned(), format!("{}+{}", params[1], params[2])]),
+        // the assembler selects the size from the type of the variable
+        MOV_LIT_MEM8 if symbol.is_some() => ("mov", params),
+        _ => (info.mnemonic, params),
+    };
+
+    let mut line = mnemonic.to_owned();
+    for param in params {
+        line.push(' ');
+        line.push_str(&param);
+    }
+
+    line
+}
+
+/// Write the `.code` segment, `code` holds the decoded instructions of `image` loaded at `base`.
+/// `start` must be the first label at `base`
+fn write_code(
+    out: &mut String,
+    image: &[u8],
+    base: usize,
+    code: &[(usize, Instruction)],
+    labels: &BTreeMap<u16, Vec<String>>,
+    relocations: &HashMap<usize, String>,
+) {
+    out.push_str(".code\n");
+
+    for (add, ins) in code.iter() {
+        for label in labels.get(&(*add as u16)).into_iter().flatten() {
+            out.push_str(&format!("    {}:\n", label));
+        }
+
+        let bytes = image[*add - base..*add - base + ins.size()].iter()
+            .map(|b| format!("{:02X}", b))
+            .collect::<Vec<_>>()
+            .join(" ");
+
+        out.push_str(&format!("    {:<28}; {:#06X}: {}\n", format_ins(ins, *add, labels, relocations), add, bytes));
+    }
+
+    // labels placed after the last instruction
+    let end = code.last().map_or(0, |(add, ins)| add + ins.size()) as u16;
+    for label in labels.get(&end).into_iter().flatten().filter(|_| !code.is_empty()) {
+        out.push_str(&format!("    {}:\n", label));
+    }
+}
+
+/// Write `data` as anonymous `u8` variables, `address` is the one of its first byte
+fn write_raw_data(out: &mut String, data: &[u8], address: usize) {
+    for (id, chunk) in data.chunks(DATA_PER_LINE).enumerate() {
+        let add = address + id * DATA_PER_LINE;
+        let bytes = chunk.iter()
+            .map(|b| format!("{:#04X}", b))
+            .collect::<Vec<_>>()
+            .join(", ");
+
+        out.push_str(&format!("    data_{:04x} u8 {}\n", add, bytes));
+    }
+}
+
+/// Give a label to every jump or call landing on an instruction
+fn add_jump_labels(code: &[(usize, Instruction)], labels: &mut BTreeMap<u16, Vec<String>>) {
+    let starts: HashSet<usize> = code.iter().map(|(add, _)| *add).collect();
+
+    for (_, ins) in code.iter() {
+        if let Some(add) = target(ins) {
+            if starts.contains(&(add as usize)) {
+                labels.entry(add).or_insert_with(|| vec![format!("label_{:04x}", add)]);
+            }
+        }
+    }
+}
+
+/// `.org` of the code loaded at `base`, the compiler places the code after the interrupt
+/// tables without it
+fn write_origin(out: &mut String, base: usize) {
+    if base != TABLES_END as usize {
+        out.push_str(&format!(".org {:#06X}\n", base));
+    }
+}
+
+/// Disassemble a raw image loaded at `base`, the output can be compiled back to the same image
+pub fn disassemble(image: &[u8], base: usize) -> String {
+    let mut code = decode_all(image, base);
+    code.truncate(code_len(&code));
+
+    let mut labels = BTreeMap::new();
+    labels.insert(base as u16, vec!["start".to_owned()]);
+    add_jump_labels(&code, &mut labels);
+
+    let mut out = String::new();
+    write_origin(&mut out, base);
+    write_code(&mut out, image, base, &code, &labels, &HashMap::new());
+
+    let data_start = code.last().map_or(base, |(add, ins)| add + ins.size());
+    if data_start < base + image.len() {
+        out.push_str("\n.data\n");
+        write_raw_data(&mut out, &image[data_start - base..], data_start);
+    }
+
+    out
+}
+
+/// Disassemble an object, using its symbols for the labels, the variables and the
+/// fields to relocate. The output can be compiled back to the same code and data
+pub fn disassemble_object(object: &Object) -> Result<String, ObjectError> {
+    let symbols = object.symbols()?;
+    let (image, base) = object.section(SectionKind::Code).map_or((&[][..], TABLES_END as usize), |sec| (&sec.data[..], sec.address as usize));
+    let code = decode_all(image, base);
+
+    let mut labels: BTreeMap<u16, Vec<String>> = BTreeMap::new();
+    for sym in symbols.iter().filter(|sym| sym.kind == SymbolKind::Label) {
+        labels.entry(sym.address).or_default().push(sym.name.to_owned());
+    }
+
+    // a library exporting its symbols may have no entry point
+    let library = symbols.iter().any(|sym| sym.global);
+    if !library {
+        let entry = labels.entry(object.entry).or_default();
+        if !entry.iter().any(|name| name == "start") {
+            entry.insert(0, "start".to_owned());
+        }
+    }
+    add_jump_labels(&code, &mut labels);
+
+    let mut relocations = HashMap::new();
+    for rel in object.relocations()? {
+        let sym = symbols.get(rel.symbol as usize).ok_or(ObjectError::BadRelocation(rel.offset))?;
+        relocations.insert(rel.offset as usize, sym.name.to_owned());
+    }
+
+    let mut out = String::new();
+    write_origin(&mut out, base);
+
+    let mut seen = HashSet::new();
+    let exports: Vec<&str> = symbols.iter()
+        .filter(|sym| sym.global && seen.insert(&sym.name))
+        .map(|sym| sym.name.as_str())
+        .collect();
+    if !exports.is_empty() {
+        out.push_str(&format!(".global {}\n", exports.join(", ")));
+    }
+
+    for sym in symbols.iter().filter(|sym| sym.kind == SymbolKind::Import) {
+        out.push_str(&format!(".extern {}\n", sym.name));
+    }
+
+    write_code(&mut out, image, base, &code, &labels, &relocations);
+
+    if let Some(data) = object.section(SectionKind::Data) {
+        out.push_str("\n.data\n");
+
+        let vars: Vec<_> = symbols.iter().filter(|sym| sym.kind == SymbolKind::Variable).collect();
+        let mut ptr = 0;
+
+        for var in vars.iter() {
+            // variables out of the data, over the previous one or holding a partial value
+            // (bss, malformed object) are left in the anonymous data
+            let start = match var.address.checked_sub(data.address) {
+                Some(start) => start as usize,
+                None => continue,
+            };
+            let end = start + var.size as usize;
+            if start < ptr || end > data.data.len() || !matches!(var.type_len, 1 | 2) || var.size % var.type_len as u16 != 0 {
+                continue;
+            }
+            let bytes = &data.data[start..end];
+
+            if start > ptr {
+                write_raw_data(&mut out, &data.data[ptr..start], data.address as usize + ptr);
+            }
+
+            let values = match var.type_len {
+                1 => bytes.iter().map(|b| format!("{:#04X}", b)).collect::<Vec<_>>(),
+                _ => bytes.chunks(2).map(|w| format!("{:#06X}", u16::from_be_bytes([w[0], w[1]]))).collect(),
+            };
+
+            out.push_str(&format!("    {} u{} {}\n", var.name, var.type_len * 8, values.join(", ")));
+            ptr = start + bytes.len();
+        }
+
+        if ptr < data.data.len() {
+            write_raw_data(&mut out, &data.data[ptr..], data.address as usize + ptr);
+        }
+    }
+
+    Ok(out)
+}
+
+fn main() {
+    let args: Args = Args::from_args();
+    let input_dir = "data/output/";
+    let out_dir = "data/scripts/";
+    let mut image = vec![];
+
+    match File::open(format!("{}{}.vmo", input_dir, args.source)) {
+        Ok(mut file) => if let Err(e) = file.read_to_end(&mut image) {
+            eprintln!("Error when reading \"{}.vmo\": {}", args.source, e);
+            return;
+        },
+        Err(e) => {
+            eprintln!("Error when oppening \"{}.vmo\": {}", args.source, e);
+            return;
+        }
+    }
+
+    let listing = match Object::is_object(&image) {
+        true => match Object::from_bytes(&image).and_then(|object| disassemble_object(&object)) {
+            Ok(listing) => listing,
+            Err(e) => {
+                eprintln!("Error when reading \"{}.vmo\": {:?}", args.source, e);
+                return;
+            }
+        },
+        // raw images are loaded by the VM at this address by default
+        false => disassemble(&image, TABLES_END as usize),
+    };
+    match args.out {
+        Some(out) => {
+            let mut out_file = File::create(format!("{}{}.vms", out_dir, out)).unwrap();
+            out_file.write_all(listing.as_bytes()).unwrap();
+        }
+        None => io::stdout().write_all(listing.as_bytes()).unwrap(),
+    }
+}
@@ -0,0 +1,209 @@
+#[cfg(test)]
+mod tests {
+    use crate::{disassemble, disassemble_object};
+    use arch::{instructions::*, registers::*};
+    use arch::object::{Object, Relocation, Section, SectionKind, Symbol};
+
+    #[test]
+    fn labels_and_operands() {
+        let image = [
+            MOV_LIT_REG, 0x00, 0x01, AX,        // 0x0000
+            CMP_REG_LIT, AX, 0x00, 0x02,        // 0x0004
+            JNE_LIT, 0x00, 0x04,                // 0x0008
+            CALL_LIT, 0x00, 0x0F,               // 0x000B
+            END,                                // 0x000E
+            MOV_LITOFF_REG, 0x14, 0x00, AX, BL, // 0x000F
+            PSH_PTRREG8, BX,                    // 0x0014
+            RET,                                // 0x0016
+        ];
+
+        let listing = disassemble(&image, 0);
+        let lines: Vec<&str> = listing.lines()
+            .map(|line| line.split(';').next().unwrap().trim())
+            .collect();
+
+        assert_eq!(lines, [
+            ".org 0x0000",
+            ".code",
+            "start:",
+            "mov 0x0001 ax",
+            "label_0004:",
+            "cmp ax 0x0002",
+            "jne label_0004",
+            "cal label_000f",
+            "end",
+            "label_000f:",
+            "mov 0x1400+ax bl",
+            "psh8 *bx",
+            "ret",
+        ]);
+    }
+
+    #[test]
+    fn trailing_data() {
+        let image = [
+            JMP_LIT, 0x00, 0x00, // infinite loop, the data is never executed
+            0x48, 0x00, 0x65,
+        ];
+
+        let listing = disassemble(&image, 0);
+        assert!(listing.contains("jmp start"));
+        assert!(listing.contains(".data\n    data_0003 u8 0x48, 0x00, 0x65\n"));
+
+        // the default origin of the compiler needs no `.org`
+        let image = [JMP_LIT, 0x00, 0x50, 0x48];
+        let listing = disassemble(&image, 0x0050);
+        assert!(listing.starts_with(".code\n"));
+        assert!(listing.contains("jmp start"));
+        assert!(listing.contains(".data\n    data_0053 u8 0x48\n"));
+    }
+
+    #[test]
+    fn object_symbols() {
+        let mut object = Object::new(0);
+        object.sections.push(Section::new(SectionKind::Code, 0, vec![
+            MOV_LIT_REG, 0x00, 0x07, AX,    // 0x0000
+            JMP_LIT, 0x00, 0x04,            // 0x0004
+        ]));
+        object.sections.push(Section::new(SectionKind::Data, 0x0007, vec![0x00, 0x2A, 0x01]));
+        object.sections.push(Symbol::to_section(&[
+            Symbol::label("start".to_owned(), 0x0000, 2),
+            Symbol::label("main".to_owned(), 0x0000, 3),
+            Symbol::label("forever".to_owned(), 0x0004, 5),
+            Symbol::variable("answer".to_owned(), 0x0007, 2, 2, 9),
+        ]));
+
+        let listing = disassemble_object(&object).unwrap();
+        let lines: Vec<&str> = listing.lines()
+            .map(|line| line.split(';').next().unwrap().trim())
+            .collect();
+
+        assert_eq!(lines, [
+            ".org 0x0000",
+            ".code",
+            "start:",
+            "main:",
+            "mov 0x0007 ax",
+            "forever:",
+            "jmp forever",
+            "",
+            ".data",
+            "answer u16 0x002A",
+            "data_0009 u8 0x01",
+        ]);
+    }
+
+    #[test]
+    fn variables_out_of_data() {
+        let mut object = Object::new(0x0050);
+        object.sections.push(Section::new(SectionKind::Code, 0x0050, vec![END]));
+        object.sections.push(Section::new(SectionKind::Data, 0x0051, vec![0x00, 0x2A, 0x01]));
+        object.sections.push(Symbol::to_section(&[
+            Symbol::label("start".to_owned(), 0x0050, 2),
+            Symbol::variable("before".to_owned(), 0x0040, 2, 2, 3),
+            Symbol::variable("answer".to_owned(), 0x0051, 2, 2, 4),
+            Symbol::variable("overlap".to_owned(), 0x0052, 1, 1, 5),
+            Symbol::variable("past".to_owned(), 0x0053, 2, 2, 6),
+            Symbol::variable("bss".to_owned(), 0x0100, 4, 2, 7),
+            Symbol::variable("odd".to_owned(), 0x0053, 1, 2, 8),
+        ]));
+
+        let listing = disassemble_object(&object).unwrap();
+        let lines: Vec<&str> = listing.lines()
+            .map(|line| line.split(';').next().unwrap().trim())
+            .collect();
+
+        assert_eq!(lines, [
+            ".code",
+            "start:",
+            "end",
+            "",
+            ".data",
+            "answer u16 0x002A",
+            "data_0053 u8 0x01",
+        ]);
+    }
+
+    #[test]
+    fn object_relocations() {
+        let mut object = Object::new(0);
+        object.sections.push(Section::new(SectionKind::Code, 0, vec![
+            MOV_LIT_REG, 0x00, 0x00, AX,    // 0x0000
+            CALL_LIT, 0x00, 0x00,           // 0x0004
+            MOV_LIT_MEM8, 0x00, 0x01, 0x00, 0x0C, // 0x0007
+            END,                            // 0x000C
+        ]));
+        object.sections.push(Section::new(SectionKind::Data, 0x000D, vec![0x00]));
+
+        let mut main = Symbol::label("main".to_owned(), 0x0000, 3);
+        main.global = true;
+        object.sections.push(Symbol::to_section(&[
+            main,
+            Symbol::variable("flag".to_owned(), 0x000D, 1, 1, 11),
+            Symbol::import("msg".to_owned(), 1),
+            Symbol::import("print".to_owned(), 1),
+        ]));
+        object.sections.push(Relocation::to_section(&[
+            Relocation { offset: 0x0001, symbol: 2, line: 4 },
+            Relocation { offset: 0x0005, symbol: 3, line: 5 },
+            Relocation { offset: 0x000A, symbol: 1, line: 6 },
+        ]));
+
+        let listing = disassemble_object(&object).unwrap();
+        let lines: Vec<&str> = listing.lines()
+            .map(|line| line.split(';').next().unwrap().trim())
+            .collect();
+
+        assert_eq!(lines, [
+            ".org 0x0000",
+            ".global main",
+            ".extern msg",
+            ".extern print",
+            ".code",
+            "main:",
+            "mov msg ax",
+            "cal print",
+            "mov 0x0001 *flag",
+            "end",
+            "",
+            ".data",
+            "flag u8 0x00",
+        ]);
+    }
+
+    #[test]
+    fn object_origin() {
+        let mut object = Object::new(0x8000);
+        object.sections.push(Section::new(SectionKind::Code, 0x8000, vec![
+            MOV_MEM_REG, 0x80, 0x07, AX,    // 0x8000
+            JMP_LIT, 0x80, 0x04,            // 0x8004
+        ]));
+        object.sections.push(Section::new(SectionKind::Data, 0x8007, vec![0x00, 0x2A]));
+        object.sections.push(Symbol::to_section(&[
+            Symbol::label("start".to_owned(), 0x8000, 3),
+            Symbol::variable("answer".to_owned(), 0x8007, 2, 2, 9),
+        ]));
+        object.sections.push(Relocation::to_section(&[
+            Relocation { offset: 0x8001, symbol: 1, line: 4 },
+        ]));
+
+        let listing = disassemble_object(&object).unwrap();
+        assert!(listing.contains("; 0x8004: 30 80 04"));
+
+        let lines: Vec<&str> = listing.lines()
+            .map(|line| line.split(';').next().unwrap().trim())
+            .collect();
+
+        assert_eq!(lines, [
+            ".org 0x8000",
+            ".code",
+            "start:",
+            "mov *answer ax",
+            "label_8004:",
+            "jmp label_8004",
+            "",
+            ".data",
+            "answer u16 0x002A",
+        ]);
+    }
+}
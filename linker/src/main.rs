@@ -0,0 +1,301 @@
+mod test;
+
+use std::collections::HashMap;
+use std::io::prelude::*;
+use std::fs::File;
+use structopt::StructOpt;
+
+use arch::object::{DebugInfo, LineEntry, Object, ObjectError, Relocation, Section, SectionKind, Symbol, SymbolKind};
+
+#[derive(StructOpt)]
+pub struct Args {
+    /// objects to link, the entry point is the `start` flag of the first one having it
+    #[structopt(required = true)]
+    pub inputs: Vec<String>,
+
+    /// name of the executable
+    #[structopt(short, long)]
+    pub out: String,
+}
+
+/// One object given to the linker, with its decoded tables
+pub struct Input {
+    pub file: String,
+    pub object: Object,
+    symbols: Vec<Symbol>,
+    relocations: Vec<Relocation>,
+    debug: Option<DebugInfo>,
+}
+
+impl Input {
+    pub fn new(file: String, object: Object) -> Result<Self, LinkError> {
+        let tables = object.symbols()
+            .and_then(|symbols| Ok((symbols, object.relocations()?, object.debug_info()?)));
+
+        match tables {
+            Ok((symbols, relocations, debug)) => Ok(Self { file, object, symbols, relocations, debug }),
+            Err(error) => Err(LinkError::BadObject { file, error }),
+        }
+    }
+
+    /// Section of the given kind, if the object has one
+    fn section(&self, kind: SectionKind) -> Option<&Section> {
+        self.object.section(kind)
+    }
+}
+
+/// Address of every section of each input in the executable
+struct Layout {
+    bases: Vec<HashMap<SectionKind, u16>>,
+    sizes: HashMap<SectionKind, usize>,
+}
+
+impl Layout {
+    const LOADED: [SectionKind; 3] = [SectionKind::Code, SectionKind::Data, SectionKind::Bss];
+
+    /// All the code first, then the data and the zeroed memory.
+    /// The program is loaded at the code address of the first object, its `.org`
+    fn new(inputs: &[Input]) -> Result<Self, LinkError> {
+        let mut bases = vec![HashMap::new(); inputs.len()];
+        let mut sizes = HashMap::new();
+        let mut ptr = inputs.first()
+            .and_then(|input| input.section(SectionKind::Code))
+            .map_or(0, |sec| sec.address as usize);
+
+        for kind in Layout::LOADED.iter() {
+            let start = ptr;
+
+            for (id, input) in inputs.iter().enumerate() {
+                bases[id].insert(*kind, ptr as u16);
+                ptr += input.section(*kind).map_or(0, |sec| sec.size as usize);
+            }
+
+            sizes.insert(*kind, ptr - start);
+        }
+
+        if ptr > 0x10000 {
+            return Err(LinkError::TooLarge(ptr));
+        }
+
+        Ok(Self { bases, sizes })
+    }
+
+    /// Address of a symbol defined by the input `id`, once linked
+    fn address(&self, inputs: &[Input], id: usize, sym: &Symbol) -> u16 {
+        let input = &inputs[id];
+        let kind = match sym.kind {
+            SymbolKind::Label => SectionKind::Code,
+            _ => [SectionKind::Data, SectionKind::Bss].iter()
+                .copied()
+                .find(|kind| input.section(*kind).is_some_and(|sec| {
+                    sec.address <= sym.address && (sym.address as usize) < sec.address as usize + sec.size as usize
+                }))
+                .unwrap_or(SectionKind::Data),
+        };
+
+        let start = input.section(kind).map_or(0, |sec| sec.address);
+        self.bases[id][&kind].wrapping_add(sym.address.wrapping_sub(start))
+    }
+}
+
+/// Combine `inputs` in one executable object, without relocations.
+/// Every error found is returned, not only the first one
+pub fn link(inputs: &[Input]) -> Result<Object, Vec<LinkError>> {
+    let layout = Layout::new(inputs).map_err(|error| vec![error])?;
+    let mut errors = vec![];
+
+    // symbols visible from every object
+    let mut globals: HashMap<&str, (u16, usize, u16)> = HashMap::new();
+    for (id, input) in inputs.iter().enumerate() {
+        for sym in input.symbols.iter().filter(|sym| sym.global) {
+            let address = layout.address(inputs, id, sym);
+
+            match globals.get(sym.name.as_str()) {
+                Some((_, first, line)) => errors.push(LinkError::Duplicate {
+                    name: sym.name.to_owned(),
+                    first: (inputs[*first].file.to_owned(), *line),
+                    second: (input.file.to_owned(), sym.line),
+                }),
+                None => {
+                    globals.insert(&sym.name, (address, id, sym.line));
+                }
+            }
+        }
+    }
+
+    let mut code = Vec::with_capacity(layout.sizes[&SectionKind::Code]);
+    let mut data = Vec::with_capacity(layout.sizes[&SectionKind::Data]);
+    let mut debug = DebugInfo::default();
+
+    for (id, input) in inputs.iter().enumerate() {
+        let mut bytes = input.section(SectionKind::Code).map_or(vec![], |sec| sec.data.clone());
+        let start = input.section(SectionKind::Code).map_or(0, |sec| sec.address);
+
+        // lines of the code, moved with it
+        if let Some(info) = &input.debug {
+            let files: Vec<u8> = info.files.iter()
+                .map(|file| match debug.files.iter().position(|f| f == file) {
+                    Some(id) => id as u8,
+                    None => {
+                        debug.files.push(file.to_owned());
+                        (debug.files.len() - 1) as u8
+                    }
+                })
+                .collect();
+
+            for entry in info.lines.iter() {
+                debug.lines.push(LineEntry {
+                    address: layout.bases[id][&SectionKind::Code].wrapping_add(entry.address.wrapping_sub(start)),
+                    file: files.get(entry.file as usize).copied().unwrap_or(0),
+                    line: entry.line,
+                });
+            }
+        }
+
+        for rel in input.relocations.iter() {
+            let sym = match input.symbols.get(rel.symbol as usize) {
+                Some(sym) => sym,
+                None => {
+                    errors.push(LinkError::BadObject { file: input.file.to_owned(), error: ObjectError::BadRelocation(rel.offset) });
+                    continue;
+                }
+            };
+
+            let address = match sym.kind {
+                SymbolKind::Import => match globals.get(sym.name.as_str()) {
+                    Some((address, _, _)) => *address,
+                    None => {
+                        errors.push(LinkError::Undefined { name: sym.name.to_owned(), file: input.file.to_owned(), line: rel.line });
+                        continue;
+                    }
+                },
+                _ => layout.address(inputs, id, sym),
+            };
+
+            let field = rel.offset.wrapping_sub(start) as usize;
+            match bytes.get_mut(field..field + 2) {
+                Some(field) => field.copy_from_slice(&address.to_be_bytes()),
+                None => errors.push(LinkError::BadObject { file: input.file.to_owned(), error: ObjectError::BadRelocation(rel.offset) }),
+            }
+        }
+
+        code.append(&mut bytes);
+        if let Some(sec) = input.section(SectionKind::Data) {
+            data.extend_from_slice(&sec.data);
+        }
+    }
+
+    // the first `start` flag is the entry point
+    let entry = inputs.iter().enumerate()
+        .find_map(|(id, input)| input.symbols.iter()
+            .find(|sym| sym.kind == SymbolKind::Label && sym.name == "start")
+            .map(|sym| layout.address(inputs, id, sym)));
+
+    if entry.is_none() {
+        errors.push(LinkError::NoEntry);
+    }
+
+    if !errors.is_empty() {
+        return Err(errors);
+    }
+
+    // every defined symbol, a local name already used by another object is dropped
+    let mut symbols: Vec<Symbol> = vec![];
+    for (id, input) in inputs.iter().enumerate() {
+        for sym in input.symbols.iter().filter(|sym| sym.kind != SymbolKind::Import) {
+            if !sym.global && symbols.iter().any(|s| s.kind == sym.kind && s.name == sym.name) {
+                continue;
+            }
+
+            let mut sym = sym.clone();
+            sym.address = layout.address(inputs, id, &sym);
+            symbols.push(sym);
+        }
+    }
+
+    let mut object = Object::new(entry.unwrap());
+    object.isa_version = inputs.iter().map(|input| input.object.isa_version).max().unwrap_or(object.isa_version);
+    object.sections.push(Section::new(SectionKind::Code, layout.bases[0][&SectionKind::Code], code));
+
+    if !data.is_empty() {
+        object.sections.push(Section::new(SectionKind::Data, layout.bases[0][&SectionKind::Data], data));
+    }
+
+    let bss = layout.sizes[&SectionKind::Bss];
+    if bss > 0 {
+        object.sections.push(Section::bss(layout.bases[0][&SectionKind::Bss], bss as u16));
+    }
+
+    object.sections.push(Symbol::to_section(&symbols));
+    if !debug.lines.is_empty() {
+        object.sections.push(debug.to_section());
+    }
+
+    Ok(object)
+}
+
+/// Enumeration of every error found while linking objects
+pub enum LinkError {
+    BadObject { file: String, error: ObjectError },
+    Duplicate { name: String, first: (String, u16), second: (String, u16) },
+    Undefined { name: String, file: String, line: u16 },
+    NoEntry,
+    TooLarge(usize),
+}
+
+impl std::fmt::Debug for LinkError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let error = match self {
+            LinkError::BadObject { file, error } => format!("{}: {:?}", file, error),
+            LinkError::Duplicate { name, first, second } => format!(
+                "Duplicate symbol {}, defined in {} on line {} and in {} on line {}", name, first.0, first.1, second.0, second.1
+            ),
+            LinkError::Undefined { name, file, line } => format!("Undefined symbol {}, used in {} on line {}", name, file, line),
+            LinkError::NoEntry => "No object has a start flag to start execution".to_owned(),
+            LinkError::TooLarge(size) => format!("Linked program needs {:#X} bytes, memory has 0x10000", size),
+        };
+
+        write!(f, "{}", error)
+    }
+}
+
+fn main() {
+    let args: Args = Args::from_args();
+    let dir = "data/output/";
+    let mut inputs = vec![];
+    let mut failed = false;
+
+    for name in args.inputs.iter() {
+        let file = format!("{}.vmo", name);
+        let mut bytes = vec![];
+
+        if let Err(e) = File::open(format!("{}{}", dir, file)).and_then(|mut f| f.read_to_end(&mut bytes)) {
+            eprintln!("Error when reading \"{}\": {}", file, e);
+            return;
+        }
+
+        match Object::from_bytes(&bytes).map_err(|error| LinkError::BadObject { file: file.to_owned(), error })
+            .and_then(|object| Input::new(file, object))
+        {
+            Ok(input) => inputs.push(input),
+            Err(e) => {
+                eprintln!("{:?}", e);
+                failed = true;
+            }
+        }
+    }
+
+    if failed {
+        return;
+    }
+
+    match link(&inputs) {
+        Ok(object) => {
+            let mut out_file = File::create(format!("{}{}.vmo", dir, args.out)).unwrap();
+            out_file.write_all(&object.to_bytes()).unwrap();
+        }
+        Err(errors) => for e in errors {
+            eprintln!("{:?}", e);
+        },
+    }
+}
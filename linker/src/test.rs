@@ -0,0 +1,154 @@
+#[cfg(test)]
+mod tests {
+    use crate::{link, Input, LinkError};
+    use arch::{instructions::*, registers::*};
+    use arch::object::{DebugInfo, LineEntry, Object, Relocation, Section, SectionKind, Symbol};
+
+    fn global(mut sym: Symbol) -> Symbol {
+        sym.global = true;
+        sym
+    }
+
+    /// `start: cal print ; mov *count ax ; end` with `count` defined here
+    fn main_object() -> Object {
+        let mut object = Object::new(0);
+        object.sections.push(Section::new(SectionKind::Code, 0, vec![
+            CALL_LIT, 0x00, 0x00,               // 0x0000
+            MOV_MEM_REG, 0x00, 0x08, AX,        // 0x0003
+            END,                                // 0x0007
+        ]));
+        object.sections.push(Section::new(SectionKind::Data, 0x0008, vec![0x00, 0x2A]));
+        object.sections.push(Symbol::to_section(&[
+            Symbol::label("start".to_owned(), 0x0000, 4),
+            global(Symbol::variable("count".to_owned(), 0x0008, 2, 2, 2)),
+            Symbol::import("print".to_owned(), 1),
+        ]));
+        object.sections.push(Relocation::to_section(&[
+            Relocation { offset: 0x0001, symbol: 2, line: 5 },
+            Relocation { offset: 0x0004, symbol: 1, line: 6 },
+        ]));
+
+        object
+    }
+
+    /// `print: mov *count bx ; jmp print`, `count` comes from main
+    fn lib_object() -> Object {
+        let mut object = Object::new(0);
+        object.sections.push(Section::new(SectionKind::Code, 0, vec![
+            MOV_MEM_REG, 0x00, 0x00, BX,        // 0x0000
+            JMP_LIT, 0x00, 0x00,                // 0x0004
+        ]));
+        object.sections.push(Section::new(SectionKind::Data, 0x0007, vec![0x01]));
+        object.sections.push(Symbol::to_section(&[
+            global(Symbol::label("print".to_owned(), 0x0000, 3)),
+            Symbol::import("count".to_owned(), 1),
+        ]));
+        object.sections.push(Relocation::to_section(&[
+            Relocation { offset: 0x0001, symbol: 1, line: 4 },
+            Relocation { offset: 0x0005, symbol: 0, line: 5 },
+        ]));
+        object.sections.push(DebugInfo {
+            files: vec!["lib.vms".to_owned()],
+            lines: vec![
+                LineEntry { address: 0x0000, file: 0, line: 4 },
+                LineEntry { address: 0x0004, file: 0, line: 5 },
+            ],
+        }.to_section());
+
+        object
+    }
+
+    #[test]
+    fn link_objects() {
+        let inputs = [
+            Input::new("main.vmo".to_owned(), main_object()).unwrap(),
+            Input::new("lib.vmo".to_owned(), lib_object()).unwrap(),
+        ];
+
+        let object = link(&inputs).unwrap();
+        assert_eq!(object.entry, 0x0000);
+        assert!(object.relocations().unwrap().is_empty());
+
+        // code of main at 0, code of lib at 8, data of main at 15 and data of lib at 17
+        assert_eq!(object.section(SectionKind::Code).unwrap().data, [
+            CALL_LIT, 0x00, 0x08,
+            MOV_MEM_REG, 0x00, 0x0F, AX,
+            END,
+            MOV_MEM_REG, 0x00, 0x0F, BX,
+            JMP_LIT, 0x00, 0x08,
+        ]);
+
+        let data = object.section(SectionKind::Data).unwrap();
+        assert_eq!((data.address, &data.data[..]), (0x000F, &[0x00, 0x2A, 0x01][..]));
+
+        let print = object.symbols().unwrap().into_iter().find(|sym| sym.name == "print").unwrap();
+        assert_eq!(print.address, 0x0008);
+
+        // main has no debug information, the lines of lib are moved with its code
+        let debug = object.debug_info().unwrap().unwrap();
+        assert_eq!(debug.line_at(0x000C), Some(("lib.vms", 5)));
+        assert_eq!(debug.lines.len(), 2);
+    }
+
+    #[test]
+    fn link_at_origin() {
+        // main_object compiled with `.org 0x8000`
+        let mut main = Object::new(0x8000);
+        main.sections.push(Section::new(SectionKind::Code, 0x8000, vec![
+            CALL_LIT, 0x00, 0x00,               // 0x8000
+            MOV_MEM_REG, 0x80, 0x08, AX,        // 0x8003
+            END,                                // 0x8007
+        ]));
+        main.sections.push(Section::new(SectionKind::Data, 0x8008, vec![0x00, 0x2A]));
+        main.sections.push(Symbol::to_section(&[
+            Symbol::label("start".to_owned(), 0x8000, 4),
+            global(Symbol::variable("count".to_owned(), 0x8008, 2, 2, 2)),
+            Symbol::import("print".to_owned(), 1),
+        ]));
+        main.sections.push(Relocation::to_section(&[
+            Relocation { offset: 0x8001, symbol: 2, line: 5 },
+            Relocation { offset: 0x8004, symbol: 1, line: 6 },
+        ]));
+
+        let inputs = [
+            Input::new("main.vmo".to_owned(), main).unwrap(),
+            Input::new("lib.vmo".to_owned(), lib_object()).unwrap(),
+        ];
+
+        // the program is loaded at the origin of the first object
+        let object = link(&inputs).unwrap();
+        assert_eq!(object.entry, 0x8000);
+
+        let code = object.section(SectionKind::Code).unwrap();
+        assert_eq!(code.address, 0x8000);
+        assert_eq!(code.data, [
+            CALL_LIT, 0x80, 0x08,
+            MOV_MEM_REG, 0x80, 0x0F, AX,
+            END,
+            MOV_MEM_REG, 0x80, 0x0F, BX,
+            JMP_LIT, 0x80, 0x08,
+        ]);
+        assert_eq!(object.section(SectionKind::Data).unwrap().address, 0x800F);
+    }
+
+    #[test]
+    fn link_errors() {
+        let inputs = [
+            Input::new("lib.vmo".to_owned(), lib_object()).unwrap(),
+            Input::new("lib2.vmo".to_owned(), lib_object()).unwrap(),
+        ];
+
+        let errors = link(&inputs).unwrap_err();
+        assert_eq!(errors.len(), 4);
+        assert!(matches!(
+            &errors[0],
+            LinkError::Duplicate { name, first, second }
+                if name == "print" && *first == ("lib.vmo".to_owned(), 3) && *second == ("lib2.vmo".to_owned(), 3)
+        ));
+        assert!(matches!(
+            &errors[1],
+            LinkError::Undefined { name, file, line } if name == "count" && file == "lib.vmo" && *line == 4
+        ));
+        assert!(matches!(errors[3], LinkError::NoEntry));
+    }
+}
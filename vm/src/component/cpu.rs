@@ -1,16 +1,25 @@
 use std::collections::HashMap;
 
-use super::memory_map::MemoryMap;
-use super::screen::Screen;
+use super::memory_map::{self, MemoryMap};
+use super::machine::MachineBuilder;
 use super::memory::Memory;
 use super::memory_io::*;
+use super::debug::DebugMap;
+use super::host::{HostError, HostFunction};
+use super::snapshot::{Snapshot, SnapshotError};
+use super::replay::InputLog;
 
-use arch::instructions::*;
+use arch::instructions::{opcode_info, INT_LIT};
+use arch::codec::{decode, DecodeError, Instruction};
 use arch::registers::*;
+use arch::interrupts::{syscall_address, vector_address, SYSCALL_COUNT, TABLES_END, VECTOR_COUNT, VECTOR_TABLE};
+use arch::flags;
 
 macro_rules! register {
     ($self:ident, $reg:expr => $data:ident) => {
         match SIZE_OF[$reg] {
+            // the mode can't be changed by writing `fl` in user mode
+            _ if $reg == FL as usize => { $self.set_flags($data as u8); Ok(()) },
             1 => $self.registers.set_memory_at_u8(ADDRESS_OF[$reg], $data as u8),
             2 => $self.registers.set_memory_at_u16(ADDRESS_OF[$reg], $data),
             x => Err(MemoryError::BadRegisterLen(x)),
@@ -26,18 +35,32 @@ macro_rules! register {
     };
 }
 
+// the interrupt flag and the mode are only changed by `cli`, `sti`, interrupts and syscalls,
+// the sign is the top bit of the value, u8 or u16
 macro_rules! flag {
     ($self:ident, $value:ident) => {
-        $self.flags = 0;
-        if $value == 0 { $self.flags |= CPU::F_ZERO_VAL; }
-        if $value > 0x7F { $self.flags |= CPU::F_NEGATIF; }
+        let mut flags = $self.flags() & CPU::PRIVILEGED_FLAGS;
+        if $value == 0 { flags |= CPU::F_ZERO_VAL; }
+        if $value.leading_zeros() == 0 { flags |= CPU::F_NEGATIF; }
+        $self.set_flags(flags);
     };
 
     ($self:ident, $value:ident, $carry:ident) => {
-        $self.flags = 0;
-        if $value == 0 { $self.flags |= CPU::F_ZERO_VAL; }
-        if $value > 0x7F { $self.flags |= CPU::F_NEGATIF; }
-        if $carry { $self.flags |= CPU::F_CARRY; }
+        flag!($self, $value);
+        if $carry { $self.set_flags($self.flags() | CPU::F_CARRY); }
+    };
+
+    ($self:ident, $value:ident, $carry:ident, $overflow:ident) => {
+        flag!($self, $value, $carry);
+        if $overflow { $self.set_flags($self.flags() | CPU::F_OVERFLOW); }
+    };
+
+    // flags of a value written in a register, 8 bits registers only keep the low byte
+    ($self:ident, $reg:expr => $value:ident) => {
+        match SIZE_OF[$reg] {
+            1 => { let $value = $value as u8; flag!($self, $value); }
+            _ => { flag!($self, $value); }
+        }
     };
 }
 
@@ -48,14 +71,81 @@ pub struct CPU {
     memory: MemoryMap,
     registers: Memory,
     stack_frame_size: usize,
+    /// highest stack address, `sp` and `fp` start on it
+    stack_base: u16,
+    /// lowest address the stack can write
+    stack_limit: u16,
     register_map: HashMap<&'static str, usize>,
-    flags: u8,
+    /// `call` saves the flags in the stackframe and `restor` restores them
+    save_flags: bool,
+    /// interrupt lines raised and not serviced yet, one bit per vector
+    pending: u16,
+    debug: Option<DebugMap>,
+    /// functions called by `hostcall`, by id
+    host_functions: HashMap<u16, HostFunction>,
+    /// cycles and instructions executed since the start
+    cycles: u64,
+    instructions: u64,
 }
 
 impl CPU {
-    const F_ZERO_VAL: u8 = 1; // bit0
-    const F_NEGATIF : u8 = 2; // bit1
-    const F_CARRY   : u8 = 4; // bit2
+    const F_ZERO_VAL: u8 = flags::ZERO;
+    const F_NEGATIF : u8 = flags::NEGATIF;
+    const F_CARRY   : u8 = flags::CARRY;
+    const F_INTERRUPT: u8 = flags::INTERRUPT;
+    const F_OVERFLOW: u8 = flags::OVERFLOW;
+    const F_SUPERVISOR: u8 = flags::SUPERVISOR;
+
+    /// flags only changed in supervisor mode
+    const PRIVILEGED_FLAGS: u8 = CPU::F_INTERRUPT | CPU::F_SUPERVISOR;
+
+    /// default stack, from the end of memory down to the limit
+    pub const STACK_BASE : u16 = 0xFFFE;
+    pub const STACK_LIMIT: u16 = 0xF000;
+
+    /// registers saved in the stackframe by `call`
+    const SAVED_REGISTERS: [&'static str; 8] = ["ax", "bx", "cx", "dx", "ex", "fx", "gx", "hx"];
+
+    /// CPU running on `memory`, in supervisor mode with the default stack. The vector and
+    /// syscall tables are protected as supervisor-only
+    pub fn new(memory: MemoryMap) -> Result<Self, MemoryError> {
+        let mut registers = Memory::new(REGISTER_NAMES.len() * 2);
+        registers.set_memory_at_u16(ADDRESS_OF[SP as usize], CPU::STACK_BASE)?;
+        registers.set_memory_at_u16(ADDRESS_OF[FP as usize], CPU::STACK_BASE)?;
+        registers.set_memory_at_u16(ADDRESS_OF[FL as usize], CPU::F_SUPERVISOR as u16)?;
+
+        // HashMap gives the register_id with the register name given
+        let register_map = REGISTER_NAMES.iter()
+            .fold(HashMap::new(), |mut map, s| {
+                let _ = map.insert(*s, map.len());
+                map
+            });
+
+        let mut cpu = Self {
+            memory,
+            registers,
+            stack_frame_size: 0,
+            stack_base: CPU::STACK_BASE,
+            stack_limit: CPU::STACK_LIMIT,
+            register_map,
+            save_flags: false,
+            pending: 0,
+            debug: None,
+            host_functions: HashMap::new(),
+            cycles: 0,
+            instructions: 0,
+        };
+
+        cpu.protect_tables()?;
+        Ok(cpu)
+    }
+
+    /// Protect the vector and syscall tables as supervisor-only, over the permissions
+    /// previously given to their addresses
+    pub fn protect_tables(&mut self) -> Result<(), MemoryError> {
+        let len = (TABLES_END - VECTOR_TABLE) as usize;
+        self.memory.protect(VECTOR_TABLE as usize, len, memory_map::RWX | memory_map::SUPERVISOR)
+    }
 
     pub fn get_register(&self, name: &'static str) -> Result<u16, MemoryError> {
         match self.register_map.get(name) {
@@ -74,7 +164,7 @@ impl CPU {
     pub fn print_registers(&self) {
         let regs = [
             "ip", "acc", "ax", "bx", "cx", "dx",
-            "ex", "fx", "gx", "hx", "sp", "fp"
+            "ex", "fx", "gx", "hx", "sp", "fp", "fl"
         ];
 
         print!("Label            : "); // gap to align text
@@ -86,102 +176,208 @@ impl CPU {
         self.registers.print_memory_chunk_u16(0, REGISTER_LEN);
     }
 
-    fn fetch_reg(&mut self) -> Result<usize, ExecutionError> {
-        Ok(self.fetch_u8()? as usize % REGISTER_NAMES.len())
+    /// Flags are stored in the register `fl`
+    fn flags(&self) -> u8 {
+        self.registers.get_memory_at_u16(ADDRESS_OF[FL as usize]).unwrap_or(0) as u8
     }
 
-    /// Gets the 8bit instruction pointed to by the instruction pointer and increase himself by one
-    fn fetch_u8(&mut self) -> Result<u8, ExecutionError> {
-        let next_instruction = self.get_register("ip")?;
-        let instruction = self.memory.get_memory_at_u8(next_instruction as usize)?;
-        self.set_register("ip", next_instruction + 1)?;
+    /// In user mode, the interrupt flag and the mode are kept
+    fn set_flags(&mut self, flags: u8) {
+        let flags = match self.supervisor() {
+            true => flags,
+            false => (flags & !CPU::PRIVILEGED_FLAGS) | (self.flags() & CPU::PRIVILEGED_FLAGS),
+        };
 
-        Ok(instruction)
+        self.write_flags(flags);
+    }
+
+    fn write_flags(&mut self, flags: u8) {
+        // `fl` is always in the register memory, writing it can't fail
+        let _ = self.registers.set_memory_at_u16(ADDRESS_OF[FL as usize], flags as u16);
+        self.memory.set_user_mode(flags & CPU::F_SUPERVISOR == 0);
+    }
+
+    fn supervisor(&self) -> bool {
+        self.flags() & CPU::F_SUPERVISOR != 0
+    }
+
+    /// Privileged instructions are only executed in supervisor mode
+    fn privileged(&self, mnemonic: &'static str) -> Result<(), ExecutionError> {
+        match self.supervisor() {
+            true => Ok(()),
+            false => Err(ExecutionError::PrivilegedInstruction(mnemonic)),
+        }
+    }
+
+    /// Save the flags in the stackframe of subroutines, they are restored on return
+    pub fn set_save_flags(&mut self, save_flags: bool) {
+        self.save_flags = save_flags;
+    }
+
+    /// Register `function`, called by `hostcall id`. It replaces the function previously
+    /// registered with this id
+    pub fn register_host_function<F>(&mut self, id: u16, function: F)
+    where F: FnMut(&mut CPU) -> Result<(), HostError> + 'static {
+        self.host_functions.insert(id, Box::new(function));
+    }
+
+    /// Call the host function `id`, it is put back once it returns
+    fn host_call(&mut self, id: u16) -> Result<(), ExecutionError> {
+        let mut function = self.host_functions.remove(&id).ok_or(ExecutionError::NoHostFunction(id))?;
+        let res = function(self);
+
+        // keep the function registered by the call itself, if any
+        self.host_functions.entry(id).or_insert(function);
+        res.map_err(|error| ExecutionError::HostFunctionError(id, error))
+    }
+
+    /// Place the stack between `limit` and `base` and empty it, pushing under `limit` is a
+    /// stack overflow and popping over `base` a stack underflow
+    pub fn set_stack(&mut self, base: u16, limit: u16) {
+        self.stack_base = base;
+        self.stack_limit = limit;
+        self.stack_frame_size = 0;
+        let _ = self.set_register("sp", base);
+        let _ = self.set_register("fp", base);
+    }
+
+    /// Register id stored in an instruction, wrapped to an existing register
+    fn reg_index(reg: u8) -> usize {
+        reg as usize % REGISTER_NAMES.len()
+    }
+
+    /// Set the flags of `a - b`, computed on the width of the register `reg` holding `a`
+    fn compare(&mut self, reg: usize, a: u16, b: u16) {
+        match SIZE_OF[reg] {
+            1 => {
+                let (a, b) = (a as u8, b as u8);
+                let (res, carry) = a.overflowing_sub(b);
+                let overflow = (a as i8).overflowing_sub(b as i8).1;
+                flag!(self, res, carry, overflow);
+            }
+            _ => {
+                let (res, carry) = a.overflowing_sub(b);
+                let overflow = (a as i16).overflowing_sub(b as i16).1;
+                flag!(self, res, carry, overflow);
+            }
+        }
+    }
+
+    /// Value of `len` bytes, sign extended when `signed`
+    fn extend(value: u16, len: u8, signed: bool) -> i32 {
+        match (len, signed) {
+            (1, false) => value as u8 as i32,
+            (1, true) => value as u8 as i8 as i32,
+            (_, false) => value as i32,
+            (_, true) => value as i16 as i32,
+        }
     }
 
-    /// Gets the instruction pointed to by the instruction pointer and increase himself by one
-    fn fetch_u16(&mut self) -> Result<u16, ExecutionError> {
-        let next_instruction = self.get_register("ip")?;
-        let instruction = self.memory.get_memory_at_u16(next_instruction as usize)?;
-        self.set_register("ip", next_instruction + 2)?;
+    /// Divide the register `reg` by `divisor` of `divisor_len` bytes, each on its own width: a
+    /// literal keeps its 16 bits when `reg` has 8. Signed results are sign extended. Returns the
+    /// values of ACC and `reg`: the quotient and the remainder, swapped for a modulo. Flags are
+    /// set from the value of ACC
+    fn divide(&mut self, reg: usize, divisor: u16, divisor_len: u8, signed: bool, modulo: bool) -> Result<(u16, u16), ExecutionError> {
+        let max = match (SIZE_OF[reg], signed) {
+            (1, false) => u8::MAX as i32,
+            (1, true) => i8::MAX as i32,
+            (_, false) => u16::MAX as i32,
+            (_, true) => i16::MAX as i32,
+        };
+
+        let value = Self::extend(register!(self, reg)?, SIZE_OF[reg], signed);
+        let divisor = Self::extend(divisor, divisor_len, signed);
+        if divisor == 0 {
+            return Err(ExecutionError::DivisionByZero);
+        }
+
+        // only MIN / -1 overflows
+        let quotient = value / divisor;
+        let overflow = quotient > max;
+        let remainder = value % divisor;
+        let (acc, res) = match modulo {
+            false => (quotient as u16, remainder as u16),
+            true => (remainder as u16, quotient as u16),
+        };
+
+        let carry = false;
+        flag!(self, acc, carry, overflow);
+        Ok((acc, res))
+    }
+
+    /// Last compared values are equal
+    fn equal(&self) -> bool {
+        (self.flags() & CPU::F_ZERO_VAL) != 0
+    }
+
+    /// Signed comparison, the first value is lower when NEGATIF differs from OVERFLOW
+    fn less(&self) -> bool {
+        ((self.flags() & CPU::F_NEGATIF) != 0) != ((self.flags() & CPU::F_OVERFLOW) != 0)
+    }
+
+    /// Unsigned comparison, the first value is lower when the substraction carried
+    fn below(&self) -> bool {
+        (self.flags() & CPU::F_CARRY) != 0
+    }
+
+    /// Decode the instruction pointed to by the instruction pointer and move it after the instruction
+    fn fetch(&mut self) -> Result<Instruction, ExecutionError> {
+        let ip = self.get_register("ip")?;
+        let opcode = self.fetch_u8(ip as usize)?;
+        let info = opcode_info(opcode).ok_or(ExecutionError::UnexpectedInstruction(opcode))?;
+
+        let mut bytes = Vec::with_capacity(info.size());
+        for offset in 0..info.size() {
+            bytes.push(self.fetch_u8(ip as usize + offset)?);
+        }
+
+        let (instruction, len) = decode(&bytes)?;
+        self.set_register("ip", ip + len as u16)?;
 
         Ok(instruction)
     }
 
-    fn execute(&mut self, instruction: u8) -> Result<(), ExecutionError> {
-        #[cfg(debug_assertions)]
-        print!("\nInstruction      : ");
+    /// Read a byte of the instruction at `address`, which must be executable
+    fn fetch_u8(&self, address: usize) -> Result<u8, ExecutionError> {
+        Ok(self.memory.fetch_u8(address)?)
+    }
 
+    fn execute(&mut self, instruction: Instruction) -> Result<(), ExecutionError> {
         match instruction {
             // Move literal into a specific register
-            MOV_LIT_REG => {
-                let literal = self.fetch_u16()?;
-                let reg = self.fetch_reg()?;
-
-                #[cfg(debug_assertions)]
-                {
-                    let reg_name = REGISTER_NAMES[reg];
-                    println!("Move {:#06X} (literal) in {}", literal, reg_name);
-                }
+            Instruction::MovLitReg(literal, reg) => {
+                let reg = Self::reg_index(reg);
 
-                flag!(self, literal);
+                flag!(self, reg => literal);
                 Ok(register!(self, reg => literal)?)
             }
             // Move literal directly in the memory
-            MOV_LIT_MEM8 => {
-                let literal = self.fetch_u16()? as u8;
-                let memory = self.fetch_u16()? as usize;
-
-                #[cfg(debug_assertions)]
-                println!(
-                    "Move {:#04X} (literal) in {:#06X} (memory)",
-                    literal, memory
-                );
+            Instruction::MovLitMem8(literal, memory) => {
+                let literal = literal as u8;
+                let memory = memory as usize;
 
                 flag!(self, literal);
                 Ok(self.memory.set_memory_at_u8(memory, literal)?)
             }
             // Move literal directly in the memory
-            MOV_LIT_MEM16 => {
-                let literal = self.fetch_u16()?;
-                let memory = self.fetch_u16()?;
-
-                #[cfg(debug_assertions)]
-                println!(
-                    "Move {:#06X} (literal) in {:#06X} (memory)",
-                    literal, memory
-                );
-
+            Instruction::MovLitMem16(literal, memory) => {
                 flag!(self, literal);
                 Ok(self.memory.set_memory_at_u16(memory as usize, literal)?)
             }
             // Move register value into a specific register
-            MOV_REG_REG => {
-                let reg_from = self.fetch_reg()?;
-                let reg_to = self.fetch_reg()?;
-
-                #[cfg(debug_assertions)]
-                {
-                    let reg_from_name = REGISTER_NAMES[reg_from];
-                    let reg_to_name = REGISTER_NAMES[reg_to];
-                    println!("Move {} in {}", reg_from_name, reg_to_name);
-                }
+            Instruction::MovRegReg(reg_from, reg_to) => {
+                let reg_from = Self::reg_index(reg_from);
+                let reg_to = Self::reg_index(reg_to);
 
                 let value = register!(self, reg_from)?;
-                flag!(self, value);
+                flag!(self, reg_to => value);
 
                 Ok(register!(self, reg_to => value)?)
             }
             // Move register value into a specific memory address
-            MOV_REG_MEM => {
-                let reg = self.fetch_reg()?;
-                let memory_address = self.fetch_u16()? as usize;
-
-                #[cfg(debug_assertions)]
-                {
-                    let reg_name = REGISTER_NAMES[reg];
-                    println!("Move {} at {:#06X} (memory)", reg_name, memory_address);
-                }
+            Instruction::MovRegMem(reg, memory_address) => {
+                let reg = Self::reg_index(reg);
+                let memory_address = memory_address as usize;
 
                 match SIZE_OF[reg] {
                     1 => {
@@ -200,40 +396,38 @@ impl CPU {
                 }
             }
             // Move memory value into a specific register
-            MOV_MEM_REG => {
-                let memory_address = self.fetch_u16()? as usize;
-                let reg = self.fetch_reg()?;
-
-                #[cfg(debug_assertions)]
-                {
-                    let reg_name = REGISTER_NAMES[reg];
-                    println!("Move {:#06X} (memory) in {}", memory_address, reg_name);
-                }
+            Instruction::MovMemReg(memory_address, reg) => {
+                let memory_address = memory_address as usize;
+                let reg = Self::reg_index(reg);
 
-                match SIZE_OF[reg] {
-                    1 => {
-                        let value = self.memory.get_memory_at_u8(memory_address)?;
-                        flag!(self, value);
-
-                        Ok(self.registers.set_memory_at_u8(ADDRESS_OF[reg], value)?)
-                    }
-                    2 => {
-                        let value = self.memory.get_memory_at_u16(memory_address)?;
-                        flag!(self, value);
+                let value = match SIZE_OF[reg] {
+                    1 => self.memory.get_memory_at_u8(memory_address)? as u16,
+                    2 => self.memory.get_memory_at_u16(memory_address)?,
+                    x => return Err(ExecutionError::from(MemoryError::BadRegisterLen(x))),
+                };
 
-                        Ok(self.registers.set_memory_at_u16(ADDRESS_OF[reg], value)?)
-                    }
-                    x => Err(ExecutionError::from(MemoryError::BadRegisterLen(x))),
-                }
+                flag!(self, reg => value);
+                Ok(register!(self, reg => value)?)
             }
             // Move memory value to another memory address
+            Instruction::MovMemMem8(from, to) => {
+                let value = self.memory.get_memory_at_u8(from as usize)?;
+                flag!(self, value);
 
+                Ok(self.memory.set_memory_at_u8(to as usize, value)?)
+            }
             // Move memory value to another memory address
+            Instruction::MovMemMem16(from, to) => {
+                let value = self.memory.get_memory_at_u16(from as usize)?;
+                flag!(self, value);
+
+                Ok(self.memory.set_memory_at_u16(to as usize, value)?)
+            }
 
             // Move a memory address pointed by register in register
-            MOV_PTRREG_REG => {
-                let r1 = self.fetch_reg()?;
-                let r2 = self.fetch_reg()?;
+            Instruction::MovPtrRegReg(r1, r2) => {
+                let r1 = Self::reg_index(r1);
+                let r2 = Self::reg_index(r2);
 
                 match SIZE_OF[r1] {
                     1 => Err(ExecutionError::BadRegisterPtrLen),
@@ -245,26 +439,16 @@ impl CPU {
                             x => return Err(ExecutionError::from(MemoryError::BadRegisterLen(x))),
                         };
 
-                        #[cfg(debug_assertions)]
-                        {
-                            let r1_name = REGISTER_NAMES[r1];
-                            let r2_name = REGISTER_NAMES[r2];
-                            println!(
-                                "Move value {:#06X} from memory {:#06X} pointed by {} to register {}",
-                                mem_val, mem_loc, r1_name, r2_name
-                            );
-                        }
-
-                        flag!(self, mem_val);
+                        flag!(self, r2 => mem_val);
                         Ok(register!(self, r2 => mem_val)?)
                     }
                     x => Err(ExecutionError::from(MemoryError::BadRegisterLen(x)))
                 }
             }
             // Move value from register to memory address pointed by register
-            MOV_REG_PTRREG => {
-                let r1 = self.fetch_reg()?;
-                let r2 = self.fetch_reg()?;
+            Instruction::MovRegPtrReg(r1, r2) => {
+                let r1 = Self::reg_index(r1);
+                let r2 = Self::reg_index(r2);
 
                 match SIZE_OF[r2] {
                     1 => Err(ExecutionError::BadRegisterPtrLen),
@@ -272,17 +456,7 @@ impl CPU {
                         let val = register!(self, r1)?;
                         let mem_loc = self.registers.get_memory_at_u16(ADDRESS_OF[r2])? as usize;
 
-                        #[cfg(debug_assertions)]
-                        {
-                            let r1_name = REGISTER_NAMES[r1];
-                            let r2_name = REGISTER_NAMES[r2];
-                            println!(
-                                "Move value {:#06X} from {} into memory {:#06X} pointed by {}",
-                                val, r1_name, mem_loc, r2_name
-                            );
-                        }
-
-                        flag!(self, val);
+                        flag!(self, r1 => val);
                         match SIZE_OF[r1] {
                             1 => Ok(self.memory.set_memory_at_u8(mem_loc, val as u8)?),
                             2 => Ok(self.memory.set_memory_at_u16(mem_loc, val)?),
@@ -293,31 +467,40 @@ impl CPU {
                 }
             }
             // Move value from memory address = [literal + register] to register
-            MOV_LITOFF_REG => {
-                let base_address = self.fetch_u16()? as usize;
-                let r1 = self.fetch_reg()?;
-                let r2 = self.fetch_reg()?;
+            Instruction::MovLitOffReg(base_address, r1, r2) => {
+                let r1 = Self::reg_index(r1);
+                let r2 = Self::reg_index(r2);
 
                 match SIZE_OF[r1] {
                     1 => Err(ExecutionError::BadRegisterPtrLen),
                     2 => {
+                        // the offset wraps, a negative offset is written as its two's complement
+                        let offset = self.registers.get_memory_at_u16(ADDRESS_OF[r1])?;
+                        let address = base_address.wrapping_add(offset) as usize;
+                        let val = self.memory.get_memory_at_u16(address)?;
 
-                        let offset = self.registers.get_memory_at_u16(r1)? as usize;
-                        let val = self.memory.get_memory_at_u16( base_address + offset)?;
+                        flag!(self, r2 => val);
+                        Ok(register!(self, r2 => val)?)
+                    }
+                    x => Err(ExecutionError::from(MemoryError::BadRegisterLen(x)))
+                }
+            }
+            // Move value from register to memory address = [literal + register]
+            Instruction::MovRegLitOff(r1, base_address, r2) => {
+                let r1 = Self::reg_index(r1);
+                let r2 = Self::reg_index(r2);
 
-                        #[cfg(debug_assertions)]
-                        {
-                            let r2_name = REGISTER_NAMES[r2];
-                            println!(
-                                "Move value {:#06X} from {:#06X} in memory to {}",
-                                val, base_address + offset, r2_name
-                            );
-                        }
+                match SIZE_OF[r2] {
+                    1 => Err(ExecutionError::BadRegisterPtrLen),
+                    2 => {
+                        let val = register!(self, r1)?;
+                        let offset = self.registers.get_memory_at_u16(ADDRESS_OF[r2])?;
+                        let address = base_address.wrapping_add(offset) as usize;
 
-                        flag!(self, val);
-                        match SIZE_OF[r2] {
-                            1 => Ok(self.registers.set_memory_at_u8(ADDRESS_OF[r2], val as u8)?),
-                            2 => Ok(self.registers.set_memory_at_u16(ADDRESS_OF[r2], val)?),
+                        flag!(self, r1 => val);
+                        match SIZE_OF[r1] {
+                            1 => Ok(self.memory.set_memory_at_u8(address, val as u8)?),
+                            2 => Ok(self.memory.set_memory_at_u16(address, val)?),
                             x => Err(ExecutionError::from(MemoryError::BadRegisterLen(x)))
                         }
                     }
@@ -325,501 +508,470 @@ impl CPU {
                 }
             }
             // unconditional jump to literal (label)
-            JMP_LIT => {
-                let add = self.fetch_u16()?;
-
-                #[cfg(debug_assertions)]
-                println!("Jump to {:#06X} (literal)", add);
-
+            Instruction::JmpLit(add) => {
                 self.set_register("ip", add)?;
                 Ok(())
             }
             // unconditional jump to register value
-            JMP_REG => {
-                let reg = self.fetch_reg()?;
+            Instruction::JmpReg(reg) => {
+                let reg = Self::reg_index(reg);
                 let add = register!(self, reg)?;
 
-                #[cfg(debug_assertions)]
-                {
-                    let reg_name = REGISTER_NAMES[reg];
-                    println!("Jump to {:#06X} (value of {})", add, reg_name);
-                }
-
                 self.set_register("ip", add)?;
                 Ok(())
             }
             // Jump to provided memory address if Zero_f is true
-            JEQ_LIT => {
-                let add = self.fetch_u16()?;
-
-                #[cfg(debug_assertions)]
-                println!("Jump to {:#06X} (literal) if flag ZERO is set to true", add);
-
-                if (self.flags & CPU::F_ZERO_VAL) != 0 { // flag f_zero_val is on
+            Instruction::JeqLit(add) => {
+                if (self.flags() & CPU::F_ZERO_VAL) != 0 { // flag f_zero_val is on
                     self.set_register("ip", add)?;
                 }
                 Ok(())
             }
             // Jump to the value in register if Zero_f is true
-            JEQ_REG => {
-                let reg = self.fetch_reg()?;
+            Instruction::JeqReg(reg) => {
+                let reg = Self::reg_index(reg);
                 let add = register!(self, reg)?;
 
-                #[cfg(debug_assertions)]
-                {
-                    let reg_name = REGISTER_NAMES[reg];
-                    println!("Jump to {:#06X} (value of {}) if flag ZERO is set to true", add, reg_name);
-                }
-
-                if (self.flags & CPU::F_ZERO_VAL) != 0 { // flag f_zero_val is on
+                if (self.flags() & CPU::F_ZERO_VAL) != 0 { // flag f_zero_val is on
                     self.set_register("ip", add)?;
                 }
                 Ok(())
             }
             // Jump to provided memory address if Zero_f is false
-            JNE_LIT => {
-                let add = self.fetch_u16()?;
-
-                #[cfg(debug_assertions)]
-                println!("Jump to {:#06X} (literal) if flag ZERO is set to false", add);
-
-                if (self.flags & CPU::F_ZERO_VAL) == 0 { // flag f_zero_val is off
+            Instruction::JneLit(add) => {
+                if (self.flags() & CPU::F_ZERO_VAL) == 0 { // flag f_zero_val is off
                     self.set_register("ip", add)?;
                 }
                 Ok(())
             }
             // Jump to the value in register if Zero_f is false
-            JNE_REG => {
-                let reg = self.fetch_reg()?;
+            Instruction::JneReg(reg) => {
+                let reg = Self::reg_index(reg);
                 let add = register!(self, reg)?;
 
-                #[cfg(debug_assertions)]
-                {
-                    let reg_name = REGISTER_NAMES[reg];
-                    println!("Jump to {:#06X} (value of {}) if flag ZERO is set to false", add, reg_name);
-                }
-
-                if (self.flags & CPU::F_ZERO_VAL) == 0 { // flag f_zero_val is off
+                if (self.flags() & CPU::F_ZERO_VAL) == 0 { // flag f_zero_val is off
                     self.set_register("ip", add)?;
                 }
                 Ok(())
             }
-            // Jump to provided memory address if Zero_f and Neg_f are false
-            JGT_LIT => {
-                let add = self.fetch_u16()?;
-
-                #[cfg(debug_assertions)]
-                println!("Jump to {:#06X} (literal) if flags ZERO and NEGATIF are set to false", add);
-
-                if (self.flags & (CPU::F_ZERO_VAL | CPU::F_NEGATIF)) == 0 {
+            // Jump to provided memory address if greater than (signed)
+            Instruction::JgtLit(add) => {
+                if !self.less() && !self.equal() {
                     self.set_register("ip", add)?;
                 }
                 Ok(())
             }
-            // Jump to the value in register if Zero_f and Neg_f are false
-            JGT_REG => {
-                let reg = self.fetch_reg()?;
+            // Jump to the value in register if greater than (signed)
+            Instruction::JgtReg(reg) => {
+                let reg = Self::reg_index(reg);
                 let add = register!(self, reg)?;
 
-                #[cfg(debug_assertions)]
-                {
-                    let reg_name = REGISTER_NAMES[reg];
-                    println!("Jump to {:#06X} (value of {}) if flags ZERO and NEGATIF are set to false", add, reg_name);
+                if !self.less() && !self.equal() {
+                    self.set_register("ip", add)?;
                 }
-
-                if (self.flags & (CPU::F_ZERO_VAL | CPU::F_NEGATIF)) == 0 {
+                Ok(())
+            }
+            // Jump to provided memory address if greater or equal (signed)
+            Instruction::JgeLit(add) => {
+                if !self.less() {
                     self.set_register("ip", add)?;
                 }
                 Ok(())
             }
-            // Jump to provided memory address if Neg_f is false
-            JGE_LIT => {
-                let add = self.fetch_u16()?;
-
-                #[cfg(debug_assertions)]
-                println!("Jump to {:#06X} (literal) if flag NEGATIF is set to false", add);
+            // Jump to the value in register if greater or equal (signed)
+            Instruction::JgeReg(reg) => {
+                let reg = Self::reg_index(reg);
+                let add = register!(self, reg)?;
 
-                if (self.flags & CPU::F_NEGATIF) == 0 {
+                if !self.less() {
                     self.set_register("ip", add)?;
                 }
                 Ok(())
             }
-            // Jump to the value in register if Neg_f is false
-            JGE_REG => {
-                let reg = self.fetch_reg()?;
+            // Jump to provided memory address if lower than (signed)
+            Instruction::JltLit(add) => {
+                if self.less() {
+                    self.set_register("ip", add)?;
+                }
+                Ok(())
+            }
+            // Jump to the value in register if lower than (signed)
+            Instruction::JltReg(reg) => {
+                let reg = Self::reg_index(reg);
                 let add = register!(self, reg)?;
 
-                #[cfg(debug_assertions)]
-                {
-                    let reg_name = REGISTER_NAMES[reg];
-                    println!("Jump to {:#06X} (value of {}) if flag NEGATIF is set to false", add, reg_name);
+                if self.less() {
+                    self.set_register("ip", add)?;
                 }
-
-                if (self.flags & CPU::F_NEGATIF) == 0 {
+                Ok(())
+            }
+            // Jump to provided memory address if lower or equal (signed)
+            Instruction::JleLit(add) => {
+                if self.less() || self.equal() {
                     self.set_register("ip", add)?;
                 }
                 Ok(())
             }
-            // Jump to provided memory address if Zero_f is false and Neg_f is true
-            JLT_LIT => {
-                let add = self.fetch_u16()?;
-
-                #[cfg(debug_assertions)]
-                println!("Jump to {:#06X} (literal) if flag ZERO is set to false and flag NEGATIF is set to true", add);
+            // Jump to the value in register if lower or equal (signed)
+            Instruction::JleReg(reg) => {
+                let reg = Self::reg_index(reg);
+                let add = register!(self, reg)?;
 
-                if (self.flags & (CPU::F_ZERO_VAL | CPU::F_NEGATIF)) == CPU::F_NEGATIF { // not equal + neg
+                if self.less() || self.equal() {
+                    self.set_register("ip", add)?;
+                }
+                Ok(())
+            }
+            // Jump to provided memory address if above (unsigned)
+            Instruction::JaLit(add) => {
+                if !self.below() && !self.equal() {
                     self.set_register("ip", add)?;
                 }
                 Ok(())
             }
-            // Jump to the value in register if Zero_f is false and Neg_f is true
-            JLT_REG => {
-                let reg = self.fetch_reg()?;
+            // Jump to the value in register if above (unsigned)
+            Instruction::JaReg(reg) => {
+                let reg = Self::reg_index(reg);
                 let add = register!(self, reg)?;
 
-                #[cfg(debug_assertions)]
-                {
-                    let reg_name = REGISTER_NAMES[reg];
-                    println!("Jump to {:#06X} (value of {}) if flag ZERO is set to false and flag NEGATIF is set to true", add, reg_name);
+                if !self.below() && !self.equal() {
+                    self.set_register("ip", add)?;
                 }
-
-                if (self.flags & (CPU::F_ZERO_VAL | CPU::F_NEGATIF)) == CPU::F_NEGATIF { // not equal + neg
+                Ok(())
+            }
+            // Jump to provided memory address if above or equal (unsigned)
+            Instruction::JaeLit(add) => {
+                if !self.below() {
                     self.set_register("ip", add)?;
                 }
                 Ok(())
             }
-            // Jump to provided memory address if Zero_f and Neg_f are true
-            JLE_LIT => {
-                let add = self.fetch_u16()?;
-
-                #[cfg(debug_assertions)]
-                println!("Jump to {:#06X} (literal) if flag NEGATIF and ZERO are set to true", add);
+            // Jump to the value in register if above or equal (unsigned)
+            Instruction::JaeReg(reg) => {
+                let reg = Self::reg_index(reg);
+                let add = register!(self, reg)?;
 
-                if (self.flags & (CPU::F_NEGATIF | CPU::F_ZERO_VAL)) != 0 {
+                if !self.below() {
                     self.set_register("ip", add)?;
                 }
                 Ok(())
             }
-            // Jump to the value in register address if Zero_f and Neg_f are true
-            JLE_REG => {
-                let reg = self.fetch_reg()?;
+            // Jump to provided memory address if below (unsigned)
+            Instruction::JbLit(add) => {
+                if self.below() {
+                    self.set_register("ip", add)?;
+                }
+                Ok(())
+            }
+            // Jump to the value in register if below (unsigned)
+            Instruction::JbReg(reg) => {
+                let reg = Self::reg_index(reg);
                 let add = register!(self, reg)?;
 
-                #[cfg(debug_assertions)]
-                {
-                    let reg_name = REGISTER_NAMES[reg];
-                    println!("Jump to {:#06X} (value of {}) if flag NEGATIF and ZERO are set to true", add, reg_name);
+                if self.below() {
+                    self.set_register("ip", add)?;
                 }
+                Ok(())
+            }
+            // Jump to provided memory address if below or equal (unsigned)
+            Instruction::JbeLit(add) => {
+                if self.below() || self.equal() {
+                    self.set_register("ip", add)?;
+                }
+                Ok(())
+            }
+            // Jump to the value in register if below or equal (unsigned)
+            Instruction::JbeReg(reg) => {
+                let reg = Self::reg_index(reg);
+                let add = register!(self, reg)?;
 
-                if (self.flags & (CPU::F_NEGATIF | CPU::F_ZERO_VAL)) != 0 {
+                if self.below() || self.equal() {
                     self.set_register("ip", add)?;
                 }
                 Ok(())
             }
             // Add register to register
-            ADD_REG_REG => {
-                let r1 = self.fetch_reg()?;
-                let r2 = self.fetch_reg()?;
-
-                #[cfg(debug_assertions)]
-                {
-                    let r1n = REGISTER_NAMES[r1];
-                    let r2n = REGISTER_NAMES[r2];
-                    println!("Add {} and {}, store result in ACC", r1n, r2n);
-                }
+            Instruction::AddRegReg(r1, r2) => {
+                let r1 = Self::reg_index(r1);
+                let r2 = Self::reg_index(r2);
 
                 let r1_value = register!(self, r1)?;
                 let r2_value = register!(self, r2)?;
 
                 let (res, carry) = r1_value.overflowing_add(r2_value);
-                flag!(self, res, carry);
+                let overflow = (r1_value as i16).overflowing_add(r2_value as i16).1;
+                flag!(self, res, carry, overflow);
 
                 Ok(self.set_register("acc", res)?)
             }
             // Add register with literal
-            ADD_REG_LIT => {
-                let reg = self.fetch_reg()?;
-                let val = self.fetch_u16()?;
-
-                #[cfg(debug_assertions)]
-                {
-                    let reg_name = REGISTER_NAMES[reg];
-                    println!("Add {} and {:#06X}, store result in ACC", reg_name, val);
-                }
+            Instruction::AddRegLit(reg, val) => {
+                let reg = Self::reg_index(reg);
 
                 let reg_val = register!(self, reg)?;
                 let (res, carry) = val.overflowing_add(reg_val);
-                flag!(self, res, carry);
+                let overflow = (val as i16).overflowing_add(reg_val as i16).1;
+                flag!(self, res, carry, overflow);
 
                 Ok(self.set_register("acc", res)?)
             }
             // Substract register to register
-            SUB_REG_REG => {
-                let r1 = self.fetch_reg()?;
-                let r2 = self.fetch_reg()?;
-
-                #[cfg(debug_assertions)]
-                {
-                    let r1n = REGISTER_NAMES[r1];
-                    let r2n = REGISTER_NAMES[r2];
-                    println!("Substract {} from {}, store result in ACC", r1n, r2n);
-                }
+            Instruction::SubRegReg(r1, r2) => {
+                let r1 = Self::reg_index(r1);
+                let r2 = Self::reg_index(r2);
 
                 let r1_value = register!(self, r1)?;
                 let r2_value = register!(self, r2)?;
 
                 let (res, carry) = r2_value.overflowing_sub(r1_value);
-                flag!(self, res, carry);
+                let overflow = (r2_value as i16).overflowing_sub(r1_value as i16).1;
+                flag!(self, res, carry, overflow);
 
                 Ok(self.set_register("acc", res)?)
             }
             // Substract register with literal
-            SUB_REG_LIT => {
-                let reg = self.fetch_reg()?;
-                let val = self.fetch_u16()?;
-
-                #[cfg(debug_assertions)]
-                {
-                    let reg_name = REGISTER_NAMES[reg];
-                    println!("Substract {} from {:#06X}, store result in ACC", reg_name, val);
-                }
+            Instruction::SubRegLit(reg, val) => {
+                let reg = Self::reg_index(reg);
 
                 let reg_val = register!(self, reg)?;
                 let (res, carry) = val.overflowing_sub(reg_val);
-                flag!(self, res, carry);
+                let overflow = (val as i16).overflowing_sub(reg_val as i16).1;
+                flag!(self, res, carry, overflow);
 
                 Ok(self.set_register("acc", res)?)
             }
             // Substract register with literal
-            SUB_LIT_REG => {
-                let val = self.fetch_u16()?;
-                let reg = self.fetch_reg()?;
-
-                #[cfg(debug_assertions)]
-                {
-                    let reg_name = REGISTER_NAMES[reg];
-                    println!("Substract {:#06X} from {}, store result in ACC", val, reg_name);
-                }
+            Instruction::SubLitReg(val, reg) => {
+                let reg = Self::reg_index(reg);
 
                 let reg_val = register!(self, reg)?;
                 let (res, carry) = reg_val.overflowing_sub(val);
-                flag!(self, res, carry);
+                let overflow = (reg_val as i16).overflowing_sub(val as i16).1;
+                flag!(self, res, carry, overflow);
 
                 Ok(self.set_register("acc", res)?)
             }
             // Multiply register to register
-            MUL_REG_REG => {
-                let r1 = self.fetch_reg()?;
-                let r2 = self.fetch_reg()?;
-
-                #[cfg(debug_assertions)]
-                {
-                    let r1n = REGISTER_NAMES[r1];
-                    let r2n = REGISTER_NAMES[r2];
-                    println!("Multiply {} and {}, store result in ACC", r1n, r2n);
-                }
+            Instruction::MulRegReg(r1, r2) => {
+                let r1 = Self::reg_index(r1);
+                let r2 = Self::reg_index(r2);
 
                 let r1_value = register!(self, r1)?;
                 let r2_value = register!(self, r2)?;
 
                 let (res, carry) = r1_value.overflowing_mul(r2_value);
-                flag!(self, res, carry);
+                let overflow = (r1_value as i16).overflowing_mul(r2_value as i16).1;
+                flag!(self, res, carry, overflow);
 
                 Ok(self.set_register("acc", res)?)
             }
             // Multiply register with literal
-            MUL_REG_LIT => {
-                let reg = self.fetch_reg()?;
-                let val = self.fetch_u16()?;
-
-                #[cfg(debug_assertions)]
-                {
-                    let reg_name = REGISTER_NAMES[reg];
-                    println!("Multiply {} and {:#06X}, store result in ACC", reg_name, val);
-                }
+            Instruction::MulRegLit(reg, val) => {
+                let reg = Self::reg_index(reg);
 
                 let reg_val = register!(self, reg)?;
                 let (res, carry) = val.overflowing_mul(reg_val);
-                flag!(self, res, carry);
+                let overflow = (val as i16).overflowing_mul(reg_val as i16).1;
+                flag!(self, res, carry, overflow);
 
                 Ok(self.set_register("acc", res)?)
             }
-            CMP_REG_REG => {
-                let r1 = self.fetch_reg()?;
-                let r2 = self.fetch_reg()?;
+            // Divide register by register, quotient in ACC and remainder in the first register
+            Instruction::DivRegReg(r1, r2) => {
+                let r1 = Self::reg_index(r1);
+                let r2 = Self::reg_index(r2);
 
-                #[cfg(debug_assertions)]
-                {
-                    let r1_name = REGISTER_NAMES[r1];
-                    let r2_name = REGISTER_NAMES[r2];
-                    println!("Compare {} and {} values", r1_name, r2_name);
-                }
+                let r2_value = register!(self, r2)?;
+                let (acc, res) = self.divide(r1, r2_value, SIZE_OF[r2], false, false)?;
+
+                register!(self, r1 => res)?;
+                Ok(self.set_register("acc", acc)?)
+            }
+            // Divide register by literal, quotient in ACC and remainder in the register
+            Instruction::DivRegLit(reg, val) => {
+                let reg = Self::reg_index(reg);
+
+                let (acc, res) = self.divide(reg, val, 2, false, false)?;
+
+                register!(self, reg => res)?;
+                Ok(self.set_register("acc", acc)?)
+            }
+            // Modulo of register by register, remainder in ACC and quotient in the first register
+            Instruction::ModRegReg(r1, r2) => {
+                let r1 = Self::reg_index(r1);
+                let r2 = Self::reg_index(r2);
+
+                let r2_value = register!(self, r2)?;
+                let (acc, res) = self.divide(r1, r2_value, SIZE_OF[r2], false, true)?;
+
+                register!(self, r1 => res)?;
+                Ok(self.set_register("acc", acc)?)
+            }
+            // Modulo of register by literal, remainder in ACC and quotient in the register
+            Instruction::ModRegLit(reg, val) => {
+                let reg = Self::reg_index(reg);
+
+                let (acc, res) = self.divide(reg, val, 2, false, true)?;
+
+                register!(self, reg => res)?;
+                Ok(self.set_register("acc", acc)?)
+            }
+            // Signed divide register by register, quotient in ACC and remainder in the first register
+            Instruction::SdivRegReg(r1, r2) => {
+                let r1 = Self::reg_index(r1);
+                let r2 = Self::reg_index(r2);
+
+                let r2_value = register!(self, r2)?;
+                let (acc, res) = self.divide(r1, r2_value, SIZE_OF[r2], true, false)?;
+
+                register!(self, r1 => res)?;
+                Ok(self.set_register("acc", acc)?)
+            }
+            // Signed divide register by literal, quotient in ACC and remainder in the register
+            Instruction::SdivRegLit(reg, val) => {
+                let reg = Self::reg_index(reg);
+
+                let (acc, res) = self.divide(reg, val, 2, true, false)?;
+
+                register!(self, reg => res)?;
+                Ok(self.set_register("acc", acc)?)
+            }
+            // Signed modulo of register by register, remainder in ACC and quotient in the first register
+            Instruction::SmodRegReg(r1, r2) => {
+                let r1 = Self::reg_index(r1);
+                let r2 = Self::reg_index(r2);
+
+                let r2_value = register!(self, r2)?;
+                let (acc, res) = self.divide(r1, r2_value, SIZE_OF[r2], true, true)?;
+
+                register!(self, r1 => res)?;
+                Ok(self.set_register("acc", acc)?)
+            }
+            // Signed modulo of register by literal, remainder in ACC and quotient in the register
+            Instruction::SmodRegLit(reg, val) => {
+                let reg = Self::reg_index(reg);
+
+                let (acc, res) = self.divide(reg, val, 2, true, true)?;
+
+                register!(self, reg => res)?;
+                Ok(self.set_register("acc", acc)?)
+            }
+            Instruction::CmpRegReg(r1, r2) => {
+                let r1 = Self::reg_index(r1);
+                let r2 = Self::reg_index(r2);
 
                 let r1_val = register!(self, r1)?;
                 let r2_val = register!(self, r2)?;
-                let (res, carry) = r1_val.overflowing_sub(r2_val);
-                flag!(self, res, carry);
+                self.compare(r1, r1_val, r2_val);
                 Ok(())
             }
-            CMP_REG_LIT => {
-                let reg = self.fetch_reg()?;
-                let lit = self.fetch_u16()?;
-
-                #[cfg(debug_assertions)]
-                {
-                    let reg_name = REGISTER_NAMES[reg];
-                    println!("Compare {} value with {:#06X}", reg_name, lit);
-                }
+            Instruction::CmpRegLit(reg, lit) => {
+                let reg = Self::reg_index(reg);
 
                 let reg_val = register!(self, reg)?;
-                let (res, carry) = reg_val.overflowing_sub(lit);
-                flag!(self, res, carry);
+                self.compare(reg, reg_val, lit);
                 Ok(())
             }
             // Increment register value by one
-            INC_REG => {
-                let reg = self.fetch_reg()?;
+            Instruction::IncReg(reg) => {
+                let reg = Self::reg_index(reg);
                 let add = ADDRESS_OF[reg];
 
-                #[cfg(debug_assertions)]
-                {
-                    let reg_name = REGISTER_NAMES[reg];
-                    println!("Increment {} value by one", reg_name);
-                }
-
                 match SIZE_OF[reg] {
                     1 => {
                         let val = self.registers.get_memory_at_u8(add)?;
                         let (res, carry) = val.overflowing_add(1);
-                        flag!(self, res, carry);
+                        let overflow = (val as i8).overflowing_add(1).1;
+                        flag!(self, res, carry, overflow);
 
-                        Ok(self.registers.set_memory_at_u8(add, res)?)
+                        let res = res as u16;
+                        Ok(register!(self, reg => res)?)
                     }
                     2 => {
                         let val = self.registers.get_memory_at_u16(add)?;
                         let (res, carry) = val.overflowing_add(1);
-                        flag!(self, res, carry);
+                        let overflow = (val as i16).overflowing_add(1).1;
+                        flag!(self, res, carry, overflow);
 
-                        Ok(self.registers.set_memory_at_u16(add, res)?)
+                        Ok(register!(self, reg => res)?)
                     }
                     x => Err(ExecutionError::from(MemoryError::BadRegisterLen(x))),
                 }
             }
             // Decrement register value by one
-            DEC_REG => {
-                let reg = self.fetch_reg()?;
+            Instruction::DecReg(reg) => {
+                let reg = Self::reg_index(reg);
                 let add = ADDRESS_OF[reg];
 
-                #[cfg(debug_assertions)]
-                {
-                    let reg_name = REGISTER_NAMES[reg];
-                    println!("Increment {} value by one", reg_name);
-                }
-
                 match SIZE_OF[reg] {
                     1 => {
                         let val = self.registers.get_memory_at_u8(add)?;
                         let (res, carry) = val.overflowing_sub(1);
-                        flag!(self, res, carry);
+                        let overflow = (val as i8).overflowing_sub(1).1;
+                        flag!(self, res, carry, overflow);
 
-                        Ok(self.registers.set_memory_at_u8(add, res)?)
+                        let res = res as u16;
+                        Ok(register!(self, reg => res)?)
                     }
                     2 => {
                         let val = self.registers.get_memory_at_u16(add)?;
                         let (res, carry) = val.overflowing_sub(1);
-                        flag!(self, res, carry);
+                        let overflow = (val as i16).overflowing_sub(1).1;
+                        flag!(self, res, carry, overflow);
 
-                        Ok(self.registers.set_memory_at_u16(add, res)?)
+                        Ok(register!(self, reg => res)?)
                     }
                     x => Err(ExecutionError::from(MemoryError::BadRegisterLen(x))),
                 }
             }
             // Push Literal on Stack
-            PSH_LIT => {
-                let value = self.fetch_u16()?;
-
-                #[cfg(debug_assertions)]
-                println!(
-                    "Push {:#06X} (literal) on stack, decrement stack pointer",
-                    value
-                );
-
+            Instruction::PshLit(value) => {
                 flag!(self, value);
                 self.push(value)
             }
             // Push register on stack
-            PSH_REG => {
-                let register_index = self.fetch_reg()?;
+            Instruction::PshReg(register_index) => {
+                let register_index = Self::reg_index(register_index);
                 let value = register!(self, register_index)?;
 
-                #[cfg(debug_assertions)]
-                {
-                    let reg_name = REGISTER_NAMES[register_index];
-                    println!(
-                        "Push {:#06X} (value on {}) on stack, decrement stack pointer",
-                        value, reg_name
-                    );
-                }
-
                 flag!(self, value);
                 self.push(value)
             }
             // Push memory on stack
-            PSH_MEM8 => {
-                let memory_add = self.fetch_u16()? as usize;
+            Instruction::PshMem8(memory_add) => {
+                let memory_add = memory_add as usize;
                 let value = self.memory.get_memory_at_u8(memory_add)?;
 
-                #[cfg(debug_assertions)]
-                println!(
-                    "Push {:#06X} (value on memory {:#06X}) on stack, decrement stack pointer",
-                    value, memory_add
-                );
-
                 flag!(self, value);
                 self.push(value as u16)
             }
             // Push memory on stack
-            PSH_MEM16 => {
-                let memory_add = self.fetch_u16()? as usize;
+            Instruction::PshMem16(memory_add) => {
+                let memory_add = memory_add as usize;
                 let value = self.memory.get_memory_at_u16(memory_add)?;
 
-                #[cfg(debug_assertions)]
-                println!(
-                    "Push {:#06X} (value on memory {:#06X}) on stack, decrement stack pointer",
-                    value, memory_add
-                );
-
                 flag!(self, value);
                 self.push(value)
             }
             // Push memory poinyed by register on stack
-            PSH_PTRREG8 => {
-                let reg = self.fetch_reg()?;
+            Instruction::PshPtrReg8(reg) => {
+                let reg = Self::reg_index(reg);
 
                 match SIZE_OF[reg] {
                     1 => Err(ExecutionError::BadRegisterPtrLen),
                     2 => {
                         let add = self.registers.get_memory_at_u16(ADDRESS_OF[reg])? as usize;
-                        let value = self.memory.get_memory_at_u8(add)? as u16;
-
-                        #[cfg(debug_assertions)]
-                        {
-                            let reg_name = REGISTER_NAMES[reg];
-                            println!(
-                                "Push {:#04X} (value on memory {:#06X} pointed by {}) on stack, decrement stack pointer",
-                                value, add, reg_name
-                            );
-                        }
+                        let value = self.memory.get_memory_at_u8(add)?;
 
                         flag!(self, value);
-                        self.push(value)
+                        self.push(value as u16)
                     }
                     x => Err(ExecutionError::from(MemoryError::BadRegisterLen(x)))
                 }
             }
             // Push memory poinyed by register on stack
-            PSH_PTRREG16 => {
-                let reg = self.fetch_reg()?;
+            Instruction::PshPtrReg16(reg) => {
+                let reg = Self::reg_index(reg);
 
                 match SIZE_OF[reg] {
                     1 => Err(ExecutionError::BadRegisterPtrLen),
@@ -827,15 +979,6 @@ impl CPU {
                         let add = self.registers.get_memory_at_u16(ADDRESS_OF[reg])? as usize;
                         let value = self.memory.get_memory_at_u16(add)?;
 
-                        #[cfg(debug_assertions)]
-                        {
-                            let reg_name = REGISTER_NAMES[reg];
-                            println!(
-                                "Push {:#06X} (value on memory {:#06X} pointed by {}) on stack, decrement stack pointer",
-                                value, add, reg_name
-                            );
-                        }
-
                         flag!(self, value);
                         self.push(value)
                     }
@@ -843,55 +986,34 @@ impl CPU {
                 }
             }
             // Pop stack head to given register
-            POP_REG => {
-                let reg = self.fetch_reg()?;
+            Instruction::PopReg(reg) => {
+                let reg = Self::reg_index(reg);
                 let value = self.pop()?;
 
-                #[cfg(debug_assertions)]
-                {
-                    let reg_name = REGISTER_NAMES[reg];
-                    println!(
-                        "Pop {:#06X} (value on stack) to {}, increment stack pointer",
-                        value, reg_name
-                    );
-                }
-
-                flag!(self, value);
+                flag!(self, reg => value);
                 register!(self, reg => value)?;
                 Ok(())
             }
             // Pop stack head to given memory address
-            POP_MEM8 => {
-                let memory_add = self.fetch_u16()? as usize;
+            Instruction::PopMem8(memory_add) => {
+                let memory_add = memory_add as usize;
                 let value = self.pop()?;
 
-                #[cfg(debug_assertions)]
-                println!(
-                    "Pop {:#06X} (value on stack) to memory {:#06X}, increment stack pointer",
-                    value, memory_add
-                );
-
                 flag!(self, value);
                 self.memory.set_memory_at_u8(memory_add, value as u8)?;
                 Ok(())
             }
-            POP_MEM16 => {
-                let memory_add = self.fetch_u16()? as usize;
+            Instruction::PopMem16(memory_add) => {
+                let memory_add = memory_add as usize;
                 let value = self.pop()?;
 
-                #[cfg(debug_assertions)]
-                println!(
-                    "Pop {:#06X} (value on stack) to memory {:#06X}, increment stack pointer",
-                    value, memory_add
-                );
-
                 flag!(self, value);
                 self.memory.set_memory_at_u16(memory_add, value)?;
                 Ok(())
             }
             // Pop stack head to memory address pointed by register
-            POP_PTRREG8 => {
-                let reg = self.fetch_reg()?;
+            Instruction::PopPtrReg8(reg) => {
+                let reg = Self::reg_index(reg);
 
                 match SIZE_OF[reg] {
                     1 => Err(ExecutionError::BadRegisterPtrLen),
@@ -899,15 +1021,6 @@ impl CPU {
                         let add = self.registers.get_memory_at_u16(ADDRESS_OF[reg])?;
                         let value = self.pop()? as u8;
 
-                        #[cfg(debug_assertions)]
-                        {
-                            let reg_name = REGISTER_NAMES[reg];
-                            println!(
-                                "Pop {:#04X} (value on stack) to memory {:#06X} pointed by {}, increment stack pointer",
-                                value, add, reg_name
-                            );
-                        }
-
                         flag!(self, value);
                         Ok(self.memory.set_memory_at_u8(add as usize, value)?)
                     }
@@ -915,8 +1028,8 @@ impl CPU {
                 }
             }
             // Pop stack head to memory address pointed by register
-            POP_PTRREG16 => {
-                let reg = self.fetch_reg()?;
+            Instruction::PopPtrReg16(reg) => {
+                let reg = Self::reg_index(reg);
 
                 match SIZE_OF[reg] {
                     1 => Err(ExecutionError::BadRegisterPtrLen),
@@ -924,371 +1037,602 @@ impl CPU {
                         let add = self.registers.get_memory_at_u16(ADDRESS_OF[reg])?;
                         let value = self.pop()?;
 
-                        #[cfg(debug_assertions)]
-                        {
-                            let reg_name = REGISTER_NAMES[reg];
-                            println!(
-                                "Pop {:#06X} (value on stack) to memory {:#06X} pointed by {}, increment stack pointer",
-                                value, add, reg_name
-                            );
-                        }
-
                         flag!(self, value);
                         Ok(self.memory.set_memory_at_u16(add as usize, value)?)
                     }
                     x => Err(ExecutionError::from(MemoryError::BadRegisterLen(x))),
                 }
             }
-            // call a function with literal address
-            CALL_LIT => {
-                let address = self.fetch_u16()?;
-
-                #[cfg(debug_assertions)]
-                println!("Call a subroutine at {:#06X} with literal", address);
+            // Push the flags on stack
+            Instruction::Pushf => {
+                self.push(self.flags() as u16)
+            }
+            // Pop stack in the flags
+            Instruction::Popf => {
+                let flags = self.pop()? as u8;
 
-                self.call(address)
+                self.set_flags(flags);
+                Ok(())
+            }
+            // call a function with literal address
+            Instruction::CallLit(address) => {
+                self.call(address, 0)
             }
             // call a function with a register value
-            CALL_REG => {
-                let reg = self.fetch_reg()?;
+            Instruction::CallReg(reg) => {
+                let reg = Self::reg_index(reg);
 
                 match SIZE_OF[reg] {
                     1 => Err(ExecutionError::BadRegisterPtrLen),
                     2 => {
                         let address = self.registers.get_memory_at_u16(ADDRESS_OF[reg])?;
 
-                        #[cfg(debug_assertions)]
-                        {
-                            let reg_name = REGISTER_NAMES[reg];
-                            println!(
-                                "Call a subroutine at {:#06X} (stored in register {})",
-                                address, reg_name
-                            );
-                        }
+                        self.call(address, 0)
+                    }
+                    x => Err(ExecutionError::from(MemoryError::BadRegisterLen(x))),
+                }
+            }
+            // call a function with literal address and `args` argument words on stack
+            Instruction::CallLitArgs(address, args) => {
+                self.call(address, args)
+            }
+            // call a function with a register value and `args` argument words on stack
+            Instruction::CallRegArgs(reg, args) => {
+                let reg = Self::reg_index(reg);
 
-                        self.call(address)
+                match SIZE_OF[reg] {
+                    1 => Err(ExecutionError::BadRegisterPtrLen),
+                    2 => {
+                        let address = self.registers.get_memory_at_u16(ADDRESS_OF[reg])?;
+
+                        self.call(address, args)
                     }
                     x => Err(ExecutionError::from(MemoryError::BadRegisterLen(x))),
                 }
             }
-            // return from subroutine
-            RET => {
-                #[cfg(debug_assertions)]
-                println!("Return from a subroutine");
+            // return from subroutine, discarding the arguments given to `cal`
+            Instruction::Ret => {
+                self.restor(None)
+            }
+            // return from subroutine, discarding `args` argument words
+            Instruction::RetLit(args) => {
+                self.restor(Some(args))
+            }
+            // call a function with literal address, only saving the return address
+            Instruction::JsrLit(address) => {
+                self.push(self.get_register("ip")?)?;
+                Ok(self.set_register("ip", address)?)
+            }
+            // call a function with a register value, only saving the return address
+            Instruction::JsrReg(reg) => {
+                let reg = Self::reg_index(reg);
+
+                match SIZE_OF[reg] {
+                    1 => Err(ExecutionError::BadRegisterPtrLen),
+                    2 => {
+                        let address = self.registers.get_memory_at_u16(ADDRESS_OF[reg])?;
 
-                self.restor()
+                        self.push(self.get_register("ip")?)?;
+                        Ok(self.set_register("ip", address)?)
+                    }
+                    x => Err(ExecutionError::from(MemoryError::BadRegisterLen(x))),
+                }
+            }
+            // return from subroutine called with `jsr`
+            Instruction::Rts => {
+                let address = self.pop()?;
+                Ok(self.set_register("ip", address)?)
             }
             // Left shift register with other register
-            LSF_REG_REG => {
-                let r1 = self.fetch_reg()?;
-                let r2 = self.fetch_reg()?;
-
-                #[cfg(debug_assertions)]
-                {
-                    let r1n = REGISTER_NAMES[r1];
-                    let r2n = REGISTER_NAMES[r2];
-                    println!("Left shift {} and {}, in {}", r1n, r2n, r1n);
-                }
+            Instruction::LsfRegReg(r1, r2) => {
+                let r1 = Self::reg_index(r1);
+                let r2 = Self::reg_index(r2);
 
                 let r1_value = register!(self, r1)?;
                 let r2_value = register!(self, r2)?;
                 let res = r1_value << r2_value;
 
-                flag!(self, res);
+                flag!(self, r1 => res);
                 Ok(register!(self, r1 => res)?)
             }
             // Left shift register with literal
-            LSF_REG_LIT => {
-                let r1 = self.fetch_reg()?;
-                let literal = self.fetch_u16()?;
-
-                #[cfg(debug_assertions)]
-                {
-                    let r1n = REGISTER_NAMES[r1];
-                    println!("Left shift {} and {:#06X}, in {}", r1n, literal, r1n);
-                }
+            Instruction::LsfRegLit(r1, literal) => {
+                let r1 = Self::reg_index(r1);
 
                 let val = register!(self, r1)?;
                 let res = val << literal;
 
-                flag!(self, res);
+                flag!(self, r1 => res);
                 Ok(register!(self, r1 => res)?)
             }
             // Right shift register with other register
-            RSF_REG_REG => {
-                let r1 = self.fetch_reg()?;
-                let r2 = self.fetch_reg()?;
-
-                #[cfg(debug_assertions)]
-                {
-                    let r1n = REGISTER_NAMES[r1];
-                    let r2n = REGISTER_NAMES[r2];
-                    println!("Right shift {} and {}, in {}", r1n, r2n, r1n);
-                }
+            Instruction::RsfRegReg(r1, r2) => {
+                let r1 = Self::reg_index(r1);
+                let r2 = Self::reg_index(r2);
 
                 let r1_value = register!(self, r1)?;
                 let r2_value = register!(self, r2)?;
                 let res = r1_value >> r2_value;
 
-                flag!(self, res);
+                flag!(self, r1 => res);
                 Ok(register!(self, r1 => res)?)
             }
             // Right shift register with literal
-            RSF_REG_LIT => {
-                let r1 = self.fetch_reg()?;
-                let literal = self.fetch_u16()?;
-
-                #[cfg(debug_assertions)]
-                {
-                    let r1n = REGISTER_NAMES[r1];
-                    println!("Right shift {} and {:#06X}, in {}", r1n, literal, r1n);
-                }
+            Instruction::RsfRegLit(r1, literal) => {
+                let r1 = Self::reg_index(r1);
 
                 let val = register!(self, r1)?;
                 let res = val >> literal;
 
-                flag!(self, res);
+                flag!(self, r1 => res);
                 Ok(register!(self, r1 => res)?)
             }
             // AND register with other register
-            AND_REG_REG => {
-                let r1 = self.fetch_reg()?;
-                let r2 = self.fetch_reg()?;
-
-                #[cfg(debug_assertions)]
-                {
-                    let r1n = REGISTER_NAMES[r1];
-                    let r2n = REGISTER_NAMES[r2];
-                    println!("AND {} and {}, in {}", r1n, r2n, r1n);
-                }
+            Instruction::AndRegReg(r1, r2) => {
+                let r1 = Self::reg_index(r1);
+                let r2 = Self::reg_index(r2);
 
                 let r1_value = register!(self, r1)?;
                 let r2_value = register!(self, r2)?;
                 let res = r1_value & r2_value;
 
-                flag!(self, res);
+                flag!(self, r1 => res);
                 Ok(register!(self, r1 => res)?)
             }
             // AND register with literal
-            AND_REG_LIT => {
-                let r1 = self.fetch_reg()?;
-                let literal = self.fetch_u16()?;
-
-                #[cfg(debug_assertions)]
-                {
-                    let r1n = REGISTER_NAMES[r1];
-                    println!("AND {} and {:#06X}, in {}", r1n, literal, r1n);
-                }
+            Instruction::AndRegLit(r1, literal) => {
+                let r1 = Self::reg_index(r1);
 
                 let val = register!(self, r1)?;
                 let res = val & literal;
 
-                flag!(self, res);
+                flag!(self, r1 => res);
                 Ok(register!(self, r1 => res)?)
             }
             // OR register with other register
-            OR_REG_REG => {
-                let r1 = self.fetch_reg()?;
-                let r2 = self.fetch_reg()?;
-
-                #[cfg(debug_assertions)]
-                {
-                    let r1n = REGISTER_NAMES[r1];
-                    let r2n = REGISTER_NAMES[r2];
-                    println!("OR {} and {}, in {}", r1n, r2n, r1n);
-                }
+            Instruction::OrRegReg(r1, r2) => {
+                let r1 = Self::reg_index(r1);
+                let r2 = Self::reg_index(r2);
 
                 let r1_value = register!(self, r1)?;
                 let r2_value = register!(self, r2)?;
                 let res = r1_value | r2_value;
 
-                flag!(self, res);
+                flag!(self, r1 => res);
                 Ok(register!(self, r1 => res)?)
             }
             // OR register with literal
-            OR_REG_LIT => {
-                let r1 = self.fetch_reg()?;
-                let literal = self.fetch_u16()?;
-
-                #[cfg(debug_assertions)]
-                {
-                    let r1n = REGISTER_NAMES[r1];
-                    println!("OR {} and {:#06X}, in {}", r1n, literal, r1n);
-                }
+            Instruction::OrRegLit(r1, literal) => {
+                let r1 = Self::reg_index(r1);
 
                 let val = register!(self, r1)?;
                 let res = val | literal;
 
-                flag!(self, res);
+                flag!(self, r1 => res);
                 Ok(register!(self, r1 => res)?)
             }
             // Xor register with other register
-            XOR_REG_REG => {
-                let r1 = self.fetch_reg()?;
-                let r2 = self.fetch_reg()?;
-
-                #[cfg(debug_assertions)]
-                {
-                    let r1n = REGISTER_NAMES[r1];
-                    let r2n = REGISTER_NAMES[r2];
-                    println!("Xor {} and {}, in {}", r1n, r2n, r1n);
-                }
+            Instruction::XorRegReg(r1, r2) => {
+                let r1 = Self::reg_index(r1);
+                let r2 = Self::reg_index(r2);
 
                 let r1_value = register!(self, r1)?;
                 let r2_value = register!(self, r2)?;
                 let res = r1_value ^ r2_value;
 
-                flag!(self, res);
+                flag!(self, r1 => res);
                 Ok(register!(self, r1 => res)?)
             }
             // Xor register with literal
-            XOR_REG_LIT => {
-                let r1 = self.fetch_reg()?;
-                let literal = self.fetch_u16()?;
-
-                #[cfg(debug_assertions)]
-                {
-                    let r1n = REGISTER_NAMES[r1];
-                    println!("Xor {} and {:#06X}, in {}", r1n, literal, r1n);
-                }
+            Instruction::XorRegLit(r1, literal) => {
+                let r1 = Self::reg_index(r1);
 
                 let val = register!(self, r1)?;
                 let res = val ^ literal;
 
-                flag!(self, res);
+                flag!(self, r1 => res);
                 Ok(register!(self, r1 => res)?)
             }
             // NOT register in place
-            NOT => {
-                let r1 = self.fetch_reg()?;
-
-                #[cfg(debug_assertions)]
-                {
-                    let r1n = REGISTER_NAMES[r1];
-                    println!("NOT {}, in {}", r1n, r1n);
-                }
+            Instruction::Not(r1) => {
+                let r1 = Self::reg_index(r1);
 
                 let val = register!(self, r1)?;
                 let res = !val;
 
-                flag!(self, res);
+                flag!(self, r1 => res);
                 Ok(register!(self, r1 => res)?)
             }
+            // Software interrupt, call the handler of the vector
+            Instruction::IntLit(vector) => {
+                self.interrupt(vector)
+            }
+            // Return from an interrupt handler, restoring the flags
+            Instruction::Iret => {
+                self.privileged("iret")?;
+                self.restor(None)?;
+                let flags = self.pop()? as u8;
+                self.set_flags(flags);
+                Ok(())
+            }
+            // Disable interrupts
+            Instruction::Cli => {
+                self.privileged("cli")?;
+                self.set_flags(self.flags() & !CPU::F_INTERRUPT);
+                Ok(())
+            }
+            // Enable interrupts
+            Instruction::Sti => {
+                self.privileged("sti")?;
+                self.set_flags(self.flags() | CPU::F_INTERRUPT);
+                Ok(())
+            }
+            // Call the handler of a syscall in supervisor mode
+            Instruction::SysLit(syscall) => {
+                self.syscall(syscall)
+            }
+            // Call a function of the host
+            Instruction::HostcallLit(id) => {
+                self.host_call(id)
+            }
             // End execution
-            END => {
-                #[cfg(debug_assertions)]
-                println!("End of execution");
-
-                Err(ExecutionError::EndOfExecution)
+            // Stop on a breakpoint, the next step goes on after it
+            Instruction::Brk => {
+                Err(ExecutionError::Breakpoint)
             }
-            code => {
-                #[cfg(debug_assertions)]
-                println!(
-                    "<ERROR> => The instruction {:#04X} is not known by this CPU\n",
-                    code
-                );
-
-                Err(ExecutionError::UnexpectedInstruction(code))
+            Instruction::End => {
+                Err(ExecutionError::EndOfExecution)
             }
         }
     }
 
     fn push(&mut self, value: u16) -> Result<(), ExecutionError> {
         let sp_address = self.get_register("sp")?;
+        let next = sp_address.checked_sub(2)
+            .filter(|_| sp_address >= self.stack_limit)
+            .ok_or(ExecutionError::StackOverflow)?;
         self.memory.set_memory_at_u16(sp_address as usize, value)?;
 
         self.stack_frame_size += 2;
-        Ok(self.set_register("sp", sp_address - 2)?)
+        Ok(self.set_register("sp", next)?)
     }
 
     fn pop(&mut self) -> Result<u16, ExecutionError> {
-        let (head, carry) = self.get_register("sp")?.overflowing_add(2);
-        if carry {
-            return Err(ExecutionError::BadReturn);
-        }
+        let head = self.get_register("sp")?.checked_add(2)
+            .filter(|head| *head <= self.stack_base)
+            .ok_or(ExecutionError::StackUnderflow)?;
         self.set_register("sp", head)?;
 
-        self.stack_frame_size -= 2;
+        // `sp` can be moved by the program, the frame size may not follow it
+        self.stack_frame_size = self.stack_frame_size.saturating_sub(2);
         Ok(self.memory.get_memory_at_u16(head as usize)?)
     }
 
-    // This methode save all registers in the stack and create a new stackframe.
-    // Once the stackframe is created, the function jump to `address` given
-    fn call(&mut self, address: u16) -> Result<(), ExecutionError> {
-        let reg_to_save = ["ax", "bx", "cx", "dx", "ex", "fx", "gx", "hx", "ip"];
-
-        // save all registers from R1 to R8 plus ip
-        for reg in reg_to_save.iter() {
-            self.push(self.get_register(reg)?)?;
-        }
-
-        // Save the size of the stackframe
-        self.push(self.stack_frame_size as u16 + 2)?;
+    // Create a new stackframe and jump to `address`, `args` words pushed before the call are
+    // the arguments. The frame pointer points under the argument count, the return address and
+    // the previous frame pointer, then registers are saved under it:
+    //
+    //   fp+8   first argument, pushed last
+    //   fp+6   argument count
+    //   fp+4   return address
+    //   fp+2   frame pointer of the caller
+    //   fp     ax, then bx ... hx down to fp-14, then the flags with `save_flags`
+    fn call(&mut self, address: u16, args: u16) -> Result<(), ExecutionError> {
+        self.push(args)?;
+        self.push(self.get_register("ip")?)?;
+        self.push(self.get_register("fp")?)?;
 
         // create a new stackframe
         self.set_register("fp", self.get_register("sp")?)?;
         self.stack_frame_size = 0;
 
+        // save all registers from R1 to R8
+        for reg in CPU::SAVED_REGISTERS.iter() {
+            self.push(self.get_register(reg)?)?;
+        }
+
+        if self.save_flags {
+            self.push(self.flags() as u16)?;
+        }
+
         // jump to given address
         self.set_register("ip", address)?;
         Ok(())
     }
 
-    // This methode save all registers in the stack and create a new stackframe.
-    // Once the stackframe is created, the function jump to `address` given
-    fn restor(&mut self) -> Result<(), ExecutionError> {
-        // erase the current stackframe
-        let fp_addr = self.get_register("fp")?;
-        self.set_register("sp", fp_addr)?;
-
-        // set stack_frame_size to 2 to avoid neg number on pop
-        self.stack_frame_size = 2;
-
-        // Restor the stackframe size and update start of stackframe
-        let sf_size = self.pop()?;
-        self.stack_frame_size = sf_size as usize;
-        self.set_register("fp", sf_size)?;
-
-        // Restor all registers, in reverse order than `call` do
-        let reg_to_load = ["ip", "hx", "gx", "fx", "ex", "dx", "cx", "bx", "ax"];
-        for reg in reg_to_load.iter() {
-            let stack_value = self.pop()?;
-            self.set_register(reg, stack_value)?;
+    // Restore the registers saved by `call` and erase the stackframe, with the arguments.
+    // `args` gives the number of argument words to discard instead of the count saved by `call`
+    fn restor(&mut self, args: Option<u16>) -> Result<(), ExecutionError> {
+        let fp = self.get_register("fp")?;
+        let read = |cpu: &Self, offset: u16| -> Result<u16, ExecutionError> {
+            Ok(cpu.memory.get_memory_at_u16(fp.wrapping_sub(offset) as usize)?)
+        };
+
+        // the top frame has no caller, its count would be over the stack base
+        let top = fp.checked_add(6)
+            .filter(|top| *top <= self.stack_base)
+            .ok_or(ExecutionError::StackUnderflow)?;
+        let count = self.memory.get_memory_at_u16(top as usize)?;
+
+        // erase the stackframe and the arguments
+        let sp = args.unwrap_or(count)
+            .checked_mul(2)
+            .and_then(|len| top.checked_add(len))
+            .filter(|sp| *sp <= self.stack_base)
+            .ok_or(ExecutionError::StackUnderflow)?;
+
+        // Restore all registers, saved under the frame pointer
+        for (id, reg) in CPU::SAVED_REGISTERS.iter().enumerate() {
+            let value = read(self, id as u16 * 2)?;
+            self.set_register(reg, value)?;
+        }
+
+        if self.save_flags {
+            let flags = read(self, CPU::SAVED_REGISTERS.len() as u16 * 2)? as u8;
+            self.set_flags(flags);
         }
 
-        // in ep004 at 13:33, we can see a part of code that pop "args"
-        // 0x4F don't realy understand what that shit mean and decide
-        // to not reproduce this code here. He expect that the
-        // `Kink kryod` understand this and don't decide to kill him.
+        let caller_fp = self.memory.get_memory_at_u16(fp as usize + 2)?;
+        let ip = self.memory.get_memory_at_u16(fp as usize + 4)?;
+
+        self.set_register("sp", sp)?;
+        self.set_register("fp", caller_fp)?;
+        self.set_register("ip", ip)?;
+        self.stack_frame_size = caller_fp.wrapping_sub(sp) as usize;
 
         Ok(())
     }
 
-    pub fn step(&mut self) -> bool {
-        match self.fetch_u8() {
-            Ok(int) => match self.execute(int) {
-                Ok(_) => true,
-                Err(err) => {
-                    match err {
-                        ExecutionError::EndOfExecution => (),
-                        _ => println!("{:?}", err),
-                    }
+    // Save the flags and call the handler of `vector` like `call` does.
+    // Interrupts are disabled until `iret` restores the flags
+    fn interrupt(&mut self, vector: u16) -> Result<(), ExecutionError> {
+        if vector >= VECTOR_COUNT {
+            return Err(ExecutionError::BadInterrupt(vector));
+        }
 
-                    false
-                }
+        match self.trap(vector_address(vector), CPU::F_INTERRUPT)? {
+            true => Ok(()),
+            false => Err(ExecutionError::NoInterruptHandler(vector)),
+        }
+    }
+
+    // Call the handler of `syscall`, interrupts stay enabled
+    fn syscall(&mut self, syscall: u16) -> Result<(), ExecutionError> {
+        if syscall >= SYSCALL_COUNT {
+            return Err(ExecutionError::BadSyscall(syscall));
+        }
+
+        match self.trap(syscall_address(syscall), 0)? {
+            true => Ok(()),
+            false => Err(ExecutionError::NoSyscallHandler(syscall)),
+        }
+    }
+
+    // Enter supervisor mode, clearing the flags of `mask`, and call the handler stored at
+    // `entry` like `call` does. The flags are saved first, `iret` restores them with the mode.
+    // Returns false when the handler address is null, the mode is only changed for a handler
+    fn trap(&mut self, entry: u16, mask: u8) -> Result<bool, ExecutionError> {
+        let flags = self.flags();
+
+        // the tables are supervisor-only, they are read with the access of the supervisor
+        self.memory.set_user_mode(false);
+        let handler = self.memory.get_memory_at_u16(entry as usize);
+        self.memory.set_user_mode(flags & CPU::F_SUPERVISOR == 0);
+
+        let handler = handler?;
+        if handler == 0 {
+            return Ok(false);
+        }
+
+        self.write_flags((flags | CPU::F_SUPERVISOR) & !mask);
+        let res = self.push(flags as u16).and_then(|_| self.call(handler, 0));
+        if res.is_err() {
+            self.write_flags(flags);
+        }
+
+        res.map(|_| true)
+    }
+
+    /// Raise the interrupt line `line`, it is serviced once interrupts are enabled
+    pub fn raise_interrupt(&mut self, line: u8) {
+        self.pending |= 1 << (line as u16 % VECTOR_COUNT);
+    }
+
+    // Collect the lines raised by the devices and, if interrupts are
+    // enabled, service the pending line with the lowest number
+    fn service_interrupts(&mut self) -> Result<(), ExecutionError> {
+        self.pending |= self.memory.poll_interrupts()?;
+        if self.flags() & CPU::F_INTERRUPT == 0 || self.pending == 0 {
+            return Ok(());
+        }
+
+        let line = self.pending.trailing_zeros() as u16;
+        self.pending &= !(1 << line);
+
+        self.interrupt(line)?;
+
+        // entering the handler takes the cycles of `int`
+        self.cycles += opcode_info(INT_LIT).map_or(0, |info| info.cycles as u64);
+        Ok(())
+    }
+
+    /// Service the pending interrupt, if any, then execute the next instruction
+    pub fn step(&mut self) -> StepResult {
+        self.memory.set_instruction(self.instructions);
+        let mut ip = self.registers.get_memory_at_u16(ADDRESS_OF[IP as usize]).unwrap_or(0);
+        let mut cycles = 0;
+        let res = self.service_interrupts()
+            .and_then(|_| {
+                ip = self.registers.get_memory_at_u16(ADDRESS_OF[IP as usize])?;
+                self.fetch()
+            })
+            .and_then(|ins| {
+                cycles = ins.info().cycles;
+                self.execute(ins)
+            });
+
+        match res {
+            Ok(_) => {
+                self.count(cycles);
+                self.memory.tick();
+                StepResult::Continue
+            },
+            Err(ExecutionError::Breakpoint) => {
+                self.count(cycles);
+                self.memory.tick();
+                StepResult::Breakpoint
+            },
+            Err(ExecutionError::EndOfExecution) => {
+                self.count(cycles);
+                StepResult::Halted
             },
-            Err(err) => {
-                println!("{:?}", err);
-                false
+            Err(error) => {
+                let opcode = self.memory.get_memory_at_u8(ip as usize).ok();
+                StepResult::Fault { ip, opcode, error }
+            },
+        }
+    }
+
+    /// Execute instructions until the program halts, faults or reaches a breakpoint
+    pub fn run(&mut self) -> StepResult {
+        self.run_while(|_| true)
+    }
+
+    /// Like `run`, but stop once `cycles` cycles were executed. The last instruction can
+    /// end a few cycles after the budget
+    pub fn run_for(&mut self, cycles: u64) -> StepResult {
+        let end = self.cycles.saturating_add(cycles);
+        self.run_while(|cpu| cpu.cycles < end)
+    }
+
+    /// Like `run`, but stop once `steps` instructions were executed
+    pub fn run_steps(&mut self, steps: u64) -> StepResult {
+        let end = self.instructions.saturating_add(steps);
+        self.run_while(|cpu| cpu.instructions < end)
+    }
+
+    fn run_while<F: Fn(&CPU) -> bool>(&mut self, budget: F) -> StepResult {
+        loop {
+            if !budget(self) {
+                return StepResult::BudgetExhausted;
+            }
+
+            match self.step() {
+                StepResult::Continue => (),
+                res => return res,
             }
         }
     }
 
-    // DEBUG FUNCTION DO NOT LEAVE IN RELEASE
-    pub fn set_instruction(&mut self, instructions: &[u8]) {
-        for (id, ins) in instructions.iter().enumerate() {
-            self.memory.set_memory_at_u8(id, *ins).unwrap();
+    fn count(&mut self, cycles: u8) {
+        self.cycles += cycles as u64;
+        self.instructions += 1;
+    }
+
+    /// Cycles executed since the start, see `arch::instructions::OPCODES`
+    pub fn cycles(&self) -> u64 {
+        self.cycles
+    }
+
+    /// Instructions executed since the start
+    pub fn instructions(&self) -> u64 {
+        self.instructions
+    }
+
+    /// Record the input of the devices, see `MemoryMap::record`
+    pub fn record_input(&mut self) {
+        self.memory.record();
+    }
+
+    /// Stop recording, returns the input recorded since `record_input`
+    pub fn take_input_log(&mut self) -> Option<InputLog> {
+        self.memory.take_recording()
+    }
+
+    /// Run again with the input recorded in `log`, see `MemoryMap::replay`
+    pub fn replay_input(&mut self, log: InputLog) {
+        self.memory.replay(log);
+    }
+
+    /// Save the whole state of the machine, it is restored with `restore`
+    pub fn snapshot(&self) -> Snapshot {
+        Snapshot {
+            registers: self.registers.save_state(),
+            stack_frame_size: self.stack_frame_size,
+            stack_base: self.stack_base,
+            stack_limit: self.stack_limit,
+            save_flags: self.save_flags,
+            pending: self.pending,
+            cycles: self.cycles,
+            instructions: self.instructions,
+            protections: self.memory.protections(),
+            devices: self.memory.save_devices(),
+        }
+    }
+
+    /// Restore a snapshot taken on a machine with the same devices. Host functions and
+    /// debug information are kept, like the input recorded or replayed: the snapshot doesn't
+    /// hold the position in the input log
+    pub fn restore(&mut self, snapshot: &Snapshot) -> Result<(), SnapshotError> {
+        if snapshot.registers.len() != self.registers.len() || snapshot.devices.len() != self.memory.device_count() {
+            return Err(SnapshotError::MachineMismatch);
+        }
+
+        self.memory.load_devices(&snapshot.devices)?;
+        self.memory.set_protections(&snapshot.protections)?;
+        self.registers.load_state(&snapshot.registers)?;
+        self.stack_frame_size = snapshot.stack_frame_size;
+        self.stack_base = snapshot.stack_base;
+        self.stack_limit = snapshot.stack_limit;
+        self.save_flags = snapshot.save_flags;
+        self.pending = snapshot.pending;
+        self.cycles = snapshot.cycles;
+        self.instructions = snapshot.instructions;
+
+        // the memory map follows the mode of the restored flags
+        self.write_flags(self.flags());
+        Ok(())
+    }
+
+    /// Debug information used to locate runtime errors
+    pub fn set_debug(&mut self, debug: DebugMap) {
+        self.debug = Some(debug);
+    }
+
+    pub fn debug(&self) -> Option<&DebugMap> {
+        self.debug.as_ref()
+    }
+
+    /// Copy `bytes` in memory, starting at `address`, even in read-only memory
+    pub fn load_at(&mut self, address: usize, bytes: &[u8]) -> Result<(), MemoryError> {
+        for (id, byte) in bytes.iter().enumerate() {
+            self.memory.load_u8(address + id, *byte)?;
         }
+
+        Ok(())
+    }
+
+    /// Read the memory like the program, in the current mode
+    pub fn get_memory_at_u8(&self, address: usize) -> Result<u8, MemoryError> {
+        self.memory.get_memory_at_u8(address)
+    }
+
+    pub fn get_memory_at_u16(&self, address: usize) -> Result<u16, MemoryError> {
+        self.memory.get_memory_at_u16(address)
+    }
+
+    /// Write the memory like the program, in the current mode
+    pub fn set_memory_at_u8(&mut self, address: usize, data: u8) -> Result<(), MemoryError> {
+        self.memory.set_memory_at_u8(address, data)
+    }
+
+    pub fn set_memory_at_u16(&mut self, address: usize, data: u16) -> Result<(), MemoryError> {
+        self.memory.set_memory_at_u16(address, data)
+    }
+
+    /// Give `permissions` to the `len` bytes from `address`, see `MemoryMap::protect`
+    pub fn protect(&mut self, address: usize, len: usize, permissions: u8) -> Result<(), MemoryError> {
+        self.memory.protect(address, len, permissions)
     }
 
     pub fn print_memory_chunk_u8(&self, start: usize, end: usize) {
@@ -1321,38 +1665,46 @@ impl CPU {
 }
 
 impl Default for CPU {
+    /// Machine built with the default settings of `MachineBuilder`
     fn default() -> Self {
-        let mut memory = MemoryMap::default();
-        let screen = Screen::new(64, 64);
-        memory.add_device(Box::new(screen), 0x3000).unwrap();
-
-        let mut registers = Memory::new(REGISTER_NAMES.len() * 2);
-        registers.set_memory_at_u16(ADDRESS_OF[SP as usize], 0xFFFE).unwrap();
-        registers.set_memory_at_u16(ADDRESS_OF[FP as usize], 0xFFFE).unwrap();
-
-        // HashMap gives the register_id with the register name given
-        let register_map = REGISTER_NAMES.iter()
-            .fold(HashMap::new(), |mut map, s| {
-                let _ = map.insert(*s, map.len());
-                map
-            });
-
-        Self {
-            memory,
-            registers,
-            stack_frame_size: 0,
-            register_map,
-            flags: 0,
-        }
+        MachineBuilder::new().build().unwrap()
     }
 }
 
-enum ExecutionError {
+/// Result of `CPU::step` and `CPU::run`
+#[derive(Debug)]
+pub enum StepResult {
+    /// the instruction was executed, the program goes on
+    Continue,
+    /// `end` was executed
+    Halted,
+    /// `brk` was executed, the program goes on with the next step
+    Breakpoint,
+    /// the cycles or the instructions given to `run_for` or `run_steps` were executed
+    BudgetExhausted,
+    /// `error` stopped the program on the instruction at `ip`, starting with `opcode`
+    Fault { ip: u16, opcode: Option<u8>, error: ExecutionError },
+}
+
+/// Errors stopping the program. `EndOfExecution` and `Breakpoint` are reported as
+/// `StepResult::Halted` and `StepResult::Breakpoint`
+pub enum ExecutionError {
     InternalMemoryError(MemoryError),
     UnexpectedInstruction(u8),
+    InvalidInstruction(DecodeError),
     BadRegisterPtrLen,
     EndOfExecution,
-    BadReturn,
+    Breakpoint,
+    StackOverflow,
+    StackUnderflow,
+    BadInterrupt(u16),
+    NoInterruptHandler(u16),
+    BadSyscall(u16),
+    NoSyscallHandler(u16),
+    PrivilegedInstruction(&'static str),
+    DivisionByZero,
+    NoHostFunction(u16),
+    HostFunctionError(u16, HostError),
 }
 
 impl From<MemoryError> for ExecutionError {
@@ -1361,14 +1713,34 @@ impl From<MemoryError> for ExecutionError {
     }
 }
 
+impl From<DecodeError> for ExecutionError {
+    fn from(error: DecodeError) -> Self {
+        match error {
+            DecodeError::UnknownOpcode(code) => Self::UnexpectedInstruction(code),
+            error => Self::InvalidInstruction(error),
+        }
+    }
+}
+
 impl std::fmt::Debug for ExecutionError {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         let error = match self {
             ExecutionError::InternalMemoryError(error) => format!("Internal memory error: {:?}", error),
             ExecutionError::UnexpectedInstruction(ins) => format!("Instruction {:#04X} is not permitted", ins),
+            ExecutionError::InvalidInstruction(error) => format!("Invalid instruction: {:?}", error),
             ExecutionError::BadRegisterPtrLen => "Register of 8bit size can't be a memory ptr".to_owned(),
-            ExecutionError::BadReturn => "Can't return outside of stackframe".to_owned(),
+            ExecutionError::StackOverflow => "Stack overflow, can't push under the stack limit".to_owned(),
+            ExecutionError::StackUnderflow => "Stack underflow, can't pop or return over the stack base".to_owned(),
             ExecutionError::EndOfExecution => "CPU reaches end of executable code".to_owned(),
+            ExecutionError::Breakpoint => "CPU reaches a breakpoint".to_owned(),
+            ExecutionError::BadInterrupt(vector) => format!("Interrupt {} doesn't exist, there are {} vectors", vector, VECTOR_COUNT),
+            ExecutionError::NoInterruptHandler(vector) => format!("Interrupt {} has no handler", vector),
+            ExecutionError::BadSyscall(syscall) => format!("Syscall {} doesn't exist, there are {} syscalls", syscall, SYSCALL_COUNT),
+            ExecutionError::NoSyscallHandler(syscall) => format!("Syscall {} has no handler", syscall),
+            ExecutionError::PrivilegedInstruction(mnemonic) => format!("Instruction {} is only executed in supervisor mode", mnemonic),
+            ExecutionError::DivisionByZero => "Division by zero".to_owned(),
+            ExecutionError::NoHostFunction(id) => format!("No host function is registered as {}", id),
+            ExecutionError::HostFunctionError(id, error) => format!("Host function {} failed: {:?}", id, error),
         };
 
         write!(f, "{}", error)
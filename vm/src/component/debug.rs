@@ -0,0 +1,55 @@
+use std::ops::Range;
+
+use arch::object::{DebugInfo, Object, ObjectError, SectionKind, SymbolKind};
+
+/// Debug information of the loaded program, used to locate runtime errors
+pub struct DebugMap {
+    lines: Option<DebugInfo>,
+    /// address and name of every label, sorted by address
+    labels: Vec<(u16, String)>,
+    /// addresses of the code, nothing is reported outside of it
+    code: Range<u16>,
+}
+
+impl DebugMap {
+    pub fn new(object: &Object) -> Result<Self, ObjectError> {
+        let mut labels: Vec<(u16, String)> = object.symbols()?.into_iter()
+            .filter(|sym| sym.kind == SymbolKind::Label)
+            .map(|sym| (sym.address, sym.name))
+            .collect();
+        labels.sort_by_key(|(address, _)| *address);
+
+        let code = object.section(SectionKind::Code)
+            .map_or(0..0, |sec| sec.address..sec.address.saturating_add(sec.size));
+
+        Ok(Self { lines: object.debug_info()?, labels, code })
+    }
+
+    /// Nearest label at or before `address`
+    pub fn label_at(&self, address: u16) -> Option<(&str, u16)> {
+        let id = self.labels.partition_point(|(add, _)| *add <= address);
+        id.checked_sub(1)
+            .map(|id| &self.labels[id])
+            .map(|(add, name)| (name.as_str(), address - add))
+    }
+
+    /// Position of `address` in the source: `file:line` and the nearest label
+    pub fn locate(&self, address: u16) -> String {
+        let mut location = format!("{:#06X}", address);
+        if !self.code.contains(&address) {
+            return location;
+        }
+
+        if let Some((file, line)) = self.lines.as_ref().and_then(|lines| lines.line_at(address)) {
+            location.push_str(&format!(" ({}:{})", file, line));
+        }
+
+        match self.label_at(address) {
+            Some((name, 0)) => location.push_str(&format!(" in {}", name)),
+            Some((name, offset)) => location.push_str(&format!(" in {}+{:#X}", name, offset)),
+            None => (),
+        }
+
+        location
+    }
+}
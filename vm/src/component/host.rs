@@ -0,0 +1,27 @@
+use super::cpu::CPU;
+use super::memory_io::MemoryError;
+
+/// Function of the host called by `hostcall id`. It reads its arguments in the registers
+/// and the memory of the CPU, and writes its results there, usually in `acc`
+pub type HostFunction = Box<dyn FnMut(&mut CPU) -> Result<(), HostError>>;
+
+/// Error returned by a host function, it stops the VM like other execution errors
+pub enum HostError {
+    InternalMemoryError(MemoryError),
+    Failed(String),
+}
+
+impl From<MemoryError> for HostError {
+    fn from(error: MemoryError) -> Self {
+        Self::InternalMemoryError(error)
+    }
+}
+
+impl std::fmt::Debug for HostError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            HostError::InternalMemoryError(error) => write!(f, "Internal memory error: {:?}", error),
+            HostError::Failed(message) => write!(f, "{}", message),
+        }
+    }
+}
@@ -0,0 +1,231 @@
+use crate::component::memory_io::*;
+use std::cell::{OnceCell, RefCell};
+use std::collections::VecDeque;
+use std::io::{Read, Write};
+use std::sync::mpsc::{self, Receiver};
+#[cfg(unix)]
+use std::sync::OnceLock;
+
+/// Keyboard fed by the host terminal. The keys are queued until the program reads them.
+/// The terminal is switched to raw mode the first time the program uses the keyboard,
+/// and restored when the keyboard is dropped.
+///
+/// | offset | size | register                                                         |
+/// |--------|------|------------------------------------------------------------------|
+/// | 0x00   | 1    | status, `READY` when a key is queued, `INTERRUPT` can be written |
+/// | 0x01   | 1    | data, next key removed from the queue, 0 when it is empty        |
+pub struct Keyboard {
+    keys: RefCell<VecDeque<u8>>,
+    /// listen to the host terminal
+    listen: bool,
+    terminal: OnceCell<Terminal>,
+    status: u8,
+    /// a key arrived since the last poll of the interrupt line
+    raised: bool,
+}
+
+impl Keyboard {
+    pub const STATUS   : usize = 0x00;
+    pub const DATA     : usize = 0x01;
+
+    // bits of the status register
+    pub const READY    : u8 = 1;
+    pub const INTERRUPT: u8 = 2;
+
+    /// Keyboard reading the keys typed on the host terminal, when `listen` is set.
+    /// Otherwise, keys are only given with `push_key`
+    pub fn new(listen: bool) -> Self {
+        Self {
+            keys: RefCell::new(VecDeque::new()),
+            listen,
+            terminal: OnceCell::new(),
+            status: 0,
+            raised: false,
+        }
+    }
+
+    /// Add a key at the end of the queue, raise the interrupt line if it is enabled
+    pub fn push_key(&mut self, key: u8) {
+        self.keys.get_mut().push_back(key);
+
+        if self.status & Self::INTERRUPT != 0 {
+            self.raised = true;
+        }
+    }
+
+    /// Listen to the terminal when the program uses the keyboard. What the
+    /// program printed is shown before it waits for a key
+    fn start(&self) {
+        if self.listen {
+            self.terminal.get_or_init(Terminal::open);
+            let _ = std::io::stdout().flush();
+        }
+    }
+}
+
+impl MemoryIO for Keyboard {
+    fn get_memory_at_u8(&self, location: usize) -> Result<u8, MemoryError> {
+        self.start();
+
+        match location {
+            Self::STATUS if self.keys.borrow().is_empty() => Ok(self.status),
+            Self::STATUS => Ok(self.status | Self::READY),
+            Self::DATA => Ok(self.keys.borrow_mut().pop_front().unwrap_or(0)),
+            _ => Err(MemoryError::OutOfBounds(location)),
+        }
+    }
+
+    /// Status in the upper byte, next key in the lower byte
+    fn get_memory_at_u16(&self, location: usize) -> Result<u16, MemoryError> {
+        let left = self.get_memory_at_u8(location)?;
+        let right = self.get_memory_at_u8(location + 1)?;
+
+        Ok(((left as u16) << 8) + (right as u16))
+    }
+
+    /// Only the `INTERRUPT` bit of the status can be written
+    fn set_memory_at_u8(&mut self, location: usize, data: u8) -> Result<(), MemoryError> {
+        self.start();
+
+        match location {
+            Self::STATUS => self.status = data & Self::INTERRUPT,
+            Self::DATA => (),
+            _ => return Err(MemoryError::OutOfBounds(location)),
+        }
+
+        Ok(())
+    }
+
+    fn set_memory_at_u16(&mut self, location: usize, data: u16) -> Result<(), MemoryError> {
+        self.set_memory_at_u8(location, (data >> 8) as u8)?;
+        self.set_memory_at_u8(location + 1, data as u8)
+    }
+
+    fn len(&self) -> usize {
+        2
+    }
+
+    fn is_empty(&self) -> bool {
+        false
+    }
+
+    /// Queue the keys typed since the last instruction
+    fn tick(&mut self) {
+        let typed: Vec<u8> = match self.terminal.get() {
+            Some(terminal) => terminal.keys.try_iter().collect(),
+            None => return,
+        };
+
+        for key in typed {
+            self.push_key(key);
+        }
+    }
+
+    fn interrupt(&mut self) -> bool {
+        std::mem::take(&mut self.raised)
+    }
+
+    /// Status, raised line, then the queued keys
+    fn save_state(&self) -> Vec<u8> {
+        let mut state = vec![self.status, self.raised as u8];
+        state.extend(self.keys.borrow().iter());
+        state
+    }
+
+    fn load_state(&mut self, state: &[u8]) -> Result<(), MemoryError> {
+        match state {
+            [status, raised, keys @ ..] => {
+                self.status = *status & Self::INTERRUPT;
+                self.raised = *raised != 0;
+                *self.keys.get_mut() = keys.iter().copied().collect();
+                Ok(())
+            }
+            _ => Err(MemoryError::BadDeviceState),
+        }
+    }
+}
+
+/// Settings of the terminal before the first switch to raw mode, restored when the
+/// VM is interrupted or killed
+#[cfg(unix)]
+static SAVED: OnceLock<libc::termios> = OnceLock::new();
+
+/// Handler of `SIGINT` and `SIGTERM`: `Drop` isn't run when the process is stopped
+/// by a signal, the terminal is restored before exiting like the default action does
+#[cfg(unix)]
+extern "C" fn restore_and_exit(signal: libc::c_int) {
+    // SAFETY: `tcsetattr` and `_exit` are async-signal-safe, `SAVED` is set before
+    // the handler is installed
+    unsafe {
+        if let Some(saved) = SAVED.get() {
+            libc::tcsetattr(libc::STDIN_FILENO, libc::TCSANOW, saved);
+        }
+        libc::_exit(128 + signal);
+    }
+}
+
+/// Host terminal in raw mode: keys are sent without waiting for a new line and aren't
+/// echoed. A thread reads them from the standard input
+struct Terminal {
+    keys: Receiver<u8>,
+    #[cfg(unix)]
+    saved: Option<libc::termios>,
+}
+
+impl Terminal {
+    fn open() -> Self {
+        let (sender, keys) = mpsc::channel();
+        std::thread::spawn(move || {
+            for key in std::io::stdin().lock().bytes() {
+                match key {
+                    Ok(key) if sender.send(key).is_ok() => (),
+                    _ => break,
+                }
+            }
+        });
+
+        Self {
+            keys,
+            #[cfg(unix)]
+            saved: Self::raw_mode(),
+        }
+    }
+
+    /// Switch the terminal to raw mode, returns its previous settings. `Ctrl-C` still
+    /// stops the VM, the terminal is restored first.
+    /// Nothing changes when the standard input isn't a terminal
+    #[cfg(unix)]
+    fn raw_mode() -> Option<libc::termios> {
+        // SAFETY: `termios` is a plain C struct, filled by `tcgetattr` before it is used
+        unsafe {
+            let mut saved: libc::termios = std::mem::zeroed();
+            if libc::tcgetattr(libc::STDIN_FILENO, &mut saved) != 0 {
+                return None;
+            }
+
+            if SAVED.set(saved).is_ok() {
+                let handler = restore_and_exit as extern "C" fn(libc::c_int) as libc::sighandler_t;
+                libc::signal(libc::SIGINT, handler);
+                libc::signal(libc::SIGTERM, handler);
+            }
+
+            let mut raw = saved;
+            raw.c_lflag &= !(libc::ICANON | libc::ECHO);
+            raw.c_cc[libc::VMIN] = 1;
+            raw.c_cc[libc::VTIME] = 0;
+            libc::tcsetattr(libc::STDIN_FILENO, libc::TCSANOW, &raw);
+
+            Some(saved)
+        }
+    }
+}
+
+impl Drop for Terminal {
+    fn drop(&mut self) {
+        #[cfg(unix)]
+        if let Some(saved) = &self.saved {
+            // SAFETY: `saved` holds the settings read by `tcgetattr`
+            unsafe { libc::tcsetattr(libc::STDIN_FILENO, libc::TCSANOW, saved) };
+        }
+    }
+}
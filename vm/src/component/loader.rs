@@ -0,0 +1,180 @@
+use super::cpu::CPU;
+use super::debug::DebugMap;
+use super::memory_io::MemoryError;
+use super::memory_map::{EXECUTE, READ, WRITE};
+
+use arch::codec::{encode, Instruction};
+use arch::instructions::ISA_VERSION;
+use arch::object::{Object, ObjectError, Section, SectionKind, SymbolKind};
+use arch::format::{self, Format, HexError};
+use arch::interrupts::{TABLES_END, VECTOR_TABLE};
+
+/// How a program is placed in memory
+#[derive(Default)]
+pub struct LoadOptions {
+    /// address of a raw image, other formats hold the address of their sections
+    pub base: u16,
+    /// write a jump to the entry point at address 0 and start on it,
+    /// like the reset vector of a real CPU
+    pub boot_stub: bool,
+}
+
+/// Load the content of a file written in `format`, returns the problems that don't prevent
+/// the program from running
+pub fn load(cpu: &mut CPU, bytes: &[u8], format: Format, options: &LoadOptions) -> Result<Vec<LoadWarning>, LoadError> {
+    let mut object = match format {
+        Format::Vmo => read_object(bytes)?,
+        Format::Raw => raw_object(bytes, options.base),
+        Format::Ihex => format::from_ihex(&String::from_utf8_lossy(bytes))?,
+        Format::Srec => format::from_srec(&String::from_utf8_lossy(bytes))?,
+    };
+
+    if options.boot_stub {
+        add_boot_stub(&mut object)?;
+    }
+
+    // code and data of raw and hex images are mixed, they stay writable and executable
+    load_sections(cpu, &object, format == Format::Vmo)
+}
+
+/// Read a `.vmo` object. Objects built for a later ISA than the one of this VM are
+/// refused, as well as objects using symbols of other objects that weren't linked
+fn read_object(bytes: &[u8]) -> Result<Object, LoadError> {
+    let object = Object::from_bytes(bytes)?;
+    if object.isa_version > ISA_VERSION {
+        return Err(ObjectError::IncompatibleIsa(object.isa_version).into());
+    }
+
+    if let Some(import) = object.symbols()?.into_iter().find(|sym| sym.kind == SymbolKind::Import) {
+        return Err(LoadError::Unresolved(import.name));
+    }
+
+    Ok(object)
+}
+
+/// Load a `.vmo` object in memory and move `ip` to its entry point
+pub fn load_object(cpu: &mut CPU, bytes: &[u8]) -> Result<Vec<LoadWarning>, LoadError> {
+    load_sections(cpu, &read_object(bytes)?, true)
+}
+
+/// A raw image is a single code section, execution starts on its first byte
+fn raw_object(bytes: &[u8], base: u16) -> Object {
+    let mut object = Object::new(base);
+    object.sections.push(Section::new(SectionKind::Code, base, bytes.to_vec()));
+    object
+}
+
+/// Add a `jmp` to the entry point of `object` at address 0, it becomes the new entry point.
+/// The program must leave the bytes of the stub free
+fn add_boot_stub(object: &mut Object) -> Result<(), LoadError> {
+    let stub = encode(&Instruction::JmpLit(object.entry));
+
+    let used = object.sections.iter()
+        .filter(|sec| matches!(sec.kind, SectionKind::Code | SectionKind::Data | SectionKind::Bss))
+        .find(|sec| sec.size > 0 && (sec.address as usize) < stub.len());
+    if let Some(sec) = used {
+        return Err(LoadError::BootStubOverlap(sec.address));
+    }
+
+    // after the program, its code stays the one used to locate errors
+    object.sections.push(Section::new(SectionKind::Code, 0, stub));
+    object.entry = 0;
+    Ok(())
+}
+
+/// Copy the loaded sections of `object` in memory and move `ip` to its entry point.
+/// With `protect`, the code is read-only and executable and the data can't be executed.
+/// The vector and syscall tables stay supervisor-only, sections written over them are reported
+fn load_sections(cpu: &mut CPU, object: &Object, protect: bool) -> Result<Vec<LoadWarning>, LoadError> {
+    let mut warnings = vec![];
+
+    for section in object.sections.iter() {
+        let address = section.address as usize;
+
+        let permissions = match section.kind {
+            SectionKind::Code => {
+                cpu.load_at(address, &section.data)?;
+                READ | EXECUTE
+            },
+            SectionKind::Data => {
+                cpu.load_at(address, &section.data)?;
+                READ | WRITE
+            },
+            SectionKind::Bss => {
+                cpu.load_at(address, &vec![0; section.size as usize])?;
+                READ | WRITE
+            },
+            SectionKind::Symbols | SectionKind::Relocations | SectionKind::Debug => continue,
+        };
+
+        let end = address + section.size as usize;
+        if address < TABLES_END as usize && end > VECTOR_TABLE as usize {
+            warnings.push(LoadWarning::TableOverlap(section.address));
+        }
+
+        if protect && section.size > 0 {
+            cpu.protect(address, section.size as usize, permissions)?;
+        }
+    }
+
+    cpu.protect_tables()?;
+    cpu.set_debug(DebugMap::new(object)?);
+    cpu.set_register("ip", object.entry)?;
+    Ok(warnings)
+}
+
+/// Problem found while loading a program that still runs
+pub enum LoadWarning {
+    /// a section loaded at this address is written over the vector and syscall tables
+    TableOverlap(u16),
+}
+
+impl std::fmt::Debug for LoadWarning {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            LoadWarning::TableOverlap(address) => write!(
+                f, "The section loaded at {:#06X} overwrites the vector and syscall tables ({:#06X}..{:#06X}), \
+                load it after them with `.org`", address, VECTOR_TABLE, TABLES_END
+            ),
+        }
+    }
+}
+
+/// Enumeration of every error that can happen while loading a program
+pub enum LoadError {
+    BadObject(ObjectError),
+    BadHexFile(HexError),
+    Unresolved(String),
+    BootStubOverlap(u16),
+    InternalMemoryError(MemoryError),
+}
+
+impl From<ObjectError> for LoadError {
+    fn from(error: ObjectError) -> Self {
+        Self::BadObject(error)
+    }
+}
+
+impl From<HexError> for LoadError {
+    fn from(error: HexError) -> Self {
+        Self::BadHexFile(error)
+    }
+}
+
+impl From<MemoryError> for LoadError {
+    fn from(error: MemoryError) -> Self {
+        Self::InternalMemoryError(error)
+    }
+}
+
+impl std::fmt::Debug for LoadError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            LoadError::BadObject(error) => write!(f, "Can't load object: {:?}", error),
+            LoadError::BadHexFile(error) => write!(f, "Can't read file: {:?}", error),
+            LoadError::Unresolved(name) => write!(f, "Symbol {} is defined in another object, link them first", name),
+            LoadError::BootStubOverlap(address) => write!(f, "Boot stub can't be written at 0, the program is loaded at {:#06X}", address),
+            LoadError::InternalMemoryError(error) => write!(f, "Can't load program in memory: {:?}", error),
+        }
+    }
+}
@@ -0,0 +1,161 @@
+use super::cpu::CPU;
+use super::memory_io::{MemoryError, MemoryIO};
+use super::memory_map::MemoryMap;
+use super::screen::Screen;
+use super::timer::Timer;
+use super::keyboard::Keyboard;
+
+/// Device added to the memory map by the builder
+struct Device {
+    device: Box<dyn MemoryIO>,
+    address: u16,
+    /// interrupt line raised by the device
+    irq: Option<u8>,
+}
+
+/// Build a CPU with its memory and devices. The default machine has 64 KiB of memory, a
+/// 64x64 screen, the timer and the keyboard listening to the terminal, and its stack at the
+/// end of memory
+pub struct MachineBuilder {
+    memory_size: usize,
+    /// width and height of the screen
+    screen: (usize, usize),
+    /// map the screen, the timer and the keyboard
+    default_devices: bool,
+    /// the keyboard reads the keys typed on the terminal
+    listen_keyboard: bool,
+    devices: Vec<Device>,
+    /// base and limit of the stack
+    stack: (u16, u16),
+}
+
+impl MachineBuilder {
+    pub const SCREEN_ADDRESS  : u16 = 0x3000;
+    pub const TIMER_ADDRESS   : u16 = 0x4000; // raises the line 0
+    pub const KEYBOARD_ADDRESS: u16 = 0x4010; // raises the line 1
+
+    pub fn new() -> Self {
+        Self {
+            memory_size: 0x1_0000,
+            screen: (64, 64),
+            default_devices: true,
+            listen_keyboard: true,
+            devices: Vec::new(),
+            stack: (CPU::STACK_BASE, CPU::STACK_LIMIT),
+        }
+    }
+
+    /// Bytes of memory from address 0, up to 64 KiB
+    pub fn memory_size(mut self, size: usize) -> Self {
+        self.memory_size = size;
+        self
+    }
+
+    /// Size of the screen in cells, one byte each
+    pub fn screen(mut self, width: usize, height: usize) -> Self {
+        self.screen = (width, height);
+        self
+    }
+
+    /// Map the screen, the timer and the keyboard at their default address
+    pub fn default_devices(mut self, default_devices: bool) -> Self {
+        self.default_devices = default_devices;
+        self
+    }
+
+    /// The keyboard reads the terminal, otherwise it only gets keys from `Keyboard::push_key`
+    pub fn listen_keyboard(mut self, listen: bool) -> Self {
+        self.listen_keyboard = listen;
+        self
+    }
+
+    /// Map `device` at `address`, over the memory and the devices added before it
+    pub fn device(mut self, address: u16, device: Box<dyn MemoryIO>) -> Self {
+        self.devices.push(Device { device, address, irq: None });
+        self
+    }
+
+    /// Map `device` at `address`, it raises the interrupt line `irq`
+    pub fn interrupt_device(mut self, address: u16, irq: u8, device: Box<dyn MemoryIO>) -> Self {
+        self.devices.push(Device { device, address, irq: Some(irq) });
+        self
+    }
+
+    /// Place the stack between `limit` and `base`, see `CPU::set_stack`
+    pub fn stack(mut self, base: u16, limit: u16) -> Self {
+        self.stack = (base, limit);
+        self
+    }
+
+    /// Build the machine. The stack and every device must be inside the memory
+    pub fn build(self) -> Result<CPU, MachineError> {
+        let (base, limit) = self.stack;
+        if limit > base {
+            return Err(MachineError::BadStack(base, limit));
+        }
+
+        // `sp` starts on the word at `base`
+        if base as usize + 2 > self.memory_size {
+            return Err(MachineError::StackOutOfMemory(base));
+        }
+
+        let mut memory = MemoryMap::new(self.memory_size)?;
+        let mut devices = Vec::new();
+
+        if self.default_devices {
+            let (width, height) = self.screen;
+            let keyboard = Keyboard::new(self.listen_keyboard);
+            devices.push(Device { device: Box::new(Screen::new(width, height)), address: Self::SCREEN_ADDRESS, irq: None });
+            devices.push(Device { device: Box::new(Timer::new()), address: Self::TIMER_ADDRESS, irq: Some(0) });
+            devices.push(Device { device: Box::new(keyboard), address: Self::KEYBOARD_ADDRESS, irq: Some(1) });
+        }
+
+        for dev in devices.into_iter().chain(self.devices) {
+            if dev.address as usize + dev.device.len() > self.memory_size {
+                return Err(MachineError::DeviceOutOfMemory(dev.address));
+            }
+
+            match dev.irq {
+                Some(irq) => memory.add_interrupt_device(dev.device, dev.address as usize, irq)?,
+                None => memory.add_device(dev.device, dev.address as usize)?,
+            }
+        }
+
+        let mut cpu = CPU::new(memory)?;
+        cpu.set_stack(base, limit);
+        Ok(cpu)
+    }
+}
+
+impl Default for MachineBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Enumeration of every error that can happen while building a machine
+pub enum MachineError {
+    InternalMemoryError(MemoryError),
+    BadStack(u16, u16),
+    /// the stack base is over the end of the memory
+    StackOutOfMemory(u16),
+    /// the device mapped at this address ends over the end of the memory
+    DeviceOutOfMemory(u16),
+}
+
+impl From<MemoryError> for MachineError {
+    fn from(error: MemoryError) -> Self {
+        Self::InternalMemoryError(error)
+    }
+}
+
+impl std::fmt::Debug for MachineError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            MachineError::InternalMemoryError(error) => write!(f, "Can't build the memory map: {:?}", error),
+            MachineError::BadStack(base, limit) => write!(f, "The stack limit {:#06X} is over the stack base {:#06X}", limit, base),
+            MachineError::StackOutOfMemory(base) => write!(f, "The stack base {:#06X} is out of the memory", base),
+            MachineError::DeviceOutOfMemory(address) => write!(f, "The device mapped at {:#06X} is out of the memory", address),
+        }
+    }
+}
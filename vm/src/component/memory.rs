@@ -16,8 +16,8 @@ impl Memory {
     /// # Examples
     ///
     /// ```
-    /// pub mod component;
-    /// use crate::component::memory::Memory;
+    /// use vm::component::memory::Memory;
+    /// use vm::MemoryIO;
     ///
     /// let m = Memory::new(0x40);
     /// assert_eq!(m.len(), 64);
@@ -92,4 +92,24 @@ impl MemoryIO for Memory {
     fn is_empty(&self) -> bool {
         self.data.is_empty()
     }
+
+    /// The whole content of the memory
+    fn save_state(&self) -> Vec<u8> {
+        self.data.clone()
+    }
+
+    /// The saved content must have the size of the memory
+    fn load_state(&mut self, state: &[u8]) -> Result<(), MemoryError> {
+        if state.len() != self.data.len() {
+            return Err(MemoryError::BadDeviceState);
+        }
+
+        self.data.copy_from_slice(state);
+        Ok(())
+    }
+
+    /// The program reads what it wrote
+    fn is_input(&self) -> bool {
+        false
+    }
 }
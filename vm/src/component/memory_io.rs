@@ -6,6 +6,32 @@ pub trait MemoryIO {
 
     fn len(&self) -> usize;
     fn is_empty(&self) -> bool;
+
+    /// Called after each executed instruction, for devices counting time
+    fn tick(&mut self) {}
+
+    /// Polled by the CPU between instructions, returns true when the device raises
+    /// its interrupt line. The CPU keeps the interrupt pending until it is serviced
+    fn interrupt(&mut self) -> bool {
+        false
+    }
+
+    /// Internal state of the device, saved in the snapshots of the machine. Devices
+    /// without state beyond what the program can read back save nothing
+    fn save_state(&self) -> Vec<u8> {
+        Vec::new()
+    }
+
+    /// Restore the state returned by `save_state`
+    fn load_state(&mut self, _state: &[u8]) -> Result<(), MemoryError> {
+        Ok(())
+    }
+
+    /// The values read from the device come from outside the machine, they are recorded
+    /// and replayed. Memory and output-only devices give back what the program did
+    fn is_input(&self) -> bool {
+        true
+    }
 }
 
 /// Enumeration of every type of memory error
@@ -14,6 +40,15 @@ pub enum MemoryError {
     BadRegisterLen(u8),
     NoRegister(&'static str),
     UnaddressableRegion(usize),
+    BadInterruptLine(u8),
+    ReadProtected(usize),
+    WriteProtected(usize),
+    NotExecutable(usize),
+    SupervisorOnly(usize),
+    BadDeviceState,
+    ReplayDiverged(usize),
+    /// the replayed run went past the instruction of the next recorded event
+    ReplayMissed(u64),
 }
 
 impl std::fmt::Debug for MemoryError {
@@ -23,6 +58,14 @@ impl std::fmt::Debug for MemoryError {
             MemoryError::OutOfBounds(address) => format!("The address {:#06X} is not in the memory", address),
             MemoryError::BadRegisterLen(len) => format!("Expected register size 1 or 2, found {}", len),
             MemoryError::NoRegister(name) => format!("Register {} does not exist", name),
+            MemoryError::BadInterruptLine(line) => format!("Interrupt line {} doesn't exist", line),
+            MemoryError::ReadProtected(address) => format!("The address {:#06X} can't be read", address),
+            MemoryError::WriteProtected(address) => format!("The address {:#06X} is read-only", address),
+            MemoryError::NotExecutable(address) => format!("The address {:#06X} is not executable", address),
+            MemoryError::SupervisorOnly(address) => format!("The address {:#06X} is only accessed in supervisor mode", address),
+            MemoryError::BadDeviceState => "The saved state doesn't match the device".to_owned(),
+            MemoryError::ReplayDiverged(address) => format!("The read of {:#06X} is not in the replayed input", address),
+            MemoryError::ReplayMissed(instruction) => format!("The input recorded at instruction {} was not replayed", instruction),
         };
 
         write!(f, "{}", error)
@@ -1,11 +1,29 @@
 use crate::component::memory_io::MemoryIO;
 use crate::component::memory::Memory;
 use super::memory_io::MemoryError;
+use super::replay::{InputEvent, InputLog};
+use std::cell::RefCell;
+
+use arch::interrupts::VECTOR_COUNT;
+
+/// Permissions of a region, combined with `|`
+pub const READ   : u8 = 1;
+pub const WRITE  : u8 = 2;
+pub const EXECUTE: u8 = 4;
+pub const RWX    : u8 = READ | WRITE | EXECUTE;
+/// only accessed in supervisor mode, see `set_user_mode`
+pub const SUPERVISOR: u8 = 8;
 
 struct Region {
     device: Box<dyn MemoryIO>,
     start: usize,
     end: usize,
+    /// interrupt line raised by the device
+    irq: Option<u8>,
+    /// devices are mapped read-write, instructions can't be fetched from them
+    permissions: u8,
+    /// reads of input devices are recorded and replayed, see `MemoryIO::is_input`
+    input: bool,
 }
 
 impl Region {
@@ -13,10 +31,11 @@ impl Region {
         let len = device.len();
         let end = start + len;
 
-        if end - 1 > 0xFFFF {
+        if end > 0x1_0000 {
             Err(MemoryError::UnaddressableRegion(end))
         } else {
-            Ok(Self { device, start, end })
+            let input = device.is_input();
+            Ok(Self { device, start, end, irq: None, permissions: READ | WRITE, input })
         }
     }
 
@@ -29,44 +48,298 @@ impl Region {
     }
 }
 
-impl Default for Region {
-    fn default() -> Self {
-        let memory = Memory::new(0x1_0000);
-        Self { device: Box::new(memory), start: 0x0000, end: 0xFFFF }
-    }
+
+/// Permissions given to a range of addresses, over the ones of its devices
+struct Protection {
+    start: usize,
+    end: usize,
+    permissions: u8,
+}
+
+/// What happens to the values read from the devices
+enum Recorder {
+    Off,
+    Record(InputLog),
+    /// values are read from the log instead of the devices, `next` is the next event
+    Replay { log: InputLog, next: usize },
 }
 
 pub struct MemoryMap {
     regions: Vec<Region>,
+    protections: Vec<Protection>,
+    /// supervisor-only addresses can't be accessed
+    user_mode: bool,
+    /// devices are read by the program, which only borrows the map
+    recorder: RefCell<Recorder>,
+    /// instructions executed by the CPU, events of the input log are keyed by it
+    instruction: u64,
 }
 
 impl MemoryMap {
+    /// Memory map with `size` bytes of memory from address 0, readable, writable and executable
+    pub fn new(size: usize) -> Result<Self, MemoryError> {
+        let mut region = Region::new(Box::new(Memory::new(size)), 0)?;
+        region.permissions = RWX;
+
+        Ok(Self {
+            regions: vec![region],
+            protections: Vec::new(),
+            user_mode: false,
+            recorder: RefCell::new(Recorder::Off),
+            instruction: 0,
+        })
+    }
+
     pub fn add_device(&mut self, device: Box<dyn MemoryIO>, start: usize) -> Result<(), MemoryError> {
         let reg = Region::new(device, start)?;
         self.regions.push(reg);
         Ok(())
     }
 
+    /// Map a device able to raise the interrupt line `irq`, see `arch::interrupts`
+    pub fn add_interrupt_device(&mut self, device: Box<dyn MemoryIO>, start: usize, irq: u8) -> Result<(), MemoryError> {
+        if irq as u16 >= VECTOR_COUNT {
+            return Err(MemoryError::BadInterruptLine(irq));
+        }
+
+        let mut reg = Region::new(device, start)?;
+        reg.irq = Some(irq);
+        self.regions.push(reg);
+        Ok(())
+    }
+
+    /// Give `permissions` to the `len` bytes from `start`, the last protection of an address
+    /// replaces the previous ones and the permissions of its device
+    pub fn protect(&mut self, start: usize, len: usize, permissions: u8) -> Result<(), MemoryError> {
+        let end = start + len;
+        if end > self.len() {
+            return Err(MemoryError::UnaddressableRegion(end));
+        }
+
+        self.protections.push(Protection { start, end, permissions });
+        Ok(())
+    }
+
+    /// Permissions of the byte at `address`, `None` when no device is mapped on it
+    pub fn permissions(&self, address: usize) -> Option<u8> {
+        let protection = self.protections.iter().rev()
+            .find(|prot| address >= prot.start && address < prot.end);
+
+        match protection {
+            Some(prot) => Some(prot.permissions),
+            None => self.find_region(address).ok().map(|(reg, _)| reg.permissions),
+        }
+    }
+
+    /// Accesses are checked for the user mode of the CPU, or for its supervisor mode
+    pub fn set_user_mode(&mut self, user_mode: bool) {
+        self.user_mode = user_mode;
+    }
+
+    /// Check the `len` bytes from `location` have `permission`
+    fn check(&self, location: usize, len: usize, permission: u8) -> Result<(), MemoryError> {
+        for address in location..location + len {
+            let perm = match self.permissions(address) {
+                Some(perm) => perm,
+                None => continue,
+            };
+
+            if self.user_mode && perm & SUPERVISOR != 0 {
+                return Err(MemoryError::SupervisorOnly(address));
+            }
+
+            if perm & permission == 0 {
+                return Err(match permission {
+                    WRITE => MemoryError::WriteProtected(address),
+                    EXECUTE => MemoryError::NotExecutable(address),
+                    _ => MemoryError::ReadProtected(address),
+                });
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Record the values read from the devices and the interrupt lines they raise, the
+    /// previous recording is dropped
+    pub fn record(&mut self) {
+        *self.recorder.get_mut() = Recorder::Record(InputLog::new());
+    }
+
+    /// Stop recording, returns the values recorded since `record`
+    pub fn take_recording(&mut self) -> Option<InputLog> {
+        match std::mem::replace(self.recorder.get_mut(), Recorder::Off) {
+            Recorder::Record(log) => Some(log),
+            recorder => {
+                *self.recorder.get_mut() = recorder;
+                None
+            }
+        }
+    }
+
+    /// Serve the values of `log` instead of reading the devices. Once the program reads
+    /// a device at another instruction or address than in the log, the read fails
+    pub fn replay(&mut self, log: InputLog) {
+        *self.recorder.get_mut() = Recorder::Replay { log, next: 0 };
+    }
+
+    /// Set the number of instructions executed by the CPU, before each instruction
+    pub fn set_instruction(&mut self, instruction: u64) {
+        self.instruction = instruction;
+    }
+
+    /// Value read on `size` bytes at `location` from a device, recorded or taken from the
+    /// replayed log
+    fn input<F>(&self, location: usize, size: u8, read: F) -> Result<u16, MemoryError>
+    where F: FnOnce() -> Result<u16, MemoryError> {
+        let instruction = self.instruction;
+
+        match &mut *self.recorder.borrow_mut() {
+            Recorder::Off => read(),
+            Recorder::Record(log) => {
+                let value = read()?;
+                log.push(InputEvent::Read { instruction, address: location as u16, size, value });
+                Ok(value)
+            }
+            Recorder::Replay { log, next } => match log.events().get(*next) {
+                Some(&InputEvent::Read { instruction: at, address, size: len, value })
+                    if at == instruction && address as usize == location && len == size => {
+                    *next += 1;
+                    Ok(value)
+                }
+                _ => Err(MemoryError::ReplayDiverged(location)),
+            },
+        }
+    }
+
+    /// Let every device know an instruction was executed
+    pub fn tick(&mut self) {
+        for reg in self.regions.iter_mut() {
+            reg.device.tick();
+        }
+    }
+
+    /// Number of mapped devices, main memory included
+    pub fn device_count(&self) -> usize {
+        self.regions.len()
+    }
+
+    /// State of every device, in the order they were mapped
+    pub fn save_devices(&self) -> Vec<Vec<u8>> {
+        self.regions.iter().map(|reg| reg.device.save_state()).collect()
+    }
+
+    /// Restore the states returned by `save_devices`, the map must have the same devices
+    pub fn load_devices(&mut self, states: &[Vec<u8>]) -> Result<(), MemoryError> {
+        if states.len() != self.regions.len() {
+            return Err(MemoryError::BadDeviceState);
+        }
+
+        for (reg, state) in self.regions.iter_mut().zip(states) {
+            reg.device.load_state(state)?;
+        }
+
+        Ok(())
+    }
+
+    /// Ranges given to `protect`, as `(start, len, permissions)`
+    pub fn protections(&self) -> Vec<(usize, usize, u8)> {
+        self.protections.iter().map(|prot| (prot.start, prot.end - prot.start, prot.permissions)).collect()
+    }
+
+    /// Replace every protection by `protections`, as returned by `protections`
+    pub fn set_protections(&mut self, protections: &[(usize, usize, u8)]) -> Result<(), MemoryError> {
+        let previous = std::mem::take(&mut self.protections);
+
+        for &(start, len, permissions) in protections {
+            if let Err(e) = self.protect(start, len, permissions) {
+                self.protections = previous;
+                return Err(e);
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Lines raised by the devices since the last call, as a bit mask. A replayed run fails
+    /// once it went past an event of the log without reading it
+    pub fn poll_interrupts(&mut self) -> Result<u16, MemoryError> {
+        let mut lines = 0;
+
+        for reg in self.regions.iter_mut() {
+            if let Some(irq) = reg.irq {
+                if reg.device.interrupt() {
+                    lines |= 1 << irq;
+                }
+            }
+        }
+
+        let instruction = self.instruction;
+        match self.recorder.get_mut() {
+            Recorder::Off => Ok(lines),
+            Recorder::Record(log) => {
+                if lines != 0 {
+                    log.push(InputEvent::Interrupt { instruction, lines });
+                }
+                Ok(lines)
+            }
+            // the devices were polled to clear their line, the log gives the raised ones.
+            // An event of a previous instruction was skipped by the replayed run
+            Recorder::Replay { log, next } => match log.events().get(*next) {
+                Some(&InputEvent::Interrupt { instruction: at, lines }) if at == instruction => {
+                    *next += 1;
+                    Ok(lines)
+                }
+                Some(event) if event.instruction() < instruction => Err(MemoryError::ReplayMissed(event.instruction())),
+                _ => Ok(0),
+            },
+        }
+    }
+
     pub fn get_memory_at_u8(&self, location: usize) -> Result<u8, MemoryError> {
+        self.check(location, 1, READ)?;
         let (reg, address) = self.find_region(location)?;
-        reg.device.get_memory_at_u8(address)
+
+        match reg.input {
+            true => Ok(self.input(location, 1, || Ok(reg.device.get_memory_at_u8(address)? as u16))? as u8),
+            false => reg.device.get_memory_at_u8(address),
+        }
     }
 
     pub fn get_memory_at_u16(&self, location: usize) -> Result<u16, MemoryError> {
+        self.check(location, 2, READ)?;
         let (reg, address) = self.find_region(location)?;
-        reg.device.get_memory_at_u16(address)
+
+        match reg.input {
+            true => self.input(location, 2, || reg.device.get_memory_at_u16(address)),
+            false => reg.device.get_memory_at_u16(address),
+        }
     }
 
     pub fn set_memory_at_u8(&mut self, location: usize, data: u8) -> Result<(), MemoryError> {
-        let (reg, address) = self.find_region_mut(location)?;
-        reg.device.set_memory_at_u8(address, data)
+        self.check(location, 1, WRITE)?;
+        self.load_u8(location, data)
     }
 
     pub fn set_memory_at_u16(&mut self, location: usize, data: u16) -> Result<(), MemoryError> {
+        self.check(location, 2, WRITE)?;
         let (reg, address) = self.find_region_mut(location)?;
         reg.device.set_memory_at_u16(address, data)
     }
 
+    /// Read a byte of an instruction, its address must be executable
+    pub fn fetch_u8(&self, location: usize) -> Result<u8, MemoryError> {
+        self.check(location, 1, EXECUTE)?;
+        let (reg, address) = self.find_region(location)?;
+        reg.device.get_memory_at_u8(address)
+    }
+
+    /// Write a byte without checking the permissions, used to load programs in read-only memory
+    pub fn load_u8(&mut self, location: usize, data: u8) -> Result<(), MemoryError> {
+        let (reg, address) = self.find_region_mut(location)?;
+        reg.device.set_memory_at_u8(address, data)
+    }
+
     fn find_region(&self, address: usize) -> Result<(&Region, usize), MemoryError> {
         for reg in self.regions.iter().rev() {
             if let Some(address) = reg.contain(address) {
@@ -98,6 +371,6 @@ impl MemoryMap {
 
 impl Default for MemoryMap {
     fn default() -> Self {
-        Self { regions: vec![Region::default()] }
+        Self::new(0x1_0000).unwrap()
     }
 }
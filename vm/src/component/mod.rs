@@ -1,5 +1,13 @@
 pub mod cpu;
 pub mod memory;
 pub mod screen;
+pub mod timer;
+pub mod keyboard;
 pub mod memory_io;
 pub mod memory_map;
+pub mod loader;
+pub mod debug;
+pub mod host;
+pub mod machine;
+pub mod snapshot;
+pub mod replay;
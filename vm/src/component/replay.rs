@@ -0,0 +1,148 @@
+//! Values read from the devices, recorded by `MemoryMap::record` and served back by
+//! `MemoryMap::replay`: the devices give the same input to the program and it runs
+//! exactly like the recorded run.
+//!
+//! Every value is stored big endian, like in the VM memory.
+//!
+//! | bytes | content                                            |
+//! |-------|----------------------------------------------------|
+//! | 4     | magic, `VMR\0`                                     |
+//! | 2     | format version                                     |
+//! | 4     | number of events                                   |
+//! | ...   | events, in the order they happened                 |
+//!
+//! Each event starts with its kind (1 byte) and the number of instructions executed
+//! before it (8 bytes). A read is followed by the address (2 bytes), the size (1 byte)
+//! and the value (2 bytes), an interrupt by the raised lines (2 bytes).
+
+use arch::reader::{Reader, Truncated};
+
+pub const MAGIC: &[u8; 4] = b"VMR\0";
+pub const FORMAT_VERSION: u16 = 1;
+
+const READ: u8 = 1;
+const INTERRUPT: u8 = 2;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum InputEvent {
+    /// `value` read at `address` on 1 or 2 bytes
+    Read { instruction: u64, address: u16, size: u8, value: u16 },
+    /// interrupt lines raised by the devices, as a bit mask
+    Interrupt { instruction: u64, lines: u16 },
+}
+
+impl InputEvent {
+    /// Number of instructions executed before the event
+    pub fn instruction(&self) -> u64 {
+        match *self {
+            InputEvent::Read { instruction, .. } | InputEvent::Interrupt { instruction, .. } => instruction,
+        }
+    }
+}
+
+/// Input of the devices during a run, in the order it was read
+#[derive(Default)]
+pub struct InputLog {
+    events: Vec<InputEvent>,
+}
+
+impl InputLog {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn events(&self) -> &[InputEvent] {
+        &self.events
+    }
+
+    pub fn push(&mut self, event: InputEvent) {
+        self.events.push(event);
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        bytes.extend_from_slice(MAGIC);
+        bytes.extend_from_slice(&FORMAT_VERSION.to_be_bytes());
+        bytes.extend_from_slice(&(self.events.len() as u32).to_be_bytes());
+
+        for event in self.events.iter() {
+            match *event {
+                InputEvent::Read { instruction, address, size, value } => {
+                    bytes.push(READ);
+                    bytes.extend_from_slice(&instruction.to_be_bytes());
+                    bytes.extend_from_slice(&address.to_be_bytes());
+                    bytes.push(size);
+                    bytes.extend_from_slice(&value.to_be_bytes());
+                }
+                InputEvent::Interrupt { instruction, lines } => {
+                    bytes.push(INTERRUPT);
+                    bytes.extend_from_slice(&instruction.to_be_bytes());
+                    bytes.extend_from_slice(&lines.to_be_bytes());
+                }
+            }
+        }
+
+        bytes
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, ReplayError> {
+        let mut reader = Reader::new(bytes);
+
+        if reader.bytes(4)? != MAGIC {
+            return Err(ReplayError::BadMagic);
+        }
+
+        let version = reader.u16()?;
+        if version != FORMAT_VERSION {
+            return Err(ReplayError::UnsupportedVersion(version));
+        }
+
+        let count = reader.u32()?;
+        let mut events = Vec::new();
+        for _ in 0..count {
+            let kind = reader.u8()?;
+            let instruction = reader.u64()?;
+
+            events.push(match kind {
+                READ => InputEvent::Read {
+                    instruction,
+                    address: reader.u16()?,
+                    size: reader.u8()?,
+                    value: reader.u16()?,
+                },
+                INTERRUPT => InputEvent::Interrupt { instruction, lines: reader.u16()? },
+                kind => return Err(ReplayError::UnknownEvent(kind)),
+            });
+        }
+
+        Ok(Self { events })
+    }
+}
+
+pub enum ReplayError {
+    BadMagic,
+    UnsupportedVersion(u16),
+    UnknownEvent(u8),
+    Truncated,
+}
+
+impl From<Truncated> for ReplayError {
+    fn from(_: Truncated) -> Self {
+        ReplayError::Truncated
+    }
+}
+
+impl std::fmt::Debug for ReplayError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let error = match self {
+            ReplayError::BadMagic => "Missing magic number, this is not an input recording".to_owned(),
+            ReplayError::UnsupportedVersion(v) => format!(
+                "Input recording format version {} is not supported, expected version {}", v, FORMAT_VERSION
+            ),
+            ReplayError::UnknownEvent(kind) => format!("Unknown input event kind {}", kind),
+            ReplayError::Truncated => "Input recording is truncated".to_owned(),
+        };
+
+        write!(f, "{}", error)
+    }
+}
@@ -120,4 +120,24 @@ impl MemoryIO for Screen {
     fn is_empty(&self) -> bool {
         self.len() > 0
     }
+
+    /// Row of the cursor, the next move is relative to it
+    fn save_state(&self) -> Vec<u8> {
+        (self.row as u16).to_be_bytes().to_vec()
+    }
+
+    fn load_state(&mut self, state: &[u8]) -> Result<(), MemoryError> {
+        match *state {
+            [hi, lo] => {
+                self.row = u16::from_be_bytes([hi, lo]) as usize;
+                Ok(())
+            }
+            _ => Err(MemoryError::BadDeviceState),
+        }
+    }
+
+    /// Nothing is read from the screen
+    fn is_input(&self) -> bool {
+        false
+    }
 }
@@ -0,0 +1,169 @@
+//! Save-state of a whole machine, written by `CPU::snapshot` and loaded by `CPU::restore`.
+//!
+//! Every value is stored big endian, like in the VM memory.
+//!
+//! | bytes | content                                            |
+//! |-------|----------------------------------------------------|
+//! | 4     | magic, `VMS\0`                                     |
+//! | 2     | format version                                     |
+//! | 2     | ISA version of the saved code                      |
+//! | 2 + n | size and content of the register memory            |
+//! | 4     | size of the current stackframe                     |
+//! | 2 x 2 | stack base and stack limit                         |
+//! | 1     | 1 when `cal` saves the flags                       |
+//! | 2     | pending interrupt lines                            |
+//! | 8 x 2 | executed cycles and instructions                   |
+//! | 2     | number of protections                              |
+//! | 9 x n | start (4 bytes), length (4 bytes) and permissions  |
+//! | 2     | number of devices                                  |
+//! | ...   | size (4 bytes) and state of every device           |
+//!
+//! Devices are stored in the order they were mapped, main memory first. A snapshot is
+//! only restored on a machine built with the same devices.
+
+use super::memory_io::MemoryError;
+
+use arch::instructions::ISA_VERSION;
+use arch::reader::{Reader, Truncated};
+
+pub const MAGIC: &[u8; 4] = b"VMS\0";
+pub const FORMAT_VERSION: u16 = 1;
+
+/// State of a machine: registers, stack, counters, memory protections and devices.
+/// Host functions and debug information aren't saved
+pub struct Snapshot {
+    pub(crate) registers: Vec<u8>,
+    pub(crate) stack_frame_size: usize,
+    pub(crate) stack_base: u16,
+    pub(crate) stack_limit: u16,
+    pub(crate) save_flags: bool,
+    pub(crate) pending: u16,
+    pub(crate) cycles: u64,
+    pub(crate) instructions: u64,
+    pub(crate) protections: Vec<(usize, usize, u8)>,
+    pub(crate) devices: Vec<Vec<u8>>,
+}
+
+impl Snapshot {
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        bytes.extend_from_slice(MAGIC);
+        bytes.extend_from_slice(&FORMAT_VERSION.to_be_bytes());
+        bytes.extend_from_slice(&ISA_VERSION.to_be_bytes());
+
+        bytes.extend_from_slice(&(self.registers.len() as u16).to_be_bytes());
+        bytes.extend_from_slice(&self.registers);
+        bytes.extend_from_slice(&(self.stack_frame_size as u32).to_be_bytes());
+        bytes.extend_from_slice(&self.stack_base.to_be_bytes());
+        bytes.extend_from_slice(&self.stack_limit.to_be_bytes());
+        bytes.push(self.save_flags as u8);
+        bytes.extend_from_slice(&self.pending.to_be_bytes());
+        bytes.extend_from_slice(&self.cycles.to_be_bytes());
+        bytes.extend_from_slice(&self.instructions.to_be_bytes());
+
+        bytes.extend_from_slice(&(self.protections.len() as u16).to_be_bytes());
+        for &(start, len, permissions) in self.protections.iter() {
+            bytes.extend_from_slice(&(start as u32).to_be_bytes());
+            bytes.extend_from_slice(&(len as u32).to_be_bytes());
+            bytes.push(permissions);
+        }
+
+        bytes.extend_from_slice(&(self.devices.len() as u16).to_be_bytes());
+        for state in self.devices.iter() {
+            bytes.extend_from_slice(&(state.len() as u32).to_be_bytes());
+            bytes.extend_from_slice(state);
+        }
+
+        bytes
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, SnapshotError> {
+        let mut reader = Reader::new(bytes);
+
+        if reader.bytes(4)? != MAGIC {
+            return Err(SnapshotError::BadMagic);
+        }
+
+        let version = reader.u16()?;
+        if version != FORMAT_VERSION {
+            return Err(SnapshotError::UnsupportedVersion(version));
+        }
+
+        // the saved memory holds code of this version
+        let isa_version = reader.u16()?;
+        if isa_version != ISA_VERSION {
+            return Err(SnapshotError::IncompatibleIsa(isa_version));
+        }
+
+        let len = reader.u16()? as usize;
+        let registers = reader.bytes(len)?.to_vec();
+        let stack_frame_size = reader.u32()? as usize;
+        let stack_base = reader.u16()?;
+        let stack_limit = reader.u16()?;
+        let save_flags = reader.u8()? != 0;
+        let pending = reader.u16()?;
+        let cycles = reader.u64()?;
+        let instructions = reader.u64()?;
+
+        let count = reader.u16()?;
+        let mut protections = Vec::with_capacity(count as usize);
+        for _ in 0..count {
+            let start = reader.u32()? as usize;
+            let len = reader.u32()? as usize;
+            protections.push((start, len, reader.u8()?));
+        }
+
+        let count = reader.u16()?;
+        let mut devices = Vec::with_capacity(count as usize);
+        for _ in 0..count {
+            let len = reader.u32()? as usize;
+            devices.push(reader.bytes(len)?.to_vec());
+        }
+
+        Ok(Self {
+            registers, stack_frame_size, stack_base, stack_limit, save_flags,
+            pending, cycles, instructions, protections, devices,
+        })
+    }
+}
+
+pub enum SnapshotError {
+    BadMagic,
+    UnsupportedVersion(u16),
+    IncompatibleIsa(u16),
+    Truncated,
+    /// the snapshot was taken on a machine with other registers or devices
+    MachineMismatch,
+    InternalMemoryError(MemoryError),
+}
+
+impl From<MemoryError> for SnapshotError {
+    fn from(error: MemoryError) -> Self {
+        SnapshotError::InternalMemoryError(error)
+    }
+}
+
+impl From<Truncated> for SnapshotError {
+    fn from(_: Truncated) -> Self {
+        SnapshotError::Truncated
+    }
+}
+
+impl std::fmt::Debug for SnapshotError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let error = match self {
+            SnapshotError::BadMagic => "Missing magic number, this is not a save-state".to_owned(),
+            SnapshotError::UnsupportedVersion(v) => format!(
+                "Save-state format version {} is not supported, expected version {}", v, FORMAT_VERSION
+            ),
+            SnapshotError::IncompatibleIsa(v) => format!(
+                "Save-state was taken with ISA version {}, this VM implements version {}", v, ISA_VERSION
+            ),
+            SnapshotError::Truncated => "Save-state is truncated".to_owned(),
+            SnapshotError::MachineMismatch => "Save-state was taken on a machine with other devices".to_owned(),
+            SnapshotError::InternalMemoryError(e) => format!("Memory error: {:?}", e),
+        };
+
+        write!(f, "{}", error)
+    }
+}
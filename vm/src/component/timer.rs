@@ -0,0 +1,151 @@
+use crate::component::memory_io::*;
+
+/// Timer counting the executed instructions. Once enabled, the counter is decremented after
+/// each instruction. When it reaches 0 the timer expires: the status flag is set, the interrupt
+/// line is raised if the control register asks for it, and the counter starts again from the
+/// reload value, unless the timer is in one-shot mode.
+///
+/// | offset | size | register                                             |
+/// |--------|------|------------------------------------------------------|
+/// | 0x00   | 2    | reload value, writing it also restarts the counter   |
+/// | 0x02   | 2    | counter, instructions left before the timer expires  |
+/// | 0x04   | 1    | control, `ENABLE`, `INTERRUPT` and `ONE_SHOT` bits   |
+/// | 0x05   | 1    | status, `EXPIRED` bit, cleared by writing a 1 on it  |
+#[derive(Default)]
+pub struct Timer {
+    reload: u16,
+    counter: u16,
+    control: u8,
+    status: u8,
+    /// the timer expired since the last poll of the interrupt line
+    raised: bool,
+}
+
+impl Timer {
+    pub const RELOAD   : usize = 0x00;
+    pub const COUNTER  : usize = 0x02;
+    pub const CONTROL  : usize = 0x04;
+    pub const STATUS   : usize = 0x05;
+
+    // bits of the control register
+    pub const ENABLE   : u8 = 1;
+    pub const INTERRUPT: u8 = 2;
+    pub const ONE_SHOT : u8 = 4;
+
+    // bits of the status register
+    pub const EXPIRED  : u8 = 1;
+
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Enabling a stopped timer starts it again from the reload value
+    fn set_control(&mut self, control: u8) {
+        self.control = control;
+
+        if control & Self::ENABLE != 0 && self.counter == 0 {
+            self.counter = self.reload;
+        }
+    }
+}
+
+impl MemoryIO for Timer {
+    fn get_memory_at_u8(&self, location: usize) -> Result<u8, MemoryError> {
+        match location {
+            0x00 => Ok((self.reload >> 8) as u8),
+            0x01 => Ok(self.reload as u8),
+            0x02 => Ok((self.counter >> 8) as u8),
+            0x03 => Ok(self.counter as u8),
+            Self::CONTROL => Ok(self.control),
+            Self::STATUS => Ok(self.status),
+            _ => Err(MemoryError::OutOfBounds(location)),
+        }
+    }
+
+    fn get_memory_at_u16(&self, location: usize) -> Result<u16, MemoryError> {
+        let left = self.get_memory_at_u8(location)?;
+        let right = self.get_memory_at_u8(location + 1)?;
+
+        Ok(((left as u16) << 8) + (right as u16))
+    }
+
+    fn set_memory_at_u8(&mut self, location: usize, data: u8) -> Result<(), MemoryError> {
+        match location {
+            0x00 => self.reload = (self.reload & 0x00FF) | ((data as u16) << 8),
+            0x01 => self.reload = (self.reload & 0xFF00) | data as u16,
+            0x02 => self.counter = (self.counter & 0x00FF) | ((data as u16) << 8),
+            0x03 => self.counter = (self.counter & 0xFF00) | data as u16,
+            Self::CONTROL => self.set_control(data),
+            Self::STATUS => self.status &= !data,
+            _ => return Err(MemoryError::OutOfBounds(location)),
+        }
+
+        if location < Self::COUNTER {
+            self.counter = self.reload;
+        }
+
+        Ok(())
+    }
+
+    fn set_memory_at_u16(&mut self, location: usize, data: u16) -> Result<(), MemoryError> {
+        self.set_memory_at_u8(location, (data >> 8) as u8)?;
+        self.set_memory_at_u8(location + 1, data as u8)
+    }
+
+    fn len(&self) -> usize {
+        6
+    }
+
+    fn is_empty(&self) -> bool {
+        false
+    }
+
+    fn tick(&mut self) {
+        if self.control & Self::ENABLE == 0 || self.counter == 0 {
+            return;
+        }
+
+        self.counter -= 1;
+        if self.counter > 0 {
+            return;
+        }
+
+        self.status |= Self::EXPIRED;
+        if self.control & Self::INTERRUPT != 0 {
+            self.raised = true;
+        }
+
+        if self.control & Self::ONE_SHOT != 0 {
+            self.control &= !Self::ENABLE;
+        } else {
+            self.counter = self.reload;
+        }
+    }
+
+    fn interrupt(&mut self) -> bool {
+        std::mem::take(&mut self.raised)
+    }
+
+    /// Registers, in the order of their offsets, then the raised line
+    fn save_state(&self) -> Vec<u8> {
+        let mut state = Vec::with_capacity(7);
+        state.extend_from_slice(&self.reload.to_be_bytes());
+        state.extend_from_slice(&self.counter.to_be_bytes());
+        state.extend_from_slice(&[self.control, self.status, self.raised as u8]);
+        state
+    }
+
+    fn load_state(&mut self, state: &[u8]) -> Result<(), MemoryError> {
+        match *state {
+            [reload_hi, reload_lo, counter_hi, counter_lo, control, status, raised] => {
+                self.reload = u16::from_be_bytes([reload_hi, reload_lo]);
+                self.counter = u16::from_be_bytes([counter_hi, counter_lo]);
+                self.control = control;
+                self.status = status;
+                self.raised = raised != 0;
+                Ok(())
+            }
+            _ => Err(MemoryError::BadDeviceState),
+        }
+    }
+}
@@ -0,0 +1,12 @@
+//! Virtual machine running the programs of the compiler. `MachineBuilder` builds a `CPU`
+//! with its memory and devices, new devices implement `MemoryIO`.
+
+pub mod component;
+mod test;
+
+pub use component::cpu::{CPU, ExecutionError, StepResult};
+pub use component::host::{HostError, HostFunction};
+pub use component::machine::{MachineBuilder, MachineError};
+pub use component::memory_io::{MemoryError, MemoryIO};
+pub use component::snapshot::{Snapshot, SnapshotError};
+pub use component::replay::{InputEvent, InputLog, ReplayError};
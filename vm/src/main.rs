@@ -1,36 +1,178 @@
-pub mod component;
-mod test;
-
 use std::fs::File;
 use std::io::prelude::*;
 use structopt::StructOpt;
 
-use crate::component::cpu::CPU;
+use vm::{InputLog, MachineBuilder, Snapshot, StepResult};
+use vm::component::loader::{self, LoadOptions};
+use arch::format::Format;
 
 #[derive(StructOpt)]
 pub struct Args {
     pub source: String,
+
+    /// format of the program: vmo, raw (legacy image without header), ihex or srec
+    #[structopt(short, long, default_value = "vmo")]
+    pub format: Format,
+
+    /// address where a raw image is loaded, like `0x8000`, by default after the interrupt tables
+    /// like the code of the compiler
+    #[structopt(short, long, default_value = "0x0050", parse(try_from_str = parse_address))]
+    pub base: u16,
+
+    /// write a jump to the entry point at address 0, execution starts on it
+    #[structopt(long)]
+    pub boot_stub: bool,
+
+    /// `cal` saves the flags in the stackframe and `ret` restores them
+    #[structopt(long)]
+    pub save_flags: bool,
+
+    /// highest address of the stack, `sp` and `fp` start on it
+    #[structopt(long, default_value = "0xFFFE", parse(try_from_str = parse_address))]
+    pub stack_base: u16,
+
+    /// lowest address the stack can write, pushing under it is a stack overflow
+    #[structopt(long, default_value = "0xF000", parse(try_from_str = parse_address))]
+    pub stack_limit: u16,
+
+    /// stop after this number of instructions, a program looping forever can't hang the VM
+    #[structopt(long)]
+    pub max_steps: Option<u64>,
+
+    /// write the state of the machine in this file when the program stops
+    #[structopt(long)]
+    pub save_state: Option<String>,
+
+    /// resume the program from a state written by `--save-state`. The state doesn't hold the
+    /// position in an input recording, it can't be used to record or replay one
+    #[structopt(long, conflicts_with_all = &["record-input", "replay-input"])]
+    pub load_state: Option<String>,
+
+    /// write the values read from the devices in this file, to replay the run
+    #[structopt(long)]
+    pub record_input: Option<String>,
+
+    /// run again with the device input written by `--record-input`
+    #[structopt(long)]
+    pub replay_input: Option<String>,
+}
+
+/// Read an address written in decimal or in hexadecimal with `0x`
+fn parse_address(src: &str) -> Result<u16, std::num::ParseIntError> {
+    match src.strip_prefix("0x").or_else(|| src.strip_prefix("0X")) {
+        Some(hex) => u16::from_str_radix(hex, 16),
+        None => src.parse(),
+    }
 }
 
 fn main() {
-    let mut cpu = CPU::default();
     let memory_capacity = 0xFF;
     let mut instructions = Vec::with_capacity(memory_capacity);
 
     let dir = "data/output/";
     let args: Args = Args::from_args();
-    let mut file = File::open(format!("{}{}.vmo", dir, args.source)).unwrap();
+    let file_name = format!("{}.{}", args.source, args.format.extension());
+    let mut file = File::open(format!("{}{}", dir, file_name)).unwrap();
     file.read_to_end(&mut instructions).unwrap();
 
-    // cpu.print_registers();
+    // a replayed run doesn't read the terminal
+    let builder = MachineBuilder::new()
+        .stack(args.stack_base, args.stack_limit)
+        .listen_keyboard(args.replay_input.is_none());
+
+    let mut cpu = match builder.build() {
+        Ok(cpu) => cpu,
+        Err(e) => {
+            eprintln!("Can't start the VM: {:?}", e);
+            return;
+        }
+    };
+    cpu.set_save_flags(args.save_flags);
+
     let start = std::time::Instant::now();
 
-    cpu.set_instruction(&instructions);
-    while cpu.step() {
-        // cpu.print_registers();
-        // cpu.print_memory_chunk_u16(0x3000, 0x3020);
+    let options = LoadOptions { base: args.base, boot_stub: args.boot_stub };
+    match loader::load(&mut cpu, &instructions, args.format, &options) {
+        Ok(warnings) => for warning in warnings {
+            eprintln!("Warning when loading \"{}\": {:?}", file_name, warning);
+        },
+        Err(e) => {
+            eprintln!("Error when loading \"{}\": {:?}", file_name, e);
+            return;
+        }
+    }
+
+    // the program is loaded first for its debug information
+    if let Some(path) = &args.load_state {
+        let restored = std::fs::read(path)
+            .map_err(|e| format!("{}", e))
+            .and_then(|bytes| Snapshot::from_bytes(&bytes).map_err(|e| format!("{:?}", e)))
+            .and_then(|snapshot| cpu.restore(&snapshot).map_err(|e| format!("{:?}", e)));
+
+        if let Err(e) = restored {
+            eprintln!("Can't load the state \"{}\": {}", path, e);
+            return;
+        }
+    }
+
+    if let Some(path) = &args.replay_input {
+        let log = std::fs::read(path)
+            .map_err(|e| format!("{}", e))
+            .and_then(|bytes| InputLog::from_bytes(&bytes).map_err(|e| format!("{:?}", e)));
+
+        match log {
+            Ok(log) => cpu.replay_input(log),
+            Err(e) => {
+                eprintln!("Can't replay the input \"{}\": {}", path, e);
+                return;
+            }
+        }
+    }
+
+    if args.record_input.is_some() {
+        cpu.record_input();
+    }
+
+    // a restored machine already counted the instructions it executed
+    let first = cpu.instructions();
+    loop {
+        let res = match args.max_steps {
+            Some(max) => cpu.run_steps(max.saturating_sub(cpu.instructions() - first)),
+            None => cpu.run(),
+        };
+
+        match res {
+            StepResult::Breakpoint => {
+                println!("\nBreakpoint");
+                cpu.print_registers();
+            },
+            StepResult::Fault { ip, error, .. } => {
+                eprintln!("{:?}", error);
+                if let Some(debug) = cpu.debug() {
+                    eprintln!("    at {}", debug.locate(ip));
+                }
+                break;
+            },
+            StepResult::BudgetExhausted => {
+                println!("\nStopped after {} instructions", cpu.instructions() - first);
+                break;
+            },
+            _ => break,
+        }
+    }
+
+    if let (Some(path), Some(log)) = (&args.record_input, cpu.take_input_log()) {
+        if let Err(e) = std::fs::write(path, log.to_bytes()) {
+            eprintln!("Can't save the input \"{}\": {}", path, e);
+        }
+    }
+
+    if let Some(path) = &args.save_state {
+        if let Err(e) = std::fs::write(path, cpu.snapshot().to_bytes()) {
+            eprintln!("Can't save the state \"{}\": {}", path, e);
+        }
     }
 
     let dur = start.elapsed().as_secs_f32();
-    println!("\nExecuted in {:.3} sec", dur);
+    println!("\nExecuted {} instructions, {} cycles in {:.3} sec", cpu.instructions(), cpu.cycles(), dur);
 }
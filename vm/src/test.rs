@@ -1,7 +1,20 @@
 #[cfg(test)]
 mod tests {
-    use crate::component::cpu::CPU;
+    use crate::component::cpu::{CPU, ExecutionError, StepResult};
+    use crate::component::machine::{MachineBuilder, MachineError};
+    use crate::component::loader::{self, LoadOptions};
+    use crate::component::debug::DebugMap;
+    use crate::component::memory_io::{MemoryError, MemoryIO};
+    use crate::component::memory_map::{self, MemoryMap};
+    use crate::component::timer::Timer;
+    use crate::component::keyboard::Keyboard;
+    use crate::component::snapshot::{Snapshot, SnapshotError};
+    use crate::component::replay::{InputEvent, InputLog, ReplayError};
+    use std::cell::Cell;
+    use arch::format::{self, Format};
+    use arch::object::{DebugInfo, LineEntry, Object, Section, SectionKind, Symbol};
     use arch::{instructions::*, registers::*};
+    use arch::interrupts::{syscall_address, vector_address, TABLES_END};
 
     #[test]
     fn cpu_register_test() {
@@ -32,7 +45,7 @@ mod tests {
             ADD_REG_REG, AX,   BX,        // add r1 and r2
         ];
         let expected = [0x001A, 0x000F];
-        cpu.set_instruction(&instructions);
+        cpu.load_at(0x0000, &instructions).unwrap();
 
         for expected_val in &expected {
             for _ in 0..3 { cpu.step(); }
@@ -56,8 +69,8 @@ mod tests {
             END,                                 // stop the program
         ];
 
-        cpu.set_instruction(&instructions);
-        while cpu.step() {}
+        cpu.load_at(0x0000, &instructions).unwrap();
+        cpu.run();
         assert_eq!(cpu.get_register("ax").unwrap(), 0x0002);
         assert_eq!(cpu.get_register("bx").unwrap(), 0x0000);
         assert_eq!(cpu.get_register("acc").unwrap(), 0x003);
@@ -97,8 +110,8 @@ mod tests {
             END,
         ];
 
-        cpu.set_instruction(&instructions);
-        while cpu.step() {}
+        cpu.load_at(0x0000, &instructions).unwrap();
+        cpu.run();
 
         assert_eq!(cpu.get_register("ax").unwrap(), 0xF400);
         assert_eq!(cpu.get_register("bx").unwrap(), 0x004F);
@@ -115,8 +128,8 @@ mod tests {
             END,
         ];
 
-        cpu.set_instruction(&instructions);
-        while cpu.step() {}
+        cpu.load_at(0x0000, &instructions).unwrap();
+        cpu.run();
 
         assert_eq!(cpu.get_register("ax").unwrap(), 0x0100);
         assert_eq!(cpu.get_register("bx").unwrap(), 0x0300);
@@ -133,13 +146,32 @@ mod tests {
             END,
         ];
 
-        cpu.set_instruction(&instructions);
-        while cpu.step() {}
+        cpu.load_at(0x0000, &instructions).unwrap();
+        cpu.run();
 
         assert_eq!(cpu.get_register("ax").unwrap(), 0x0100);
         assert_eq!(cpu.get_register("bh").unwrap(), 0x45); // lost upper byte of data -> 0x03
     }
 
+    #[test]
+    fn move_memory_to_memory() {
+        let mut cpu = CPU::default();
+        let instructions = [
+            MOV_LIT_MEM16,  0x12, 0x34, 0x15, 0x00,  // put 0x1234 at 0x1500 in memory
+            MOV_MEM_MEM_16, 0x15, 0x00, 0x16, 0x00,  // copy 0x1500 to 0x1600
+            MOV_MEM_MEM_8,  0x15, 0x01, 0x17, 0x00,  // copy 0x1501 to 0x1700
+            MOV_MEM_REG,    0x16, 0x00, AX,
+            MOV_MEM_REG,    0x17, 0x00, BL,
+            END,
+        ];
+
+        cpu.load_at(0x0000, &instructions).unwrap();
+        cpu.run();
+
+        assert_eq!(cpu.get_register("ax").unwrap(), 0x1234);
+        assert_eq!(cpu.get_register("bl").unwrap(), 0x34);
+    }
+
     #[test]
     fn test_subtractions() {
         let mut cpu = CPU::default();
@@ -150,8 +182,8 @@ mod tests {
             END,
         ];
 
-        cpu.set_instruction(&instructions);
-        while cpu.step() {}
+        cpu.load_at(0x0000, &instructions).unwrap();
+        cpu.run();
 
         assert_eq!(cpu.get_register("ah").unwrap(), 0x04);
         assert_eq!(cpu.get_register("al").unwrap(), 0x03);
@@ -168,8 +200,8 @@ mod tests {
             END,
         ];
 
-        cpu.set_instruction(&instructions);
-        while cpu.step() {}
+        cpu.load_at(0x0000, &instructions).unwrap();
+        cpu.run();
 
         assert_eq!(cpu.get_register("ah").unwrap(), 0x04);
         assert_eq!(cpu.get_register("acc").unwrap(), 0x0001);
@@ -184,8 +216,8 @@ mod tests {
             END,
         ];
 
-        cpu.set_instruction(&instructions);
-        while cpu.step() {}
+        cpu.load_at(0x0000, &instructions).unwrap();
+        cpu.run();
 
         assert_eq!(cpu.get_register("ah").unwrap(), 0x04);
         assert_eq!(cpu.get_register("acc").unwrap(), 0x0001);
@@ -201,8 +233,8 @@ mod tests {
             END,
         ];
 
-        cpu.set_instruction(&instructions);
-        while cpu.step() {}
+        cpu.load_at(0x0000, &instructions).unwrap();
+        cpu.run();
 
         assert_eq!(cpu.get_register("ah").unwrap(), 0x04);
         assert_eq!(cpu.get_register("al").unwrap(), 0x03);
@@ -219,13 +251,187 @@ mod tests {
             END,
         ];
 
-        cpu.set_instruction(&instructions);
-        while cpu.step() {}
+        cpu.load_at(0x0000, &instructions).unwrap();
+        cpu.run();
 
         assert_eq!(cpu.get_register("ah").unwrap(), 0x04);
         assert_eq!(cpu.get_register("acc").unwrap(), 0x000C);
     }
 
+    #[test]
+    fn step_results() {
+        let mut cpu = CPU::default();
+        let instructions = [
+            MOV_LIT_REG, 0x00, 0x01, AX, // 0x0000
+            BRK,                         // 0x0004
+            INC_REG, AX,                 // 0x0005
+            DIV_REG_REG, AX, BX,         // 0x0007
+            END,                         // 0x000A
+        ];
+
+        cpu.load_at(0x0000, &instructions).unwrap();
+        assert!(matches!(cpu.run(), StepResult::Breakpoint));
+        assert_eq!(cpu.get_register("ip").unwrap(), 0x0005);
+
+        // the program goes on after the breakpoint
+        match cpu.run() {
+            StepResult::Fault { ip, opcode, error } => {
+                assert_eq!(ip, 0x0007);
+                assert_eq!(opcode, Some(DIV_REG_REG));
+                assert!(matches!(error, ExecutionError::DivisionByZero));
+            },
+            res => panic!("unexpected {:?}", res),
+        }
+        assert_eq!(cpu.get_register("ax").unwrap(), 0x0002);
+
+        cpu.set_register("ip", 0x000A).unwrap();
+        assert!(matches!(cpu.step(), StepResult::Halted));
+    }
+
+    #[test]
+    fn cycles_and_budget() {
+        let mut cpu = CPU::default();
+        let instructions = [
+            MOV_LIT_REG, 0x00, 0x03, AX, // 0x0000, 1 cycle
+            PSH_REG, AX,                 // 0x0004, 2
+            POP_REG, BX,                 // 0x0006, 2
+            MUL_REG_REG, AX, BX,         // 0x0008, 4
+            END,                         // 0x000B, 1
+        ];
+
+        cpu.load_at(0x0000, &instructions).unwrap();
+        assert!(matches!(cpu.run(), StepResult::Halted));
+        assert_eq!(cpu.instructions(), 5);
+        assert_eq!(cpu.cycles(), 10);
+
+        // a loop never ending
+        let mut cpu = CPU::default();
+        let instructions = [
+            INC_REG, AX,                 // 0x0000
+            JMP_LIT, 0x00, 0x00,         // 0x0002
+        ];
+
+        cpu.load_at(0x0000, &instructions).unwrap();
+        assert!(matches!(cpu.run_steps(7), StepResult::BudgetExhausted));
+        assert_eq!(cpu.instructions(), 7);
+        assert_eq!(cpu.get_register("ax").unwrap(), 0x0004);
+
+        assert!(matches!(cpu.run_for(100), StepResult::BudgetExhausted));
+        assert_eq!(cpu.cycles(), 107);
+        assert_eq!(cpu.instructions(), 107);
+
+        // a breakpoint stops the run before the budget
+        let mut cpu = CPU::default();
+        cpu.load_at(0x0000, &[BRK, END]).unwrap();
+        assert!(matches!(cpu.run_for(100), StepResult::Breakpoint));
+        assert_eq!(cpu.cycles(), 1);
+
+        // an interrupt without handler isn't billed
+        let mut cpu = CPU::default();
+        cpu.load_at(0x0000, &[STI, END]).unwrap();
+        assert!(matches!(cpu.step(), StepResult::Continue));
+        let cycles = cpu.cycles();
+
+        cpu.raise_interrupt(1);
+        let res = cpu.step();
+        assert!(matches!(res, StepResult::Fault { error: ExecutionError::NoInterruptHandler(1), .. }));
+        assert_eq!(cpu.cycles(), cycles);
+    }
+
+    #[test]
+    fn test_division() {
+        let mut cpu = CPU::default();
+        let instructions = [
+            MOV_LIT_REG, 0x00, 0x64, AX,
+            DIV_REG_LIT, AX, 0x00, 0x07,
+            MOV_REG_REG, ACC, BX,
+            MOV_LIT_REG, 0x00, 0x64, CX,
+            MOV_LIT_REG, 0x00, 0x0A, DX,
+            MOD_REG_REG, CX, DX,
+            END,
+        ];
+
+        cpu.load_at(0x0000, &instructions).unwrap();
+        cpu.run();
+
+        assert_eq!(cpu.get_register("bx").unwrap(), 0x000E);
+        assert_eq!(cpu.get_register("ax").unwrap(), 0x0002);
+        assert_eq!(cpu.get_register("cx").unwrap(), 0x000A);
+        assert_eq!(cpu.get_register("acc").unwrap(), 0x0000);
+    }
+
+    #[test]
+    fn narrow_division() {
+        // an 8 bits register is divided by the whole divisor
+        let mut cpu = CPU::default();
+        let instructions = [
+            MOV_LIT_REG, 0x00, 0x37, AL,
+            DIV_REG_LIT, AL, 0x01, 0x00,
+            MOV_REG_REG, ACC, EX,
+            MOV_LIT_REG, 0x00, 0x37, CL,
+            MOD_REG_LIT, CL, 0x01, 0x01,
+            MOV_REG_REG, ACC, FX,
+            MOV_LIT_REG, 0x01, 0x00, DX,
+            MOV_LIT_REG, 0x00, 0x40, AH,
+            DIV_REG_REG, AH, DX,
+            MOV_REG_REG, ACC, GX,
+            MOV_LIT_REG, 0x00, 0xFA, BL, // -6 on 8 bits
+            SDIV_REG_LIT, BL, 0xFF, 0xFE, // -2
+            END,
+        ];
+
+        cpu.load_at(0x0000, &instructions).unwrap();
+        assert!(matches!(cpu.run(), StepResult::Halted));
+
+        assert_eq!(cpu.get_register("ex").unwrap(), 0x0000);
+        assert_eq!(cpu.get_register("al").unwrap(), 0x0037);
+        assert_eq!(cpu.get_register("fx").unwrap(), 0x0037);
+        assert_eq!(cpu.get_register("cl").unwrap(), 0x0000);
+        assert_eq!(cpu.get_register("gx").unwrap(), 0x0000);
+        assert_eq!(cpu.get_register("ah").unwrap(), 0x0040);
+        assert_eq!(cpu.get_register("acc").unwrap(), 0x0003);
+        assert_eq!(cpu.get_register("bl").unwrap(), 0x0000);
+    }
+
+    #[test]
+    fn test_signed_division() {
+        let mut cpu = CPU::default();
+        let instructions = [
+            MOV_LIT_REG, 0xFF, 0x9C, AX,  // -100
+            SDIV_REG_LIT, AX, 0x00, 0x07,
+            MOV_REG_REG, ACC, BX,
+            MOV_LIT_REG, 0x00, 0xF9, CL,  // -7 on 8 bits
+            SMOD_REG_LIT, CL, 0x00, 0x02,
+            END,
+        ];
+
+        cpu.load_at(0x0000, &instructions).unwrap();
+        cpu.run();
+
+        assert_eq!(cpu.get_register("bx").unwrap(), 0xFFF2);
+        assert_eq!(cpu.get_register("ax").unwrap(), 0xFFFE);
+        assert_eq!(cpu.get_register("cl").unwrap(), 0x00FD);
+        assert_eq!(cpu.get_register("acc").unwrap(), 0xFFFF);
+    }
+
+    #[test]
+    fn division_by_zero() {
+        let mut cpu = CPU::default();
+        let instructions = [
+            MOV_LIT_REG, 0x00, 0x05, AX,
+            DIV_REG_REG, AX, BX,
+            MOV_LIT_REG, 0x00, 0x01, CX,
+            END,
+        ];
+
+        cpu.load_at(0x0000, &instructions).unwrap();
+        assert!(matches!(cpu.step(), StepResult::Continue));
+        assert!(matches!(cpu.step(), StepResult::Fault { ip: 0x0004, .. }));
+
+        assert_eq!(cpu.get_register("ax").unwrap(), 0x0005);
+        assert_eq!(cpu.get_register("cx").unwrap(), 0x0000);
+    }
+
     #[test]
     fn test_shifts() {
         let mut cpu = CPU::default();
@@ -240,8 +446,8 @@ mod tests {
             END,
         ];
 
-        cpu.set_instruction(&instructions);
-        while cpu.step() {}
+        cpu.load_at(0x0000, &instructions).unwrap();
+        cpu.run();
 
         assert_eq!(cpu.get_register("al").unwrap(), 0x01);
         assert_eq!(cpu.get_register("bl").unwrap(), 0x01);
@@ -266,8 +472,8 @@ mod tests {
             END,
         ];
 
-        cpu.set_instruction(&instructions);
-        while cpu.step() {}
+        cpu.load_at(0x0000, &instructions).unwrap();
+        cpu.run();
 
         assert_eq!(cpu.get_register("ax").unwrap(), 0x0103);
         assert_eq!(cpu.get_register("bx").unwrap(), 0xFE02);
@@ -275,6 +481,55 @@ mod tests {
         assert_eq!(cpu.get_register("dx").unwrap(), 0x0001);
     }
 
+    #[test]
+    fn flags_register() {
+        let mut cpu = CPU::default();
+        let instructions = [
+            CMP_REG_LIT, AX, 0x00, 0x01, // 0x0000, carry and neg
+            PUSHF,                       // 0x0004
+            MOV_REG_REG, FL, BX,         // 0x0005
+            CMP_REG_REG, AX, AX,         // 0x0008, zero
+            POPF,                        // 0x000B
+            JB_LIT, 0x00, 0x13,          // 0x000C
+            MOV_LIT_REG, 0x00, 0x01, CX, // 0x000F
+            END                          // 0x0013
+        ];
+
+        cpu.load_at(0x0000, &instructions).unwrap();
+        cpu.run();
+
+        // carry and neg, in supervisor mode
+        assert_eq!(cpu.get_register("bx").unwrap(), 0x0026);
+        assert_eq!(cpu.get_register("cx").unwrap(), 0x0000);
+        assert_eq!(cpu.get_register("fl").unwrap(), 0x0026);
+    }
+
+    #[test]
+    fn save_flags_in_stackframe() {
+        let instructions = [
+            CMP_REG_LIT, AX, 0x00, 0x01, // 0x0000, carry
+            CALL_LIT, 0x00, 0x0F,        // 0x0004
+            JB_LIT, 0x00, 0x0E,          // 0x0007
+            MOV_LIT_REG, 0x00, 0x01, CX, // 0x000A
+            END,                         // 0x000E
+
+            CMP_REG_REG, AX, AX,         // 0x000F, clear carry
+            RET                          // 0x0012
+        ];
+
+        let mut cpu = CPU::default();
+        cpu.load_at(0x0000, &instructions).unwrap();
+        cpu.run();
+        assert_eq!(cpu.get_register("cx").unwrap(), 0x0001);
+
+        let mut cpu = CPU::default();
+        cpu.set_save_flags(true);
+        cpu.load_at(0x0000, &instructions).unwrap();
+        cpu.run();
+        assert_eq!(cpu.get_register("cx").unwrap(), 0x0000);
+        assert_eq!(cpu.get_register("sp").unwrap(), 0xFFFE);
+    }
+
     #[test]
     fn call_subroutine() {
         let mut cpu = CPU::default();
@@ -294,8 +549,8 @@ mod tests {
             RET,                         // 0x0026
         ];
 
-        cpu.set_instruction(&instructions);
-        while cpu.step() {}
+        cpu.load_at(0x0000, &instructions).unwrap();
+        cpu.run();
 
         assert_eq!(cpu.get_register("ax").unwrap(), 0x1111);
         assert_eq!(cpu.get_register("bx").unwrap(), 0x2222);
@@ -303,6 +558,130 @@ mod tests {
         assert_eq!(cpu.get_register("dx").unwrap(), 0x0000);
     }
 
+    #[test]
+    fn call_with_arguments() {
+        let mut cpu = CPU::default();
+        let instructions = [
+            PSH_LIT, 0x00, 0x05,                    // 0x0000, second argument
+            PSH_LIT, 0x00, 0x03,                    // 0x0003, first argument
+            CALL_LIT_ARGS, 0x00, 0x0E, 0x00, 0x02,  // 0x0006
+            END,                                    // 0x000B
+            0x00, 0x00,
+            PSH_LIT, 0x00, 0x00,                    // 0x000E, local at fp-16
+            CALL_LIT, 0x00, 0x30,                   // 0x0011
+            MOV_LITOFF_REG, 0x00, 0x08, FP, AX,     // 0x0014
+            MOV_LITOFF_REG, 0x00, 0x0A, FP, BX,     // 0x0019
+            SUB_REG_REG, AX, BX,                    // 0x001E
+            MOV_REG_LITOFF, ACC, 0xFF, 0xF0, FP,    // 0x0021
+            MOV_LIT_REG, 0x00, 0x00, ACC,           // 0x0026
+            MOV_LITOFF_REG, 0xFF, 0xF0, FP, ACC,    // 0x002A
+            RET,                                    // 0x002F
+            MOV_LIT_REG, 0x12, 0x34, AX,            // 0x0030
+            RET,                                    // 0x0034
+        ];
+
+        cpu.load_at(0x0000, &instructions).unwrap();
+        cpu.run();
+
+        assert_eq!(cpu.get_register("acc").unwrap(), 0x0002);
+        assert_eq!(cpu.get_register("ax").unwrap(), 0x0000);
+        assert_eq!(cpu.get_register("bx").unwrap(), 0x0000);
+        assert_eq!(cpu.get_register("sp").unwrap(), 0xFFFE);
+        assert_eq!(cpu.get_register("fp").unwrap(), 0xFFFE);
+    }
+
+    #[test]
+    fn return_discards_arguments() {
+        let mut cpu = CPU::default();
+        let instructions = [
+            PSH_LIT, 0x11, 0x11,         // 0x0000
+            PSH_LIT, 0x22, 0x22,         // 0x0003
+            CALL_LIT, 0x00, 0x0C,        // 0x0006
+            POP_REG, AX,                 // 0x0009
+            END,                         // 0x000B
+            RET_LIT, 0x00, 0x01,         // 0x000C
+        ];
+
+        cpu.load_at(0x0000, &instructions).unwrap();
+        cpu.run();
+
+        assert_eq!(cpu.get_register("ax").unwrap(), 0x1111);
+        assert_eq!(cpu.get_register("sp").unwrap(), 0xFFFE);
+    }
+
+    #[test]
+    fn jump_to_subroutine() {
+        let mut cpu = CPU::default();
+        let instructions = [
+            MOV_LIT_REG, 0x11, 0x11, AX, // 0x0000
+            JSR_LIT, 0x00, 0x0C,         // 0x0004
+            MOV_REG_REG, AX, BX,         // 0x0007
+            END,                         // 0x000A
+            0x00,
+            MOV_LIT_REG, 0x22, 0x22, AX, // 0x000C, registers aren't saved
+            RTS,                         // 0x0010
+        ];
+
+        cpu.load_at(0x0000, &instructions).unwrap();
+        cpu.run();
+
+        assert_eq!(cpu.get_register("bx").unwrap(), 0x2222);
+        assert_eq!(cpu.get_register("sp").unwrap(), 0xFFFE);
+    }
+
+    #[test]
+    fn stack_overflow() {
+        let mut cpu = CPU::default();
+        cpu.load_at(0x0100, &[
+            JSR_LIT, 0x01, 0x00,         // 0x0100, endless recursion
+        ]).unwrap();
+        cpu.load_at(0x011E, &[0x12, 0x34]).unwrap();
+        cpu.load_at(0x0200, &[
+            MOV_MEM_REG, 0x01, 0x1E, AX, // 0x0200
+            END,                         // 0x0204
+        ]).unwrap();
+        cpu.set_stack(0x0140, 0x0120);
+        cpu.set_register("ip", 0x0100).unwrap();
+
+        cpu.run();
+        assert_eq!(cpu.get_register("sp").unwrap(), 0x011E);
+
+        // the word under the limit isn't overwritten
+        cpu.set_register("ip", 0x0200).unwrap();
+        cpu.run();
+        assert_eq!(cpu.get_register("ax").unwrap(), 0x1234);
+    }
+
+    #[test]
+    fn stack_underflow() {
+        for ins in [POP_REG, RET, RTS].iter() {
+            let mut cpu = CPU::default();
+            cpu.load_at(0x0000, &[*ins, AX, END]).unwrap();
+
+            assert!(matches!(cpu.step(), StepResult::Fault { ip: 0x0000, .. }));
+            assert_eq!(cpu.get_register("sp").unwrap(), 0xFFFE);
+        }
+
+        // popping after moving `sp` by hand stays in the stack
+        let mut cpu = CPU::default();
+        let instructions = [
+            PSH_LIT, 0x00, 0x07,         // 0x0000
+            MOV_LIT_REG, 0xFF, 0xFA, SP, // 0x0003
+            POP_REG, AX,                 // 0x0007
+            POP_REG, BX,                 // 0x0009
+            POP_REG, CX,                 // 0x000B, over the base
+            END,
+        ];
+
+        cpu.load_at(0x0000, &instructions).unwrap();
+        cpu.run();
+
+        assert_eq!(cpu.get_register("bx").unwrap(), 0x0007);
+        assert_eq!(cpu.get_register("ip").unwrap(), 0x000D);
+        assert_eq!(cpu.get_register("cx").unwrap(), 0x0000);
+        assert_eq!(cpu.get_register("sp").unwrap(), 0xFFFE);
+    }
+
     #[test]
     fn jump_unconditional() {
         let mut cpu = CPU::default();
@@ -315,8 +694,8 @@ mod tests {
             END                          // 0x0011
         ];
 
-        cpu.set_instruction(&instructions);
-        while cpu.step() {}
+        cpu.load_at(0x0000, &instructions).unwrap();
+        cpu.run();
 
         assert_eq!(cpu.get_register("bx").unwrap(), 0x0000);
     }
@@ -356,8 +735,8 @@ mod tests {
         let mut cpu = CPU::default();
         let instructions = jump_code!(JEQ_LIT, JEQ_REG);
 
-        cpu.set_instruction(&instructions);
-        while cpu.step() {}
+        cpu.load_at(0x0000, &instructions).unwrap();
+        cpu.run();
 
         assert_eq!(cpu.get_register("bx").unwrap(), 0x0101);
         assert_eq!(cpu.get_register("cx").unwrap(), 0x0000);
@@ -369,8 +748,8 @@ mod tests {
         let mut cpu = CPU::default();
         let instructions = jump_code!(JNE_LIT, JNE_REG);
 
-        cpu.set_instruction(&instructions);
-        while cpu.step() {}
+        cpu.load_at(0x0000, &instructions).unwrap();
+        cpu.run();
 
         assert_eq!(cpu.get_register("bx").unwrap(), 0x0000);
         assert_eq!(cpu.get_register("cx").unwrap(), 0x0101);
@@ -382,8 +761,8 @@ mod tests {
         let mut cpu = CPU::default();
         let instructions = jump_code!(JGT_LIT, JGT_REG);
 
-        cpu.set_instruction(&instructions);
-        while cpu.step() {}
+        cpu.load_at(0x0000, &instructions).unwrap();
+        cpu.run();
 
         assert_eq!(cpu.get_register("bx").unwrap(), 0x0101);
         assert_eq!(cpu.get_register("cx").unwrap(), 0x0101);
@@ -395,8 +774,8 @@ mod tests {
         let mut cpu = CPU::default();
         let instructions = jump_code!(JGE_LIT, JGE_REG);
 
-        cpu.set_instruction(&instructions);
-        while cpu.step() {}
+        cpu.load_at(0x0000, &instructions).unwrap();
+        cpu.run();
 
         assert_eq!(cpu.get_register("bx").unwrap(), 0x0101);
         assert_eq!(cpu.get_register("cx").unwrap(), 0x0000);
@@ -408,8 +787,8 @@ mod tests {
         let mut cpu = CPU::default();
         let instructions = jump_code!(JLT_LIT, JLT_REG);
 
-        cpu.set_instruction(&instructions);
-        while cpu.step() {}
+        cpu.load_at(0x0000, &instructions).unwrap();
+        cpu.run();
 
         assert_eq!(cpu.get_register("bx").unwrap(), 0x0000);
         assert_eq!(cpu.get_register("cx").unwrap(), 0x0101);
@@ -421,11 +800,819 @@ mod tests {
         let mut cpu = CPU::default();
         let instructions = jump_code!(JLE_LIT, JLE_REG);
 
-        cpu.set_instruction(&instructions);
-        while cpu.step() {}
+        cpu.load_at(0x0000, &instructions).unwrap();
+        cpu.run();
 
         assert_eq!(cpu.get_register("bx").unwrap(), 0x0000);
         assert_eq!(cpu.get_register("cx").unwrap(), 0x0000);
         assert_eq!(cpu.get_register("dx").unwrap(), 0x0101);
     }
+
+    #[test]
+    fn jump_above() {
+        let mut cpu = CPU::default();
+        let instructions = jump_code!(JA_LIT, JA_REG);
+
+        cpu.load_at(0x0000, &instructions).unwrap();
+        cpu.run();
+
+        assert_eq!(cpu.get_register("bx").unwrap(), 0x0101);
+        assert_eq!(cpu.get_register("cx").unwrap(), 0x0101);
+        assert_eq!(cpu.get_register("dx").unwrap(), 0x0000);
+    }
+
+    #[test]
+    fn jump_above_or_equal() {
+        let mut cpu = CPU::default();
+        let instructions = jump_code!(JAE_LIT, JAE_REG);
+
+        cpu.load_at(0x0000, &instructions).unwrap();
+        cpu.run();
+
+        assert_eq!(cpu.get_register("bx").unwrap(), 0x0101);
+        assert_eq!(cpu.get_register("cx").unwrap(), 0x0000);
+        assert_eq!(cpu.get_register("dx").unwrap(), 0x0000);
+    }
+
+    #[test]
+    fn jump_below() {
+        let mut cpu = CPU::default();
+        let instructions = jump_code!(JB_LIT, JB_REG);
+
+        cpu.load_at(0x0000, &instructions).unwrap();
+        cpu.run();
+
+        assert_eq!(cpu.get_register("bx").unwrap(), 0x0000);
+        assert_eq!(cpu.get_register("cx").unwrap(), 0x0101);
+        assert_eq!(cpu.get_register("dx").unwrap(), 0x0101);
+    }
+
+    #[test]
+    fn jump_below_or_equal() {
+        let mut cpu = CPU::default();
+        let instructions = jump_code!(JBE_LIT, JBE_REG);
+
+        cpu.load_at(0x0000, &instructions).unwrap();
+        cpu.run();
+
+        assert_eq!(cpu.get_register("bx").unwrap(), 0x0000);
+        assert_eq!(cpu.get_register("cx").unwrap(), 0x0000);
+        assert_eq!(cpu.get_register("dx").unwrap(), 0x0101);
+    }
+
+    #[test]
+    fn signed_comparison() {
+        let mut cpu = CPU::default();
+        let instructions = [
+            MOV_LIT_REG, 0xFF, 0xFF, AX, // 0x0000, -1
+            CMP_REG_LIT, AX, 0x00, 0x01, // 0x0004
+            JLT_LIT, 0x00, 0x0F,         // 0x0008, -1 < 1
+            MOV_LIT_REG, 0x00, 0x01, BH, // 0x000B
+            CMP_REG_LIT, AX, 0x00, 0x01, // 0x000F
+            JA_LIT, 0x00, 0x1A,          // 0x0013, 0xFFFF > 1
+            MOV_LIT_REG, 0x00, 0x01, BL, // 0x0016
+            MOV_LIT_REG, 0x00, 0x80, CX, // 0x001A
+            CMP_REG_LIT, CL, 0x00, 0x00, // 0x001E
+            JLT_LIT, 0x00, 0x29,         // 0x0022, cl is -128
+            MOV_LIT_REG, 0x00, 0x01, DH, // 0x0025
+            CMP_REG_LIT, CX, 0x00, 0x00, // 0x0029
+            JLT_LIT, 0x00, 0x34,         // 0x002D, cx is 128
+            MOV_LIT_REG, 0x00, 0x01, DL, // 0x0030
+            MOV_LIT_REG, 0x80, 0x00, AX, // 0x0034, -32768
+            CMP_REG_LIT, AX, 0x00, 0x01, // 0x0038, overflows
+            JLT_LIT, 0x00, 0x43,         // 0x003C
+            MOV_LIT_REG, 0x00, 0x01, EX, // 0x003F
+            END                          // 0x0043
+        ];
+
+        cpu.load_at(0x0000, &instructions).unwrap();
+        cpu.run();
+
+        assert_eq!(cpu.get_register("bx").unwrap(), 0x0000);
+        assert_eq!(cpu.get_register("dx").unwrap(), 0x0001);
+        assert_eq!(cpu.get_register("ex").unwrap(), 0x0000);
+    }
+
+    #[test]
+    fn overflow_flag() {
+        let mut cpu = CPU::default();
+        let instructions = [
+            MOV_LIT_REG, 0x7F, 0xFF, AX, // 0x0000
+            ADD_REG_LIT, AX, 0x00, 0x01, // 0x0004, 0x8000 is negative but 0x7FFF + 1 doesn't carry
+            JB_LIT, 0x00, 0x0F,          // 0x0008
+            MOV_LIT_REG, 0x00, 0x01, BH, // 0x000B
+            MOV_REG_REG, ACC, AX,        // 0x000F
+            DEC_REG, AX,                 // 0x0012, -32768 - 1 overflows
+            JLT_LIT, 0x00, 0x1B,         // 0x0014, the true result is negative
+            MOV_LIT_REG, 0x00, 0x01, BL, // 0x0017
+            END                          // 0x001B
+        ];
+
+        cpu.load_at(0x0000, &instructions).unwrap();
+        cpu.run();
+
+        assert_eq!(cpu.get_register("acc").unwrap(), 0x8000);
+        assert_eq!(cpu.get_register("ax").unwrap(), 0x7FFF);
+        assert_eq!(cpu.get_register("bx").unwrap(), 0x0100);
+    }
+
+    #[test]
+    fn load_object() {
+        let mut cpu = CPU::default();
+
+        let mut object = Object::new(0x0004);
+        object.sections.push(Section::new(SectionKind::Code, 0, vec![
+            END,
+            0x00, 0x00, 0x00,
+            MOV_MEM_REG, 0x00, 0x20, AX,  // entry point, load the data
+            INC_REG, BX,
+            END,
+        ]));
+        object.sections.push(Section::new(SectionKind::Data, 0x0020, vec![0x12, 0x34]));
+
+        loader::load_object(&mut cpu, &object.to_bytes()).unwrap();
+        assert_eq!(cpu.get_register("ip").unwrap(), 0x0004);
+
+        cpu.run();
+        assert_eq!(cpu.get_register("ax").unwrap(), 0x1234);
+        assert_eq!(cpu.get_register("bx").unwrap(), 0x0001);
+
+        object.isa_version = ISA_VERSION + 1;
+        assert!(loader::load_object(&mut cpu, &object.to_bytes()).is_err());
+        assert!(loader::load_object(&mut cpu, &[END]).is_err());
+    }
+
+    #[test]
+    fn load_protected_object() {
+        let mut object = Object::new(0x0000);
+        object.sections.push(Section::new(SectionKind::Code, 0, vec![
+            MOV_LIT_MEM16, 0x00, 0x01, 0x00, 0x60, // write the data
+            MOV_LIT_MEM16, 0x00, 0x01, 0x00, 0x00, // write the code
+            END,
+        ]));
+        object.sections.push(Section::new(SectionKind::Data, 0x0060, vec![0x12, 0x34, END]));
+
+        let mut cpu = CPU::default();
+        loader::load_object(&mut cpu, &object.to_bytes()).unwrap();
+
+        assert!(matches!(cpu.step(), StepResult::Continue));
+        assert!(matches!(cpu.step(), StepResult::Fault { ip: 0x0005, .. }));
+        assert_eq!(cpu.get_register("ip").unwrap(), 0x000A);
+
+        // the data can't be executed
+        cpu.set_register("ip", 0x0062).unwrap();
+        assert!(matches!(cpu.step(), StepResult::Fault { ip: 0x0062, .. }));
+        assert_eq!(cpu.get_register("ip").unwrap(), 0x0062);
+
+        // a raw image stays writable
+        let mut cpu = CPU::default();
+        let image = object.sections[0].data.clone();
+        loader::load(&mut cpu, &image, Format::Raw, &LoadOptions::default()).unwrap();
+
+        cpu.run();
+        assert_eq!(cpu.get_register("ip").unwrap(), 0x000B);
+    }
+
+    #[test]
+    fn load_over_tables() {
+        // code loaded at 0 runs over the tables, they stay supervisor-only
+        let mut object = Object::new(0x0000);
+        let mut code = vec![0x00; 0x60];
+        code[0] = END;
+        object.sections.push(Section::new(SectionKind::Code, 0, code));
+
+        let mut cpu = CPU::default();
+        let warnings = loader::load_object(&mut cpu, &object.to_bytes()).unwrap();
+        assert!(matches!(warnings[..], [loader::LoadWarning::TableOverlap(0x0000)]));
+
+        cpu.set_register("fl", 0).unwrap();
+        assert!(matches!(cpu.get_memory_at_u8(0x0010), Err(MemoryError::SupervisorOnly(0x0010))));
+        assert!(matches!(cpu.get_memory_at_u8(0x004F), Err(MemoryError::SupervisorOnly(0x004F))));
+        assert!(cpu.get_memory_at_u8(0x0050).is_ok());
+
+        // a program placed after the tables
+        let mut object = Object::new(TABLES_END);
+        object.sections.push(Section::new(SectionKind::Code, TABLES_END, vec![END]));
+        let mut cpu = CPU::default();
+        assert!(loader::load_object(&mut cpu, &object.to_bytes()).unwrap().is_empty());
+    }
+
+    #[test]
+    fn locate_address() {
+        let mut object = Object::new(0);
+        object.sections.push(Section::new(SectionKind::Code, 0, vec![
+            MOV_LIT_REG, 0x00, 0x01, AX,    // 0x0000
+            INC_REG, AX,                    // 0x0004
+            RET,                            // 0x0006
+        ]));
+        object.sections.push(Symbol::to_section(&[
+            Symbol::label("start".to_owned(), 0x0000, 2),
+            Symbol::label("fail".to_owned(), 0x0004, 4),
+        ]));
+        object.sections.push(DebugInfo {
+            files: vec!["main.vms".to_owned()],
+            lines: vec![
+                LineEntry { address: 0x0000, file: 0, line: 3 },
+                LineEntry { address: 0x0004, file: 0, line: 5 },
+                LineEntry { address: 0x0006, file: 0, line: 6 },
+            ],
+        }.to_section());
+
+        let debug = DebugMap::new(&object).unwrap();
+        assert_eq!(debug.locate(0x0000), "0x0000 (main.vms:3) in start");
+        assert_eq!(debug.locate(0x0006), "0x0006 (main.vms:6) in fail+0x2");
+        assert_eq!(debug.locate(0x3000), "0x3000");
+    }
+
+    #[test]
+    fn load_hex_files() {
+        let mut object = Object::new(0x0100);
+        object.sections.push(Section::new(SectionKind::Code, 0x0100, vec![
+            MOV_LIT_REG, 0x12, 0x34, AX,
+            END,
+        ]));
+
+        let files = [
+            (Format::Ihex, format::to_ihex(&object)),
+            (Format::Srec, format::to_srec(&object, "test")),
+        ];
+
+        for (fmt, text) in files.iter() {
+            let mut cpu = CPU::default();
+            loader::load(&mut cpu, text.as_bytes(), *fmt, &LoadOptions::default()).unwrap();
+            assert_eq!(cpu.get_register("ip").unwrap(), 0x0100);
+
+            cpu.run();
+            assert_eq!(cpu.get_register("ax").unwrap(), 0x1234);
+        }
+
+        let mut cpu = CPU::default();
+        assert!(loader::load(&mut cpu, b":0201000012345B\n", Format::Ihex, &LoadOptions::default()).is_err());
+    }
+
+    #[test]
+    fn load_at_base() {
+        // the jump skips the first `end`, only when the image is at 0x8000
+        let image = [
+            MOV_LIT_REG, 0x00, 0x05, AX,
+            JMP_LIT, 0x80, 0x08,
+            END,
+            END,
+        ];
+        let options = LoadOptions { base: 0x8000, boot_stub: true };
+
+        let mut cpu = CPU::default();
+        loader::load(&mut cpu, &image, Format::Raw, &options).unwrap();
+        assert_eq!(cpu.get_register("ip").unwrap(), 0x0000);
+
+        cpu.run();
+        assert_eq!(cpu.get_register("ax").unwrap(), 0x0005);
+        assert_eq!(cpu.get_register("ip").unwrap(), 0x8009);
+
+        // the program already uses address 0
+        let mut cpu = CPU::default();
+        let options = LoadOptions { base: 0x0002, boot_stub: true };
+        assert!(loader::load(&mut cpu, &image, Format::Raw, &options).is_err());
+    }
+
+    #[test]
+    fn software_interrupt() {
+        let mut cpu = CPU::default();
+        cpu.load_at(vector_address(2) as usize, &[0x01, 0x40]).unwrap();
+        cpu.load_at(0x0100, &[
+            CMP_REG_LIT, AX, 0x00, 0x00,    // 0x0100: set the zero flag
+            STI,                            // 0x0104
+            INT_LIT, 0x00, 0x02,            // 0x0105
+            JEQ_LIT, 0x01, 0x0C,            // 0x0108: zero flag restored by iret
+            END,                            // 0x010B
+            MOV_LIT_REG, 0x00, 0x01, CX,    // 0x010C
+            END,                            // 0x0110
+        ]).unwrap();
+        cpu.load_at(0x0140, &[
+            MOV_LIT_REG, 0x12, 0x34, AX,    // 0x0140: clobber ax and the flags
+            IRET,                           // 0x0144
+        ]).unwrap();
+        cpu.set_register("ip", 0x0100).unwrap();
+
+        cpu.run();
+        assert_eq!(cpu.get_register("cx").unwrap(), 0x0001);
+        assert_eq!(cpu.get_register("ax").unwrap(), 0x0000);
+        assert_eq!(cpu.get_register("sp").unwrap(), 0xFFFE);
+
+        // vector without handler, and vector out of the table
+        for vector in [0x05, 0x40].iter() {
+            let mut cpu = CPU::default();
+            cpu.load_at(0x0100, &[INT_LIT, 0x00, *vector, END]).unwrap();
+            cpu.set_register("ip", 0x0100).unwrap();
+
+            assert!(matches!(cpu.step(), StepResult::Fault { ip: 0x0100, .. }));
+            assert_eq!(cpu.get_register("ip").unwrap(), 0x0103);
+        }
+    }
+
+    #[test]
+    fn syscall_in_user_mode() {
+        let mut cpu = CPU::default();
+        cpu.load_at(syscall_address(3) as usize, &[0x02, 0x00]).unwrap();
+        cpu.load_at(0x0100, &[
+            MOV_LIT_REG, 0x00, 0x00, FL,    // 0x0100: enter user mode
+            SYS_LIT, 0x00, 0x03,            // 0x0104
+            MOV_REG_REG, FL, BX,            // 0x0107
+            MOV_LIT_REG, 0x00, 0xFF, FL,    // 0x010A: the mode is kept
+            STI,                            // 0x010E
+            END,                            // 0x010F
+        ]).unwrap();
+        cpu.load_at(0x0200, &[
+            MOV_REG_REG, FL, ACC,           // 0x0200, result in acc
+            MOV_LIT_REG, 0x00, 0x07, AX,    // 0x0203, restored by iret
+            IRET,                           // 0x0207
+        ]).unwrap();
+        cpu.set_register("ip", 0x0100).unwrap();
+
+        cpu.run();
+        assert_eq!(cpu.get_register("acc").unwrap() & 0x20, 0x20);
+        assert_eq!(cpu.get_register("ax").unwrap(), 0x0000);
+        assert_eq!(cpu.get_register("bx").unwrap() & 0x20, 0x00);
+        assert_eq!(cpu.get_register("fl").unwrap(), 0x00D7);
+        assert_eq!(cpu.get_register("ip").unwrap(), 0x010F);
+
+        // syscall without handler, and syscall out of the table
+        for syscall in [0x02, 0x10].iter() {
+            let mut cpu = CPU::default();
+            cpu.load_at(0x0100, &[SYS_LIT, 0x00, *syscall, END]).unwrap();
+            cpu.set_register("ip", 0x0100).unwrap();
+
+            assert!(matches!(cpu.step(), StepResult::Fault { ip: 0x0100, .. }));
+            assert_eq!(cpu.get_register("ip").unwrap(), 0x0103);
+        }
+    }
+
+    #[test]
+    fn failed_trap_keeps_mode() {
+        // user mode with interrupts enabled, no handler in the tables
+        let mut cpu = CPU::default();
+        cpu.load_at(0x0100, &[MOV_LIT_REG, 0x00, 0x08, FL, SYS_LIT, 0x00, 0x03, END]).unwrap();
+        cpu.set_register("ip", 0x0100).unwrap();
+
+        let res = cpu.run();
+        assert!(matches!(res, StepResult::Fault { error: ExecutionError::NoSyscallHandler(3), .. }));
+        assert_eq!(cpu.get_register("fl").unwrap(), 0x0008);
+        assert_eq!(cpu.get_register("sp").unwrap(), CPU::STACK_BASE);
+
+        cpu.raise_interrupt(1);
+        let res = cpu.step();
+        assert!(matches!(res, StepResult::Fault { error: ExecutionError::NoInterruptHandler(1), .. }));
+        assert_eq!(cpu.get_register("fl").unwrap(), 0x0008);
+    }
+
+    #[test]
+    fn user_mode_flag_writes() {
+        // every instruction writing `fl` keeps the mode and the interrupt flag
+        let programs: [&[u8]; 4] = [
+            &[MOV_MEM_REG, 0x00, 0x80, FL],
+            &[MOV_LITOFF_REG, 0x00, 0x7E, AX, FL],
+            &[MOV_LIT_REG, 0x00, 0x07, FL, INC_REG, FL],
+            &[DEC_REG, FL],
+        ];
+
+        for program in programs.iter() {
+            let mut cpu = CPU::default();
+            cpu.load_at(0x0080, &[0x00, 0x28]).unwrap();
+            cpu.load_at(0x0100, &[MOV_LIT_REG, 0x00, 0x00, FL]).unwrap();
+            cpu.load_at(0x0104, program).unwrap();
+            cpu.load_at(0x0104 + program.len(), &[CLI, END]).unwrap();
+            cpu.set_register("ax", 0x0002).unwrap();
+            cpu.set_register("ip", 0x0100).unwrap();
+
+            let res = cpu.run();
+            assert!(matches!(res, StepResult::Fault { error: ExecutionError::PrivilegedInstruction("cli"), .. }));
+            assert_eq!(cpu.get_register("fl").unwrap() & 0x28, 0x00);
+        }
+    }
+
+    #[test]
+    fn supervisor_only_memory() {
+        let instructions = [
+            MOV_LIT_MEM16, 0x01, 0x40, 0x00, 0x10, // 0x0100: write the vector 0
+            MOV_LIT_REG, 0x00, 0x00, FL,            // 0x0105: enter user mode
+            MOV_LIT_MEM16, 0x01, 0x40, 0x00, 0x12, // 0x0109
+            END,                                    // 0x010E
+        ];
+
+        let mut cpu = CPU::default();
+        cpu.load_at(0x0100, &instructions).unwrap();
+        cpu.set_register("ip", 0x0100).unwrap();
+
+        cpu.run();
+        assert_eq!(cpu.get_register("ip").unwrap(), 0x010E);
+
+        let mut memory = MemoryMap::default();
+        memory.protect(0x0100, 0x10, memory_map::RWX | memory_map::SUPERVISOR).unwrap();
+        memory.set_memory_at_u8(0x0100, 0x01).unwrap();
+
+        memory.set_user_mode(true);
+        assert!(memory.get_memory_at_u8(0x0100).is_err());
+        assert!(memory.fetch_u8(0x0100).is_err());
+        assert_eq!(memory.get_memory_at_u8(0x0110).unwrap(), 0x00);
+    }
+
+    #[test]
+    fn host_functions() {
+        use crate::component::host::HostError;
+        use std::cell::RefCell;
+        use std::rc::Rc;
+
+        let log = Rc::new(RefCell::new(String::new()));
+        let mut cpu = CPU::default();
+
+        // acc = ax + bx
+        cpu.register_host_function(1, |cpu| {
+            let sum = cpu.get_register("ax")?.wrapping_add(cpu.get_register("bx")?);
+            Ok(cpu.set_register("acc", sum)?)
+        });
+
+        // log the string of `cx` bytes at `ax`, and clear it
+        let output = Rc::clone(&log);
+        cpu.register_host_function(2, move |cpu| {
+            let address = cpu.get_register("ax")? as usize;
+            for id in 0..cpu.get_register("cx")? as usize {
+                output.borrow_mut().push(cpu.get_memory_at_u8(address + id)? as char);
+                cpu.set_memory_at_u8(address + id, 0)?;
+            }
+            Ok(())
+        });
+
+        cpu.register_host_function(3, |_| Err(HostError::Failed("not found".to_owned())));
+
+        cpu.load_at(0x0200, b"hi").unwrap();
+        cpu.load_at(0x0000, &[
+            MOV_LIT_REG, 0x02, 0x00, AX,    // 0x0000
+            MOV_LIT_REG, 0x00, 0x02, BX,    // 0x0004
+            MOV_LIT_REG, 0x00, 0x02, CX,    // 0x0008
+            HOSTCALL_LIT, 0x00, 0x01,       // 0x000C
+            HOSTCALL_LIT, 0x00, 0x02,       // 0x000F
+            MOV_MEM_REG, 0x02, 0x00, DX,    // 0x0012
+            HOSTCALL_LIT, 0x00, 0x03,       // 0x0016
+            END,                            // 0x0019
+        ]).unwrap();
+
+        cpu.run();
+        assert_eq!(cpu.get_register("acc").unwrap(), 0x0202);
+        assert_eq!(log.borrow().as_str(), "hi");
+        assert_eq!(cpu.get_register("dx").unwrap(), 0x0000);
+        assert_eq!(cpu.get_register("ip").unwrap(), 0x0019);
+
+        // the function stays registered after a call
+        cpu.set_register("ip", 0x000C).unwrap();
+        assert!(matches!(cpu.step(), StepResult::Continue));
+        assert_eq!(cpu.get_register("acc").unwrap(), 0x0202);
+
+        let mut cpu = CPU::default();
+        cpu.load_at(0x0000, &[HOSTCALL_LIT, 0x00, 0x04, END]).unwrap();
+        assert!(matches!(cpu.step(), StepResult::Fault { ip: 0x0000, .. }));
+    }
+
+    #[test]
+    fn pending_interrupt() {
+        let mut cpu = CPU::default();
+        cpu.load_at(vector_address(5) as usize, &[0x01, 0x40]).unwrap();
+        cpu.load_at(0x0100, &[
+            MOV_LIT_REG, 0x00, 0x01, AX,    // 0x0100
+            STI,                            // 0x0104
+            END,                            // 0x0105
+        ]).unwrap();
+        cpu.load_at(0x0140, &[END]).unwrap();
+        cpu.set_register("ip", 0x0100).unwrap();
+
+        // the line waits until interrupts are enabled
+        cpu.raise_interrupt(5);
+        assert!(matches!(cpu.step(), StepResult::Continue));
+        assert_eq!(cpu.get_register("ip").unwrap(), 0x0104);
+        assert!(matches!(cpu.step(), StepResult::Continue));
+        assert!(matches!(cpu.step(), StepResult::Halted));
+        assert_eq!(cpu.get_register("ip").unwrap(), 0x0141);
+    }
+
+    /// Device raising its line once, after `delay` polls
+    struct Alarm {
+        delay: usize,
+    }
+
+    impl MemoryIO for Alarm {
+        fn get_memory_at_u8(&self, _location: usize) -> Result<u8, MemoryError> { Ok(0) }
+        fn get_memory_at_u16(&self, _location: usize) -> Result<u16, MemoryError> { Ok(0) }
+        fn set_memory_at_u8(&mut self, _location: usize, _data: u8) -> Result<(), MemoryError> { Ok(()) }
+        fn set_memory_at_u16(&mut self, _location: usize, _data: u16) -> Result<(), MemoryError> { Ok(()) }
+        fn len(&self) -> usize { 1 }
+        fn is_empty(&self) -> bool { false }
+
+        fn interrupt(&mut self) -> bool {
+            self.delay = self.delay.saturating_sub(1);
+            self.delay == 1
+        }
+    }
+
+    #[test]
+    fn device_interrupt() {
+        let mut memory = MemoryMap::default();
+        memory.add_device(Box::new(Alarm { delay: 2 }), 0x4000).unwrap();
+        memory.add_interrupt_device(Box::new(Alarm { delay: 3 }), 0x4001, 3).unwrap();
+        assert!(memory.add_interrupt_device(Box::new(Alarm { delay: 3 }), 0x4002, 16).is_err());
+
+        // only the device mapped with a line is polled
+        assert_eq!(memory.poll_interrupts().unwrap(), 0);
+        assert_eq!(memory.poll_interrupts().unwrap(), 1 << 3);
+        assert_eq!(memory.poll_interrupts().unwrap(), 0);
+    }
+
+    #[test]
+    fn region_permissions() {
+        let mut memory = MemoryMap::default();
+        memory.add_device(Box::new(Alarm { delay: 0 }), 0x4000).unwrap();
+        memory.protect(0x0100, 0x10, memory_map::READ | memory_map::EXECUTE).unwrap();
+        memory.protect(0x0108, 0x08, memory_map::WRITE).unwrap();
+        assert!(memory.protect(0xFFF0, 0x20, memory_map::READ).is_err());
+
+        assert_eq!(memory.permissions(0x0000), Some(memory_map::RWX));
+        assert_eq!(memory.permissions(0x4000), Some(memory_map::READ | memory_map::WRITE));
+
+        // the last protection wins
+        assert!(memory.set_memory_at_u8(0x0100, 0x01).is_err());
+        assert!(memory.set_memory_at_u16(0x00FF, 0x0101).is_err());
+        assert!(memory.get_memory_at_u8(0x0108).is_err());
+        memory.set_memory_at_u8(0x0108, 0x01).unwrap();
+
+        assert_eq!(memory.fetch_u8(0x0100).unwrap(), 0x00);
+        assert!(memory.fetch_u8(0x0108).is_err());
+        assert!(memory.fetch_u8(0x4000).is_err());
+
+        // programs are loaded in read-only memory
+        memory.load_u8(0x0100, 0x12).unwrap();
+        assert_eq!(memory.get_memory_at_u8(0x0100).unwrap(), 0x12);
+
+        // the screen is a device, instructions can't be fetched from it
+        let mut cpu = CPU::default();
+        cpu.set_register("ip", 0x3000).unwrap();
+        assert!(matches!(cpu.step(), StepResult::Fault { ip: 0x3000, .. }));
+        assert_eq!(cpu.get_register("ip").unwrap(), 0x3000);
+    }
+
+    #[test]
+    fn machine_builder() {
+        let mut cpu = MachineBuilder::new()
+            .memory_size(0x1000)
+            .default_devices(false)
+            .interrupt_device(0x0800, 2, Box::new(Alarm { delay: 3 }))
+            .stack(0x0FFE, 0x0F00)
+            .build()
+            .unwrap();
+
+        assert_eq!(cpu.get_register("sp").unwrap(), 0x0FFE);
+        assert!(cpu.get_memory_at_u8(0x3000).is_err());
+        assert!(cpu.load_at(0x1000, &[0x01]).is_err());
+
+        // the device raises the line 2 and the handler is called with the stack of the machine
+        cpu.load_at(vector_address(2) as usize, &[0x01, 0x00]).unwrap();
+        cpu.load_at(0x0000, &[STI, INC_REG, AX, INC_REG, AX, END]).unwrap();
+        cpu.load_at(0x0100, &[MOV_REG_REG, SP, ACC, END]).unwrap();
+
+        cpu.run();
+        assert_eq!(cpu.get_register("ip").unwrap(), 0x0104);
+        assert_eq!(cpu.get_register("acc").unwrap(), 0x0FFE - 2 * 12);
+
+        assert!(MachineBuilder::new().stack(0x1000, 0x2000).build().is_err());
+        assert!(MachineBuilder::new().memory_size(0x1_0001).build().is_err());
+
+        // the stack and the devices must be inside the memory
+        let small = || MachineBuilder::new().memory_size(0x1000).listen_keyboard(false);
+        assert!(matches!(small().build(), Err(MachineError::StackOutOfMemory(0xFFFE))));
+        assert!(matches!(small().stack(0x0FFF, 0x0F00).build(), Err(MachineError::StackOutOfMemory(0x0FFF))));
+        assert!(matches!(small().stack(0x0FFE, 0x0F00).build(), Err(MachineError::DeviceOutOfMemory(0x3000))));
+        let res = small().default_devices(false).stack(0x0FFE, 0x0F00).device(0x1000, Box::new(Alarm { delay: 3 })).build();
+        assert!(matches!(res, Err(MachineError::DeviceOutOfMemory(0x1000))));
+        assert!(small().default_devices(false).stack(0x0FFE, 0x0F00).device(0x0FFF, Box::new(Alarm { delay: 3 })).build().is_ok());
+
+        let cpu = MachineBuilder::new().listen_keyboard(false).screen(80, 25).build().unwrap();
+        assert!(cpu.get_memory_at_u8(0x3000 + 80 * 25 - 1).is_ok());
+    }
+
+    #[test]
+    fn snapshot_restore() {
+        let machine = || MachineBuilder::new().listen_keyboard(false).build().unwrap();
+        let mut cpu = machine();
+        let instructions = [
+            INC_REG, AX,                 // 0x0000
+            PSH_REG, AX,                 // 0x0002
+            JMP_LIT, 0x00, 0x00,         // 0x0004
+        ];
+
+        cpu.load_at(0x0000, &instructions).unwrap();
+        cpu.set_memory_at_u16(0x4000 + Timer::RELOAD, 0x0100).unwrap();
+        cpu.set_memory_at_u8(0x4000 + Timer::CONTROL, Timer::ENABLE).unwrap();
+        cpu.run_steps(10);
+
+        // a new machine resumes from the saved state
+        let bytes = cpu.snapshot().to_bytes();
+        let mut restored = machine();
+        restored.restore(&Snapshot::from_bytes(&bytes).unwrap()).unwrap();
+
+        cpu.run_steps(20);
+        restored.run_steps(20);
+        for reg in ["ip", "ax", "sp", "fp", "fl"] {
+            assert_eq!(restored.get_register(reg).unwrap(), cpu.get_register(reg).unwrap());
+        }
+        assert_eq!(restored.get_memory_at_u16(0xFFFE - 18).unwrap(), 0x000A);
+        assert_eq!(restored.get_memory_at_u16(0x4000 + Timer::COUNTER).unwrap(), 0x0100 - 30);
+        assert_eq!(restored.instructions(), 30);
+        assert_eq!(restored.cycles(), cpu.cycles());
+
+        // protections of the vector table are restored
+        restored.set_register("fl", 0).unwrap();
+        assert!(matches!(restored.get_memory_at_u8(0x10), Err(MemoryError::SupervisorOnly(_))));
+
+        assert!(matches!(Snapshot::from_bytes(b"VMO\0"), Err(SnapshotError::BadMagic)));
+        assert!(matches!(Snapshot::from_bytes(&bytes[..bytes.len() - 1]), Err(SnapshotError::Truncated)));
+
+        let mut other = bytes.clone();
+        other[5] += 1;
+        assert!(matches!(Snapshot::from_bytes(&other), Err(SnapshotError::UnsupportedVersion(2))));
+
+        let mut other = MachineBuilder::new().default_devices(false).build().unwrap();
+        let snapshot = Snapshot::from_bytes(&bytes).unwrap();
+        assert!(matches!(other.restore(&snapshot), Err(SnapshotError::MachineMismatch)));
+    }
+
+    /// Device giving a new value on every read
+    struct Dice {
+        value: Cell<u16>,
+    }
+
+    impl MemoryIO for Dice {
+        fn get_memory_at_u8(&self, _location: usize) -> Result<u8, MemoryError> { Ok(self.get_memory_at_u16(0)? as u8) }
+        fn get_memory_at_u16(&self, _location: usize) -> Result<u16, MemoryError> {
+            self.value.set(self.value.get() + 1);
+            Ok(self.value.get())
+        }
+        fn set_memory_at_u8(&mut self, _location: usize, _data: u8) -> Result<(), MemoryError> { Ok(()) }
+        fn set_memory_at_u16(&mut self, _location: usize, _data: u16) -> Result<(), MemoryError> { Ok(()) }
+        fn len(&self) -> usize { 2 }
+        fn is_empty(&self) -> bool { false }
+    }
+
+    #[test]
+    fn record_replay_input() {
+        let machine = |value, delay| {
+            let mut cpu = MachineBuilder::new()
+                .default_devices(false)
+                .device(0x0800, Box::new(Dice { value: Cell::new(value) }))
+                .interrupt_device(0x0810, 2, Box::new(Alarm { delay }))
+                .build()
+                .unwrap();
+
+            cpu.load_at(vector_address(2) as usize, &[0x01, 0x00]).unwrap();
+            cpu.load_at(0x0000, &[
+                STI,                         // 0x0000
+                MOV_MEM_REG, 0x08, 0x00, AX, // 0x0001
+                PSH_REG, AX,                 // 0x0005
+                JMP_LIT, 0x00, 0x01,         // 0x0007
+            ]).unwrap();
+            cpu.load_at(0x0100, &[INC_REG, ACC, IRET]).unwrap();
+            cpu
+        };
+
+        let mut cpu = machine(0, 6);
+        cpu.record_input();
+        cpu.run_steps(20);
+
+        let log = cpu.take_input_log().unwrap();
+        assert!(cpu.take_input_log().is_none());
+        assert_eq!(log.events()[0], InputEvent::Read { instruction: 1, address: 0x0800, size: 2, value: 1 });
+        assert!(log.events().contains(&InputEvent::Interrupt { instruction: 4, lines: 1 << 2 }));
+
+        // other devices give the recorded input
+        let mut replayed = machine(100, 0);
+        replayed.replay_input(InputLog::from_bytes(&log.to_bytes()).unwrap());
+        replayed.run_steps(20);
+
+        for reg in ["ip", "acc", "ax", "sp", "fl"] {
+            assert_eq!(replayed.get_register(reg).unwrap(), cpu.get_register(reg).unwrap());
+        }
+        assert_eq!(replayed.get_register("acc").unwrap(), 1);
+        for address in (0xFFF0..=0xFFFE).step_by(2) {
+            assert_eq!(replayed.get_memory_at_u16(address).unwrap(), cpu.get_memory_at_u16(address).unwrap());
+        }
+
+        // the program reads more than what was recorded
+        let res = replayed.run_steps(20);
+        assert!(matches!(res, StepResult::Fault {
+            ip: 0x0001,
+            error: ExecutionError::InternalMemoryError(MemoryError::ReplayDiverged(0x0800)), ..
+        }));
+
+        // an interrupt of the log is skipped by a run starting later
+        let mut skipped = InputLog::new();
+        skipped.push(InputEvent::Interrupt { instruction: 1, lines: 1 << 2 });
+        let mut replayed = machine(0, 0);
+        replayed.run_steps(2);
+        replayed.replay_input(skipped);
+        let res = replayed.step();
+        assert!(matches!(res, StepResult::Fault {
+            error: ExecutionError::InternalMemoryError(MemoryError::ReplayMissed(1)), ..
+        }));
+
+        // the screen isn't an input, its reads aren't recorded
+        let mut cpu = MachineBuilder::new().listen_keyboard(false).build().unwrap();
+        cpu.load_at(0x0000, &[MOV_MEM_REG, 0x30, 0x00, AX, END]).unwrap();
+        cpu.record_input();
+        assert!(matches!(cpu.run(), StepResult::Halted));
+        assert!(cpu.take_input_log().unwrap().events().is_empty());
+
+        assert!(matches!(InputLog::from_bytes(b"VMS\0"), Err(ReplayError::BadMagic)));
+        let bytes = log.to_bytes();
+        assert!(matches!(InputLog::from_bytes(&bytes[..bytes.len() - 1]), Err(ReplayError::Truncated)));
+    }
+
+    #[test]
+    fn timer_registers() {
+        let mut timer = Timer::new();
+        timer.set_memory_at_u16(Timer::RELOAD, 0x0002).unwrap();
+        assert_eq!(timer.get_memory_at_u16(Timer::COUNTER).unwrap(), 0x0002);
+
+        // nothing is counted until the timer is enabled
+        timer.tick();
+        assert_eq!(timer.get_memory_at_u16(Timer::COUNTER).unwrap(), 0x0002);
+
+        // periodic timer, polled with the status flag
+        timer.set_memory_at_u8(Timer::CONTROL, Timer::ENABLE).unwrap();
+        timer.tick();
+        assert_eq!(timer.get_memory_at_u8(Timer::STATUS).unwrap(), 0);
+        timer.tick();
+        assert_eq!(timer.get_memory_at_u8(Timer::STATUS).unwrap(), Timer::EXPIRED);
+        assert_eq!(timer.get_memory_at_u16(Timer::COUNTER).unwrap(), 0x0002);
+        assert!(!timer.interrupt());
+
+        timer.set_memory_at_u8(Timer::STATUS, Timer::EXPIRED).unwrap();
+        assert_eq!(timer.get_memory_at_u8(Timer::STATUS).unwrap(), 0);
+
+        // one-shot timer raising its line
+        timer.set_memory_at_u8(Timer::CONTROL, Timer::ENABLE | Timer::INTERRUPT | Timer::ONE_SHOT).unwrap();
+        timer.tick();
+        timer.tick();
+        assert!(timer.interrupt());
+        assert!(!timer.interrupt());
+        assert_eq!(timer.get_memory_at_u8(Timer::CONTROL).unwrap(), Timer::INTERRUPT | Timer::ONE_SHOT);
+
+        timer.tick();
+        timer.tick();
+        assert!(!timer.interrupt());
+        assert!(timer.get_memory_at_u8(0x06).is_err());
+    }
+
+    #[test]
+    fn timer_interrupt() {
+        let mut cpu = CPU::default();
+        cpu.load_at(vector_address(0) as usize, &[0x01, 0x40]).unwrap();
+        cpu.load_at(0x0100, &[
+            MOV_LIT_MEM16, 0x00, 0x03, 0x40, 0x00, // 0x0100: reload value
+            MOV_LIT_MEM8, 0x00, 0x03, 0x40, 0x04,  // 0x0105: enable with interrupt
+            STI,                                   // 0x010A
+            JMP_LIT, 0x01, 0x0B,                   // 0x010B: wait forever
+        ]).unwrap();
+        cpu.load_at(0x0140, &[END]).unwrap();
+        cpu.set_register("ip", 0x0100).unwrap();
+
+        let mut steps = 1;
+        while let StepResult::Continue = cpu.step() {
+            steps += 1;
+            assert!(steps < 10);
+        }
+
+        // expired after the jump, 3 instructions after it was enabled
+        assert_eq!(steps, 5);
+        assert_eq!(cpu.get_register("ip").unwrap(), 0x0141);
+    }
+
+    #[test]
+    fn keyboard_queue() {
+        let mut keyboard = Keyboard::new(false);
+        assert_eq!(keyboard.get_memory_at_u8(Keyboard::STATUS).unwrap(), 0);
+        assert_eq!(keyboard.get_memory_at_u8(Keyboard::DATA).unwrap(), 0);
+
+        keyboard.push_key(b'h');
+        keyboard.push_key(b'i');
+        assert!(!keyboard.interrupt());
+        assert_eq!(keyboard.get_memory_at_u8(Keyboard::STATUS).unwrap(), Keyboard::READY);
+        assert_eq!(keyboard.get_memory_at_u8(Keyboard::DATA).unwrap(), b'h');
+        assert_eq!(keyboard.get_memory_at_u8(Keyboard::DATA).unwrap(), b'i');
+        assert_eq!(keyboard.get_memory_at_u8(Keyboard::STATUS).unwrap(), 0);
+
+        // with the interrupt enabled, each key raises the line
+        keyboard.set_memory_at_u8(Keyboard::STATUS, 0xFF).unwrap();
+        assert_eq!(keyboard.get_memory_at_u8(Keyboard::STATUS).unwrap(), Keyboard::INTERRUPT);
+        keyboard.push_key(b'!');
+        assert!(keyboard.interrupt());
+        assert!(!keyboard.interrupt());
+        assert_eq!(keyboard.get_memory_at_u16(Keyboard::STATUS).unwrap(), 0x0321);
+        assert!(keyboard.get_memory_at_u8(0x02).is_err());
+    }
 }